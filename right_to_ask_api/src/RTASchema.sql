@@ -1,5 +1,14 @@
 
 drop table if exists SchemaVersion;
+drop table if exists NotificationDeliveries;
+drop table if exists NotificationOutbox;
+drop table if exists Subscriptions;
+drop table if exists QuestionTags;
+drop table if exists TopicTags;
+drop table if exists ModerationClaims;
+drop table if exists ModerationAudit;
+drop table if exists PublicKeyHistory;
+drop table if exists PendingAccountRecovery;
 drop table if exists AnswerReportedReasons;
 drop table if exists QuestionReportedReasons;
 drop table if exists EmailRateLimitHistory;
@@ -11,6 +20,9 @@ drop table if exists Organisations;
 drop table if exists Minister_IDs;
 drop table if exists Committee_IDs;
 drop table if exists MP_IDs;
+drop table if exists LifecycleTransitions;
+drop table if exists AnonymousVotes;
+drop table if exists VoteTokensIssued;
 drop table if exists HAS_VOTED;
 drop table if exists QUESTIONS;
 drop table if exists BADGES;
@@ -34,6 +46,32 @@ create table if not exists USERS
     UNIQUE INDEX UPPER_CASE_UID (UPPER_CASE_UID)
 ) CHARACTER SET utf8;
 
+/* History of the public keys used by each user, so old signatures can be checked against the key valid at the time */
+create table if not exists PublicKeyHistory
+(
+    id          INTEGER PRIMARY KEY AUTO_INCREMENT NOT NULL,
+    user_id     INTEGER NOT NULL, /* reference to id in USERS table */
+    PublicKey   TEXT NOT NULL,
+    ValidFrom   BIGINT UNSIGNED NULL, /* When the key became valid. NULL for keys that predate this table */
+    ValidUntil  BIGINT UNSIGNED NULL, /* When the key was replaced. NULL if current */
+    BulletinBoardHash BINARY(32) NULL, /* The NewUser, RotateKey, AddDeviceKey or AccountRecovery bulletin board entry introducing this key */
+    RevocationBulletinBoardHash BINARY(32) NULL, /* The RotateKey, RevokeDeviceKey or AccountRecovery bulletin board entry retiring this key. NULL if current */
+    DeviceName  VARCHAR(60) NULL, /* For keys added as device keys. NULL for the key registered with */
+    INDEX(user_id),
+    constraint foreign key (user_id) REFERENCES USERS (id) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
+/* Account recoveries that have been verified by email, but are still in the cooling off period */
+create table if not exists PendingAccountRecovery
+(
+    user_id      INTEGER PRIMARY KEY NOT NULL, /* reference to id in USERS table. Only one pending recovery per user */
+    NewPublicKey TEXT NOT NULL,
+    Requested    BIGINT UNSIGNED NOT NULL, /* when the email was verified */
+    Effective    BIGINT UNSIGNED NOT NULL, /* when the recovery may be completed */
+    Request      TEXT NOT NULL, /* JSON of the signed RequestEmailValidation, for the bulletin board */
+    constraint foreign key (user_id) REFERENCES USERS (id) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
 /* The definition of an electorate, referred to in UserElectorate by the id */
 create table if not exists ElectorateDefinition
 (
@@ -92,19 +130,81 @@ create table if not exists QUESTIONS
     NetVotes    INT NOT NULL DEFAULT 0,
     CensorshipStatus  ENUM('NotFlagged','Flagged','Allowed','StructureChanged','StructureChangedThenFlagged','Censored') NOT NULL DEFAULT 'NotFlagged',
     NumFlags INTEGER NOT NULL DEFAULT 0, /* number of flags of the question (or an answer in it) [since the last moderator approval] */
+    WithdrawnTimestamp BIGINT UNSIGNED NULL, /* If not null, when the author withdrew the question. */
+    CanOthersSetTopicTags BOOLEAN NOT NULL DEFAULT FALSE,
+    DuplicateOf BINARY(32) NULL, /* If not null, the canonical question this is a duplicate of. Never itself a duplicate. */
+    ProposedDuplicateOf BINARY(32) NULL, /* A duplicate proposed by the author, waiting for a moderator to confirm */
+    Lifecycle ENUM('Open','CommittedToAsk','Asked','Answered','Closed') NOT NULL DEFAULT 'Open',
+    FlaggedTimestamp BIGINT UNSIGNED NULL, /* When NumFlags last went from 0 to positive. NULL when NumFlags is 0 */
     INDEX(LastModifiedTimestamp),
     INDEX(NumFlags),
+    INDEX(DuplicateOf),
+    INDEX(Lifecycle),
     foreign key (CreatedById) REFERENCES USERS (id) ON DELETE CASCADE ON UPDATE RESTRICT
 ) CHARACTER SET utf8;
 
+/* The controlled vocabulary of topic tags */
+create table if not exists TopicTags
+(
+    id  INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    Tag VARCHAR(40) NOT NULL UNIQUE, /* lower case */
+    Description TEXT NULL
+) CHARACTER SET utf8;
+
+create table if not exists QuestionTags
+(
+    QuestionId  BINARY(32) NOT NULL,
+    TagId INT NOT NULL,
+    PRIMARY KEY (QuestionId,TagId),
+    INDEX(TagId),
+    constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT,
+    constraint foreign key (TagId) REFERENCES TopicTags (id) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
 create table if not exists HAS_VOTED
 (
     QuestionId  BINARY(32) NOT NULL, /* The hash of the question defining fields */
     VoterId INTEGER NOT NULL, /* reference to id in Users table */
+    Up BOOLEAN NULL, /* true for an up vote, false for a down vote. NULL for old votes whose direction was not recorded. */
+    UNIQUE KEY (QuestionId,VoterId),
     constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT,
     constraint foreign key (VoterId) REFERENCES USERS (id) ON DELETE CASCADE ON UPDATE RESTRICT
 ) CHARACTER SET utf8;
 
+/* Who has been issued an anonymous vote token for a question, and the tokens used. See anonymous_voting.rs */
+create table if not exists VoteTokensIssued
+(
+    QuestionId  BINARY(32) NOT NULL,
+    UserId INTEGER NOT NULL,
+    PRIMARY KEY (QuestionId,UserId),
+    constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT,
+    constraint foreign key (UserId) REFERENCES USERS (id) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
+create table if not exists AnonymousVotes
+(
+    QuestionId  BINARY(32) NOT NULL,
+    Token BINARY(32) NOT NULL,
+    Up BOOLEAN NOT NULL,
+    BulletinBoardHash BINARY(32) NOT NULL,
+    PRIMARY KEY (QuestionId,Token),
+    constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
+/* Each change to QUESTIONS.Lifecycle, who made it and when. See lifecycle.rs */
+create table if not exists LifecycleTransitions
+(
+    QuestionId  BINARY(32) NOT NULL,
+    FromStatus ENUM('Open','CommittedToAsk','Asked','Answered','Closed') NOT NULL,
+    ToStatus ENUM('Open','CommittedToAsk','Asked','Answered','Closed') NOT NULL,
+    UserId INTEGER NOT NULL,
+    timestamp BIGINT UNSIGNED NOT NULL,
+    BulletinBoardHash BINARY(32) NOT NULL,
+    INDEX(QuestionId),
+    constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT,
+    constraint foreign key (UserId) REFERENCES USERS (id) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
 create table if not exists MP_IDs(
      id INT PRIMARY KEY NOT NULL AUTO_INCREMENT,
      Chamber     ENUM('ACT_Legislative_Assembly',
@@ -208,6 +308,9 @@ create table if not exists Answer
     timestamp   BIGINT UNSIGNED NOT NULL,
     answer      TEXT NOT NULL,
     version     BINARY(32), /* when the answer was created. Used as a key for censoring */
+    AmendedTimestamp BIGINT UNSIGNED NULL, /* If not null, when the answer was last amended */
+    AmendedVersion BINARY(32) NULL, /* If not null, the bulletin board entry for the last amendment */
+    Accepted BOOLEAN NOT NULL DEFAULT FALSE, /* Whether this is the answer the question's author accepted */
     CensorshipStatus  ENUM('NotFlagged','Flagged','Allowed','StructureChanged','StructureChangedThenFlagged','Censored') NOT NULL DEFAULT 'NotFlagged',
     INDEX(version),
     foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT,
@@ -248,6 +351,7 @@ CREATE TABLE QuestionReportedReasons (
                                          QuestionId BINARY (32) NOT NULL, /* The hash of the question defining fields */
                                          reason ENUM('NotAQuestion','ThreateningViolence','IncludesPrivateInformation','IncitesHatredOrDiscrimination','EncouragesHarm','TargetedHarassment','DefamatoryInsinuation','Illegal','Impersonation','Spam') NOT NULL,
                                          user_id INTEGER NOT NULL,
+                                         timestamp BIGINT UNSIGNED NULL, /* NULL for reports made before this was recorded */
                                          CONSTRAINT FOREIGN KEY (user_id) REFERENCES USERS (id) ON DELETE CASCADE ON UPDATE RESTRICT,
                                          constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT,
                                          constraint qru unique (QuestionId,reason,user_id)
@@ -258,6 +362,7 @@ CREATE TABLE AnswerReportedReasons (
                                        reason ENUM('NotAQuestion','ThreateningViolence','IncludesPrivateInformation','IncitesHatredOrDiscrimination','EncouragesHarm','TargetedHarassment','DefamatoryInsinuation','Illegal','Impersonation','Spam') NOT NULL,
                                        answer BINARY (32) NOT NULL,
                                        user_id INTEGER NOT NULL,
+                                       timestamp BIGINT UNSIGNED NULL, /* NULL for reports made before this was recorded */
                                        CONSTRAINT FOREIGN KEY (user_id) REFERENCES USERS (id) ON DELETE CASCADE ON UPDATE RESTRICT,
                                        constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT,
                                        constraint foreign key (answer) REFERENCES Answer (version) ON DELETE CASCADE ON UPDATE RESTRICT,
@@ -265,11 +370,85 @@ CREATE TABLE AnswerReportedReasons (
 )  CHARACTER SET utf8;
 
 
+/* Record of who did which moderation action */
+create table if not exists ModerationAudit
+(
+    id          INTEGER PRIMARY KEY AUTO_INCREMENT NOT NULL,
+    timestamp   BIGINT UNSIGNED NOT NULL,
+    Moderator   VARCHAR(60) NOT NULL, /* The name of the administrator in config.toml. Not a reference to USERS */
+    Action      ENUM('CensorQuestion','CensorAnswers','AllowQuestion','BlockUser','UnblockUser','CensorLeaf','MarkDuplicate') NOT NULL,
+    QuestionId  BINARY(32) NULL, /* Not a foreign key, as the audit trail should outlive the question */
+    UID         VARCHAR(30) NULL, /* The user blocked or unblocked. Not a foreign key, as the audit trail should outlive the user */
+    Reason      ENUM('NotAQuestion','ThreateningViolence','IncludesPrivateInformation','IncitesHatredOrDiscrimination','EncouragesHarm','TargetedHarassment','DefamatoryInsinuation','Illegal','Impersonation','Spam') NULL,
+    BulletinBoardHash BINARY(32) NULL, /* The bulletin board entry for the action, or the leaf censored */
+    INDEX(Moderator),
+    INDEX(QuestionId),
+    INDEX(UID)
+) CHARACTER SET utf8;
+
+/* A moderator working on the reports for a question. See moderation_queue.rs */
+create table if not exists ModerationClaims
+(
+    QuestionId  BINARY(32) PRIMARY KEY NOT NULL,
+    Moderator   VARCHAR(60) NOT NULL, /* The name of the administrator in config.toml. Not a reference to USERS */
+    ClaimedTimestamp BIGINT UNSIGNED NOT NULL,
+    ExpiresTimestamp BIGINT UNSIGNED NOT NULL,
+    constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
+/* Notification subscriptions, and the outbox of events to notify subscribers about. See notifications.rs */
+create table if not exists Subscriptions
+(
+    id  BIGINT PRIMARY KEY AUTO_INCREMENT NOT NULL,
+    UserId INTEGER NOT NULL,
+    QuestionId BINARY(32) NULL,
+    MP INT NULL,
+    ElectorateId INT NULL,
+    TagId INT NULL,
+    Sink ENUM('Email','Webhook','Pull') NOT NULL,
+    WebhookURL TEXT NULL,
+    INDEX(UserId),
+    INDEX(QuestionId),
+    constraint foreign key (UserId) REFERENCES USERS (id) ON DELETE CASCADE ON UPDATE RESTRICT,
+    constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT,
+    constraint foreign key (MP) REFERENCES MP_IDs (id) ON DELETE CASCADE ON UPDATE RESTRICT,
+    constraint foreign key (ElectorateId) REFERENCES ElectorateDefinition (id) ON DELETE CASCADE ON UPDATE RESTRICT,
+    constraint foreign key (TagId) REFERENCES TopicTags (id) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
+create table if not exists NotificationOutbox
+(
+    id  BIGINT PRIMARY KEY AUTO_INCREMENT NOT NULL,
+    QuestionId BINARY(32) NOT NULL,
+    Event ENUM('AnswerAdded','HansardLinkAdded','QuestionCensored','FollowUpPosted') NOT NULL,
+    Related BINARY(32) NULL,
+    Url TEXT NULL,
+    timestamp BIGINT UNSIGNED NOT NULL,
+    Processed BOOLEAN NOT NULL DEFAULT FALSE,
+    INDEX(Processed),
+    constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
+create table if not exists NotificationDeliveries
+(
+    id  BIGINT PRIMARY KEY AUTO_INCREMENT NOT NULL,
+    OutboxId BIGINT NOT NULL,
+    UserId INTEGER NOT NULL,
+    Sink ENUM('Email','Webhook','Pull') NOT NULL,
+    WebhookURL TEXT NULL,
+    Status ENUM('Pending','Delivered','Failed') NOT NULL DEFAULT 'Pending',
+    Attempts INT NOT NULL DEFAULT 0,
+    NextAttemptTimestamp BIGINT UNSIGNED NULL, /* Not retried before this, after a failed attempt. */
+    INDEX(Status),
+    INDEX(UserId),
+    constraint foreign key (OutboxId) REFERENCES NotificationOutbox (id) ON DELETE CASCADE ON UPDATE RESTRICT,
+    constraint foreign key (UserId) REFERENCES USERS (id) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
 
 create table SchemaVersion
 (
     version INT
 );
 
-insert into SchemaVersion (version) values (10);
+insert into SchemaVersion (version) values (27);
 
@@ -1,7 +1,23 @@
-/* This is a preventative to be included in the next update. It is not actually applied yet. Remove this header when done. */
+/* The following is added as I seemed to have some problems with some databases without it depending upon when they are done, and it is unlikely to harm anything */
 
+alter table USERS ORDER BY id;
 
+/* Record of who did which moderation action */
 
-/* The following is added as I seemed to have some problems with some databases without it depending upon when they are done, and it is unlikely to harm anything */
+create table if not exists ModerationAudit
+(
+    id          INTEGER PRIMARY KEY AUTO_INCREMENT NOT NULL,
+    timestamp   BIGINT UNSIGNED NOT NULL,
+    Moderator   VARCHAR(60) NOT NULL, /* The name of the administrator in config.toml. Not a reference to USERS */
+    Action      ENUM('CensorQuestion','CensorAnswers','AllowQuestion','BlockUser','UnblockUser','CensorLeaf') NOT NULL,
+    QuestionId  BINARY(32) NULL, /* Not a foreign key, as the audit trail should outlive the question */
+    UID         VARCHAR(30) NULL, /* The user blocked or unblocked. Not a foreign key, as the audit trail should outlive the user */
+    Reason      ENUM('NotAQuestion','ThreateningViolence','IncludesPrivateInformation','IncitesHatredOrDiscrimination','EncouragesHarm','TargetedHarassment','DefamatoryInsinuation','Illegal','Impersonation','Spam') NULL,
+    BulletinBoardHash BINARY(32) NULL, /* The bulletin board entry for the action, or the leaf censored */
+    INDEX(Moderator),
+    INDEX(QuestionId),
+    INDEX(UID)
+) CHARACTER SET utf8;
 
-alter table USERS ORDER BY id;
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (11);
@@ -0,0 +1,18 @@
+/* History of the public keys used by each user, so old signatures can be checked against the key valid at the time */
+
+create table if not exists PublicKeyHistory
+(
+    id          INTEGER PRIMARY KEY AUTO_INCREMENT NOT NULL,
+    user_id     INTEGER NOT NULL, /* reference to id in USERS table */
+    PublicKey   TEXT NOT NULL,
+    ValidFrom   BIGINT UNSIGNED NULL, /* When the key became valid. NULL for keys that predate this table */
+    ValidUntil  BIGINT UNSIGNED NULL, /* When the key was replaced. NULL if current */
+    BulletinBoardHash BINARY(32) NULL, /* The NewUser or RotateKey bulletin board entry introducing this key */
+    INDEX(user_id),
+    constraint foreign key (user_id) REFERENCES USERS (id) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
+insert into PublicKeyHistory (user_id,PublicKey) select id,PublicKey from USERS;
+
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (12);
@@ -0,0 +1,14 @@
+/* Account recoveries that have been verified by email, but are still in the cooling off period */
+
+create table if not exists PendingAccountRecovery
+(
+    user_id      INTEGER PRIMARY KEY NOT NULL, /* reference to id in USERS table. Only one pending recovery per user */
+    NewPublicKey TEXT NOT NULL,
+    Requested    BIGINT UNSIGNED NOT NULL, /* when the email was verified */
+    Effective    BIGINT UNSIGNED NOT NULL, /* when the recovery may be completed */
+    Request      TEXT NOT NULL, /* JSON of the signed RequestEmailValidation, for the bulletin board */
+    constraint foreign key (user_id) REFERENCES USERS (id) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (13);
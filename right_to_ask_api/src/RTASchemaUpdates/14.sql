@@ -0,0 +1,6 @@
+/* Users may have multiple active keys, one per device. Active keys are those with ValidUntil NULL */
+
+ALTER TABLE PublicKeyHistory ADD DeviceName VARCHAR(60) NULL;
+
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (14);
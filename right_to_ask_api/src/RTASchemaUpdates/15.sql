@@ -0,0 +1,6 @@
+/* Questions may be withdrawn by their author. They are kept (so votes and answers remain auditable) but not offered as similar questions. */
+
+ALTER TABLE QUESTIONS ADD WithdrawnTimestamp BIGINT UNSIGNED NULL;
+
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (15);
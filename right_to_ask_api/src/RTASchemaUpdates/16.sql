@@ -0,0 +1,23 @@
+/* Topic tags on questions, from a controlled vocabulary. */
+
+create table if not exists TopicTags
+(
+    id  INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    Tag VARCHAR(40) NOT NULL UNIQUE, /* lower case */
+    Description TEXT NULL
+) CHARACTER SET utf8;
+
+create table if not exists QuestionTags
+(
+    QuestionId  BINARY(32) NOT NULL,
+    TagId INT NOT NULL,
+    PRIMARY KEY (QuestionId,TagId),
+    INDEX(TagId),
+    constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT,
+    constraint foreign key (TagId) REFERENCES TopicTags (id) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
+ALTER TABLE QUESTIONS ADD CanOthersSetTopicTags BOOLEAN NOT NULL DEFAULT FALSE;
+
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (16);
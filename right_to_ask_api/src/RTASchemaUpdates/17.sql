@@ -0,0 +1,6 @@
+/* Record the direction of each vote so it can be changed or retracted. NULL for votes recorded before this, whose direction is unknown. */
+
+ALTER TABLE HAS_VOTED ADD Up BOOLEAN NULL;
+
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (17);
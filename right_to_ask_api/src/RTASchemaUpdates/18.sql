@@ -0,0 +1,23 @@
+/* Anonymous voting with blind tokens. See anonymous_voting.rs */
+
+create table if not exists VoteTokensIssued
+(
+    QuestionId  BINARY(32) NOT NULL,
+    UserId INTEGER NOT NULL,
+    PRIMARY KEY (QuestionId,UserId),
+    constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT,
+    constraint foreign key (UserId) REFERENCES USERS (id) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
+create table if not exists AnonymousVotes
+(
+    QuestionId  BINARY(32) NOT NULL,
+    Token BINARY(32) NOT NULL,
+    Up BOOLEAN NOT NULL,
+    BulletinBoardHash BINARY(32) NOT NULL,
+    PRIMARY KEY (QuestionId,Token),
+    constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (18);
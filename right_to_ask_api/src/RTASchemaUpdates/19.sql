@@ -0,0 +1,7 @@
+/* MPs may amend their answers. The version stays the same (it identifies the answer), the text is replaced. */
+
+ALTER TABLE Answer ADD AmendedTimestamp BIGINT UNSIGNED NULL;
+ALTER TABLE Answer ADD AmendedVersion BINARY(32) NULL;
+
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (19);
@@ -0,0 +1,6 @@
+/* The question's author may accept a specific answer. */
+
+ALTER TABLE Answer ADD Accepted BOOLEAN NOT NULL DEFAULT FALSE;
+
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (20);
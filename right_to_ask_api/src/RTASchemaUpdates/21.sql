@@ -0,0 +1,9 @@
+/* Questions may be marked as duplicates of another, canonical, question. See duplicates.rs */
+
+ALTER TABLE QUESTIONS ADD DuplicateOf BINARY(32) NULL;
+ALTER TABLE QUESTIONS ADD ProposedDuplicateOf BINARY(32) NULL;
+ALTER TABLE QUESTIONS ADD INDEX(DuplicateOf);
+ALTER TABLE ModerationAudit MODIFY Action ENUM('CensorQuestion','CensorAnswers','AllowQuestion','BlockUser','UnblockUser','CensorLeaf','MarkDuplicate') NOT NULL;
+
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (21);
@@ -0,0 +1,22 @@
+/* An explicit lifecycle for questions, and a record of each change. See lifecycle.rs */
+
+ALTER TABLE QUESTIONS ADD Lifecycle ENUM('Open','CommittedToAsk','Asked','Answered','Closed') NOT NULL DEFAULT 'Open';
+ALTER TABLE QUESTIONS ADD INDEX(Lifecycle);
+UPDATE QUESTIONS set Lifecycle='Asked' where exists (select * from HansardLink where HansardLink.QuestionId=QUESTIONS.QuestionId);
+UPDATE QUESTIONS set Lifecycle='Answered' where exists (select * from Answer where Answer.QuestionId=QUESTIONS.QuestionId and Answer.CensorshipStatus!='Censored');
+
+create table if not exists LifecycleTransitions
+(
+    QuestionId  BINARY(32) NOT NULL,
+    FromStatus ENUM('Open','CommittedToAsk','Asked','Answered','Closed') NOT NULL,
+    ToStatus ENUM('Open','CommittedToAsk','Asked','Answered','Closed') NOT NULL,
+    UserId INTEGER NOT NULL,
+    timestamp BIGINT UNSIGNED NOT NULL,
+    BulletinBoardHash BINARY(32) NOT NULL,
+    INDEX(QuestionId),
+    constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT,
+    constraint foreign key (UserId) REFERENCES USERS (id) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (22);
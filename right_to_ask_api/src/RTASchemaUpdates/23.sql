@@ -0,0 +1,51 @@
+/* Notification subscriptions, and the outbox of events to notify subscribers about. See notifications.rs */
+
+create table if not exists Subscriptions
+(
+    id  BIGINT PRIMARY KEY AUTO_INCREMENT NOT NULL,
+    UserId INTEGER NOT NULL,
+    QuestionId BINARY(32) NULL,
+    MP INT NULL,
+    ElectorateId INT NULL,
+    TagId INT NULL,
+    Sink ENUM('Email','Webhook','Pull') NOT NULL,
+    WebhookURL TEXT NULL,
+    INDEX(UserId),
+    INDEX(QuestionId),
+    constraint foreign key (UserId) REFERENCES USERS (id) ON DELETE CASCADE ON UPDATE RESTRICT,
+    constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT,
+    constraint foreign key (MP) REFERENCES MP_IDs (id) ON DELETE CASCADE ON UPDATE RESTRICT,
+    constraint foreign key (ElectorateId) REFERENCES ElectorateDefinition (id) ON DELETE CASCADE ON UPDATE RESTRICT,
+    constraint foreign key (TagId) REFERENCES TopicTags (id) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
+create table if not exists NotificationOutbox
+(
+    id  BIGINT PRIMARY KEY AUTO_INCREMENT NOT NULL,
+    QuestionId BINARY(32) NOT NULL,
+    Event ENUM('AnswerAdded','HansardLinkAdded','QuestionCensored','FollowUpPosted') NOT NULL,
+    Related BINARY(32) NULL,
+    Url TEXT NULL,
+    timestamp BIGINT UNSIGNED NOT NULL,
+    Processed BOOLEAN NOT NULL DEFAULT FALSE,
+    INDEX(Processed),
+    constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
+create table if not exists NotificationDeliveries
+(
+    id  BIGINT PRIMARY KEY AUTO_INCREMENT NOT NULL,
+    OutboxId BIGINT NOT NULL,
+    UserId INTEGER NOT NULL,
+    Sink ENUM('Email','Webhook','Pull') NOT NULL,
+    WebhookURL TEXT NULL,
+    Status ENUM('Pending','Delivered','Failed') NOT NULL DEFAULT 'Pending',
+    Attempts INT NOT NULL DEFAULT 0,
+    INDEX(Status),
+    INDEX(UserId),
+    constraint foreign key (OutboxId) REFERENCES NotificationOutbox (id) ON DELETE CASCADE ON UPDATE RESTRICT,
+    constraint foreign key (UserId) REFERENCES USERS (id) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (23);
@@ -0,0 +1,18 @@
+/* A moderation queue, ordered by priority, with items claimed by one moderator at a time. See moderation_queue.rs */
+
+ALTER TABLE QUESTIONS ADD FlaggedTimestamp BIGINT UNSIGNED NULL;
+UPDATE QUESTIONS set FlaggedTimestamp=LastModifiedTimestamp where NumFlags>0;
+ALTER TABLE QuestionReportedReasons ADD timestamp BIGINT UNSIGNED NULL;
+ALTER TABLE AnswerReportedReasons ADD timestamp BIGINT UNSIGNED NULL;
+
+create table if not exists ModerationClaims
+(
+    QuestionId  BINARY(32) PRIMARY KEY NOT NULL,
+    Moderator   VARCHAR(60) NOT NULL, /* The name of the administrator in config.toml. Not a reference to USERS */
+    ClaimedTimestamp BIGINT UNSIGNED NOT NULL,
+    ExpiresTimestamp BIGINT UNSIGNED NOT NULL,
+    constraint foreign key (QuestionId) REFERENCES QUESTIONS (QuestionId) ON DELETE CASCADE ON UPDATE RESTRICT
+) CHARACTER SET utf8;
+
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (24);
@@ -0,0 +1,12 @@
+/* At most one plain text vote per user per question. See PlainTextVoteOnQuestionCommand in question.rs */
+
+/* Collapse any duplicate votes left by concurrent requests before the key was added. */
+create temporary table DuplicateVotes as select QuestionId,VoterId,MAX(Up) as Up from HAS_VOTED group by QuestionId,VoterId having count(*)>1;
+delete HAS_VOTED from HAS_VOTED inner join DuplicateVotes on HAS_VOTED.QuestionId=DuplicateVotes.QuestionId and HAS_VOTED.VoterId=DuplicateVotes.VoterId;
+insert into HAS_VOTED (QuestionId,VoterId,Up) select QuestionId,VoterId,Up from DuplicateVotes;
+drop temporary table DuplicateVotes;
+
+ALTER TABLE HAS_VOTED ADD UNIQUE KEY (QuestionId,VoterId);
+
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (25);
@@ -0,0 +1,6 @@
+/* Exponential backoff between attempts to deliver a notification. See notifications.rs */
+
+ALTER TABLE NotificationDeliveries ADD NextAttemptTimestamp BIGINT UNSIGNED NULL;
+
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (26);
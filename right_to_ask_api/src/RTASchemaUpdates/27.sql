@@ -0,0 +1,6 @@
+/* The bulletin board entry that retired a key. See PublicKeyHistoryEntry in person.rs */
+
+ALTER TABLE PublicKeyHistory ADD RevocationBulletinBoardHash BINARY(32) NULL;
+
+delete from SchemaVersion;
+insert into SchemaVersion (version) values (27);
@@ -0,0 +1,181 @@
+//! Roles of administrators, and an audit trail of moderation actions they take.
+//!
+//! Administrators are listed in config.toml (see [crate::config::AdministratorConfig]), not in the USERS table.
+//! Each has one or more roles, and each `/admin/*` or `/moderation/*` API requires one of a set of roles.
+
+use std::fmt;
+use anyhow::anyhow;
+use merkle_tree_bulletin_board::hash::HashValue;
+use merkle_tree_bulletin_board::hash_history::{Timestamp, timestamp_now};
+use mysql::prelude::Queryable;
+use mysql_common::value::convert::{ConvIr, FromValue, FromValueError};
+use mysql_common::value::Value;
+use serde::{Serialize, Deserialize};
+use crate::censorship::CensorshipReason;
+use crate::database::get_rta_database_connection;
+use crate::person::UserUID;
+use crate::question::{hash_from_value, QuestionID};
+
+/// What an administrator is allowed to do. An administrator may have multiple roles.
+#[derive(Debug,Copy,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub enum AdministratorRole {
+    /// May review reported questions, censor or allow them, block users, and view the moderation audit log.
+    Moderator,
+    /// May do things to the server itself - reload MP lists, publish bulletin board roots, censor
+    /// arbitrary bulletin board leaves, and view the moderation audit log.
+    Admin,
+    /// May view and change the lists of email addresses - the do not email list and the times sent lists.
+    DataAdmin,
+}
+
+/// The kind of action recorded in the moderation audit log.
+#[derive(Debug,Copy,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub enum ModerationAction {
+    /// A whole question was censored
+    CensorQuestion,
+    /// Some answers to a question were censored, the rest of the question was allowed.
+    CensorAnswers,
+    /// A reported question was looked at and allowed.
+    AllowQuestion,
+    BlockUser,
+    UnblockUser,
+    /// A bulletin board leaf was censored directly.
+    CensorLeaf,
+    /// A question was marked (or unmarked) as a duplicate of another.
+    MarkDuplicate,
+}
+
+/* Boilerplate to make it easy to transfer ModerationAction to SQL */
+
+// Provide Display & to_string() for ModerationAction enum
+impl fmt::Display for ModerationAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<ModerationAction> for Value {
+    fn from(s: ModerationAction) -> Self {
+        Value::Bytes(s.to_string().into_bytes())
+    }
+}
+
+impl TryFrom<&str> for ModerationAction {
+    type Error = anyhow::Error;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.as_bytes() {
+            b"CensorQuestion" => Ok(ModerationAction::CensorQuestion),
+            b"CensorAnswers" => Ok(ModerationAction::CensorAnswers),
+            b"AllowQuestion" => Ok(ModerationAction::AllowQuestion),
+            b"BlockUser" => Ok(ModerationAction::BlockUser),
+            b"UnblockUser" => Ok(ModerationAction::UnblockUser),
+            b"CensorLeaf" => Ok(ModerationAction::CensorLeaf),
+            b"MarkDuplicate" => Ok(ModerationAction::MarkDuplicate),
+            _ => Err(anyhow!("Invalid moderation action {}",value)),
+        }
+    }
+}
+impl ConvIr<ModerationAction> for ModerationAction {
+    fn new(v: Value) -> Result<Self, FromValueError> {
+        match v {
+            Value::Bytes(bytes) => match bytes.as_slice() {
+                b"CensorQuestion" => Ok(ModerationAction::CensorQuestion),
+                b"CensorAnswers" => Ok(ModerationAction::CensorAnswers),
+                b"AllowQuestion" => Ok(ModerationAction::AllowQuestion),
+                b"BlockUser" => Ok(ModerationAction::BlockUser),
+                b"UnblockUser" => Ok(ModerationAction::UnblockUser),
+                b"CensorLeaf" => Ok(ModerationAction::CensorLeaf),
+                b"MarkDuplicate" => Ok(ModerationAction::MarkDuplicate),
+                _ => Err(FromValueError(Value::Bytes(bytes))),
+            },
+            v => Err(FromValueError(v)),
+        }
+    }
+
+    fn commit(self) -> Self { self }
+    fn rollback(self) -> Value { self.into() }
+}
+
+impl FromValue for ModerationAction {
+    type Intermediate = Self;
+}
+
+/// An entry in the moderation audit log, recording who did what.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct ModerationAuditEntry {
+    pub timestamp : Timestamp,
+    /// The name of the administrator in config.toml
+    pub moderator : String,
+    pub action : ModerationAction,
+    /// The question affected, if any.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub question_id : Option<QuestionID>,
+    /// The user affected, if any.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub uid : Option<UserUID>,
+    /// The reason given for censorship, if any.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub reason : Option<CensorshipReason>,
+    /// The bulletin board entry made for the action, or the leaf censored for [ModerationAction::CensorLeaf].
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub bulletin_board_hash : Option<HashValue>,
+}
+
+impl ModerationAuditEntry {
+    /// Make a new entry with the current time and no details.
+    pub fn new(moderator:&str,action:ModerationAction) -> anyhow::Result<Self> {
+        Ok(ModerationAuditEntry{
+            timestamp: timestamp_now()?,
+            moderator: moderator.to_string(),
+            action,
+            question_id: None,
+            uid: None,
+            reason: None,
+            bulletin_board_hash: None,
+        })
+    }
+
+    /// Store in the database. Typically this is done in the same transaction as the action being audited.
+    pub fn record(&self,conn:&mut impl Queryable) -> mysql::Result<()> {
+        conn.exec_drop("insert into ModerationAudit (timestamp,Moderator,Action,QuestionId,UID,Reason,BulletinBoardHash) values (?,?,?,?,?,?,?)",
+                       (self.timestamp,&self.moderator,self.action,self.question_id.map(|h|h.0),&self.uid,self.reason,self.bulletin_board_hash.map(|h|h.0)))
+    }
+}
+
+/// A request to look at the moderation audit log. All fields are optional filters.
+#[derive(Serialize,Deserialize,Debug,Clone,Default)]
+pub struct ModerationAuditQuery {
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub moderator : Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub question_id : Option<QuestionID>,
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub uid : Option<UserUID>,
+}
+
+/// The columns of a row of the ModerationAudit table, as selected by [ModerationAuditQuery::get_moderation_audit_log].
+type ModerationAuditRow = (Timestamp,String,ModerationAction,Option<Value>,Option<UserUID>,Option<CensorshipReason>,Option<Value>);
+
+impl ModerationAuditQuery {
+    /// The maximum number of entries returned, most recent first.
+    const MAX_ENTRIES : usize = 1000;
+
+    pub async fn get_moderation_audit_log(&self) -> mysql::Result<Vec<ModerationAuditEntry>> {
+        let mut conn = get_rta_database_connection().await?;
+        let mut query = "SELECT timestamp,Moderator,Action,QuestionId,UID,Reason,BulletinBoardHash from ModerationAudit where TRUE".to_string();
+        let mut params : Vec<Value> = vec![];
+        if let Some(moderator) = &self.moderator { query.push_str(" and Moderator=?"); params.push(moderator.into()); }
+        if let Some(question_id) = self.question_id { query.push_str(" and QuestionId=?"); params.push(question_id.0.into()); }
+        if let Some(uid) = &self.uid { query.push_str(" and UID=?"); params.push(uid.into()); }
+        query.push_str(&format!(" ORDER BY id DESC LIMIT {}",Self::MAX_ENTRIES));
+        conn.exec_map(query,params,|(timestamp,moderator,action,question_id,uid,reason,bulletin_board_hash):ModerationAuditRow|ModerationAuditEntry{
+            timestamp,
+            moderator,
+            action,
+            question_id: question_id.map(hash_from_value),
+            uid,
+            reason,
+            bulletin_board_hash: bulletin_board_hash.map(hash_from_value),
+        })
+    }
+}
@@ -0,0 +1,250 @@
+//! Anonymous voting on questions, so that the server does not learn who voted which way.
+//!
+//! Each question has its own token key `k` (derived from `vote_token_secret` in config.toml), with public key `K=k·G` on
+//! the Ristretto group. Voting takes two steps:
+//!
+//! 1. Getting a token. The client picks a random 32 byte token `t`, hashes it to a point `T=H(question_id,t)`, and sends
+//!    the blinded point `B=r·T` for random `r` in a signed [RequestVoteTokenCommand]. The server checks this user has not
+//!    had a token (or cast a plain text vote) for this question, and returns `k·B` with a proof that the same `k` was used
+//!    as in `K`. The client checks the proof and unblinds it to get `W=r⁻¹·k·B=k·T`. This is a blind signature on `t`:
+//!    the server never sees `t` or `W`, so cannot link them to the user.
+//! 2. Voting. The client sends an unsigned [AnonymousVoteCommand] containing `t`, `W` and the direction. The server checks
+//!    `W=k·T` and that `t` has not been used before for this question, counts the vote, and publishes it on the bulletin board
+//!    along with a proof that `W=k·T`, so anyone can check each published vote against `K` and recount the tally.
+//!
+//! Clients should wait a random time between steps, and use a different connection, as otherwise the timing or IP address could link them.
+//! Anonymous votes cannot be changed or retracted.
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use merkle_tree_bulletin_board::hash::HashValue;
+use merkle_tree_bulletin_board::hash_history::{Timestamp, timestamp_now};
+use mysql::prelude::Queryable;
+use mysql::TxOpts;
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha512};
+use crate::censorship::CensorshipStatus;
+use crate::config::CONFIG;
+use crate::database::{get_rta_database_connection, LogInBulletinBoard};
+use crate::person::{get_user_id, UserID};
+use crate::question::{bulletin_board_error, hash_from_value, internal_error, QuestionError, QuestionID};
+use crate::signing::ClientSigned;
+
+/// A Ristretto point or scalar, as 64 hex characters.
+pub type HexEncoded = String;
+
+fn decode_32(hex:&str) -> Result<[u8;32],QuestionError> {
+    hex::decode(hex).ok().and_then(|v|v.try_into().ok()).ok_or(QuestionError::InvalidVoteToken)
+}
+
+fn decode_point(hex:&str) -> Result<RistrettoPoint,QuestionError> {
+    CompressedRistretto(decode_32(hex)?).decompress().ok_or(QuestionError::InvalidVoteToken)
+}
+
+fn decode_scalar(hex:&str) -> Result<Scalar,QuestionError> {
+    Option::from(Scalar::from_canonical_bytes(decode_32(hex)?)).ok_or(QuestionError::InvalidVoteToken)
+}
+
+fn encode_point(point:&RistrettoPoint) -> HexEncoded { hex::encode(point.compress().as_bytes()) }
+
+fn random_scalar() -> Scalar {
+    let mut bytes = [0u8;64];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    Scalar::from_bytes_mod_order_wide(&bytes)
+}
+
+/// The point `T` that a token is hashed to.
+pub fn token_point(question_id:QuestionID,token:&[u8;32]) -> RistrettoPoint {
+    let mut input = b"RightToAsk vote token".to_vec();
+    input.extend_from_slice(&question_id.0);
+    input.extend_from_slice(token);
+    RistrettoPoint::hash_from_bytes::<Sha512>(&input)
+}
+
+/// The secret token key for a question, or an error if anonymous voting is not configured.
+fn token_key(question_id:QuestionID) -> Result<Scalar,QuestionError> {
+    let secret = CONFIG.vote_token_secret.as_ref().ok_or(QuestionError::AnonymousVotingNotEnabled)?;
+    Ok(Scalar::from_hash(Sha512::new().chain_update(b"RightToAsk vote token key").chain_update(secret.as_bytes()).chain_update(question_id.0)))
+}
+
+/// Whether vote_token_secret is set in config.toml.
+pub fn anonymous_voting_enabled() -> bool { CONFIG.vote_token_secret.is_some() }
+
+/// The public token key `K` for a question, hex encoded.
+pub fn get_vote_token_public_key(question_id:QuestionID) -> Result<HexEncoded,QuestionError> {
+    Ok(encode_point(&(token_key(question_id)?*RISTRETTO_BASEPOINT_POINT)))
+}
+
+/// A non-interactive Chaum-Pedersen proof that `log_G K = log_X Y`, i.e. that the same key was used for `K` and `Y=k·X`.
+#[derive(Serialize,Deserialize,Debug,Clone,Eq,PartialEq)]
+pub struct EqualLogProof {
+    pub c : HexEncoded,
+    pub z : HexEncoded,
+}
+
+fn equal_log_challenge(k:&RistrettoPoint,x:&RistrettoPoint,y:&RistrettoPoint,a1:&RistrettoPoint,a2:&RistrettoPoint) -> Scalar {
+    let mut hasher = Sha512::new().chain_update(b"RightToAsk equal log proof");
+    for p in [&RISTRETTO_BASEPOINT_POINT,k,x,y,a1,a2] { hasher.update(p.compress().as_bytes()); }
+    Scalar::from_hash(hasher)
+}
+
+impl EqualLogProof {
+    /// Prove that `k·G=K` and `k·X=Y`.
+    fn prove(key:&Scalar,x:&RistrettoPoint) -> EqualLogProof {
+        let s = random_scalar();
+        let c = equal_log_challenge(&(key*RISTRETTO_BASEPOINT_POINT),x,&(key*x),&(s*RISTRETTO_BASEPOINT_POINT),&(s*x));
+        let z = s-c*key;
+        EqualLogProof{ c: hex::encode(c.as_bytes()), z: hex::encode(z.as_bytes()) }
+    }
+
+    /// Check the proof that `log_G K = log_X Y`.
+    pub fn verify(&self,k:&RistrettoPoint,x:&RistrettoPoint,y:&RistrettoPoint) -> bool {
+        let (Ok(c),Ok(z)) = (decode_scalar(&self.c),decode_scalar(&self.z)) else { return false };
+        let a1 = z*RISTRETTO_BASEPOINT_POINT+c*k;
+        let a2 = z*x+c*y;
+        equal_log_challenge(k,x,y,&a1,&a2)==c
+    }
+}
+
+/// Ask for a blind token for voting on a question. Signed by the user.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct RequestVoteTokenCommand {
+    pub question_id : QuestionID,
+    /// The blinded point `B=r·T`.
+    pub blinded : HexEncoded,
+}
+
+/// The server's response to a [RequestVoteTokenCommand].
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct BlindVoteToken {
+    /// `k·B`
+    pub evaluated : HexEncoded,
+    /// Proof that `log_G K = log_B evaluated`, where `K` is the public token key for the question.
+    pub proof : EqualLogProof,
+}
+
+impl RequestVoteTokenCommand {
+    /// Issue a token, at most one per user per question.
+    pub async fn issue(command:&ClientSigned<RequestVoteTokenCommand>) -> Result<BlindVoteToken,QuestionError> {
+        let question_id = command.parsed.question_id;
+        let key = token_key(question_id)?;
+        let blinded = decode_point(&command.parsed.blinded)?;
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error)?;
+        let user_id = get_user_id(&command.signed_message.user,QuestionError::NoSuchUser,QuestionError::InternalError,&mut transaction)?;
+        // Lock the question so a concurrent plain text vote by the same user is not also accepted.
+        let (withdrawn,censorship_status) = transaction.exec_first::<(Option<Timestamp>,CensorshipStatus), _, _>("select WithdrawnTimestamp,CensorshipStatus from QUESTIONS where QuestionID=? for update", (question_id.0,)).map_err(internal_error)?.ok_or(QuestionError::QuestionDoesNotExist)?;
+        if withdrawn.is_some() { return Err(QuestionError::QuestionWithdrawn) }
+        if censorship_status==CensorshipStatus::Censored { return Err(QuestionError::Censored) }
+        let plain_text_votes = transaction.exec_first::<u32, _, _>("select count(*) from HAS_VOTED where QuestionId=? and VoterId=?", (question_id.0, user_id)).map_err(internal_error)?.ok_or(QuestionError::InternalError)?;
+        if plain_text_votes>0 { return Err(QuestionError::AlreadyVoted) }
+        transaction.exec_drop("insert ignore into VoteTokensIssued (QuestionId,UserId) values (?,?)", (question_id.0, user_id)).map_err(internal_error)?;
+        if transaction.affected_rows()==0 { return Err(QuestionError::VoteTokenAlreadyIssued) }
+        transaction.commit().map_err(internal_error)?;
+        Ok(BlindVoteToken{ evaluated: encode_point(&(key*blinded)), proof: EqualLogProof::prove(&key,&blinded) })
+    }
+}
+
+/// Whether a user has been issued a vote token for a question, in which case they may not also vote in plain text.
+pub(crate) fn has_been_issued_vote_token(conn:&mut impl Queryable,question_id:QuestionID,user_id:UserID) -> Result<bool,QuestionError> {
+    let issued = conn.exec_first::<u32, _, _>("select count(*) from VoteTokensIssued where QuestionId=? and UserId=?", (question_id.0, user_id)).map_err(internal_error)?.ok_or(QuestionError::InternalError)?;
+    Ok(issued>0)
+}
+
+/// Cast an anonymous vote using an unblinded token. Not signed.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct AnonymousVoteCommand {
+    pub question_id : QuestionID,
+    /// The random 32 byte token `t`, hex encoded.
+    pub token : HexEncoded,
+    /// The unblinded signature `W=k·T` on the token.
+    pub unblinded : HexEncoded,
+    /// If true, an up vote. If false, a down vote.
+    pub up : bool,
+}
+
+/// The structure posted to the bulletin board for an anonymous vote. It is not part of the question's history chain.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct AnonymousVotePostedToBulletinBoard {
+    pub command : AnonymousVoteCommand,
+    /// The public token key `K` for the question.
+    pub public_key : HexEncoded,
+    /// Proof that `log_G K = log_T unblinded`, so anyone can check the token was valid.
+    pub proof : EqualLogProof,
+    pub timestamp : Timestamp,
+}
+
+impl AnonymousVotePostedToBulletinBoard {
+    /// Check that the vote used a valid token for the question. Does not check for reuse of tokens.
+    pub fn verify(&self) -> bool {
+        let (Ok(token),Ok(k),Ok(w)) = (decode_32(&self.command.token),decode_point(&self.public_key),decode_point(&self.command.unblinded)) else { return false };
+        self.proof.verify(&k,&token_point(self.command.question_id,&token),&w)
+    }
+}
+
+impl AnonymousVoteCommand {
+    /// Count the vote and publish it, returning the bulletin board entry.
+    pub async fn vote(&self) -> Result<HashValue,QuestionError> {
+        let key = token_key(self.question_id)?;
+        let token = decode_32(&self.token)?;
+        let point = token_point(self.question_id,&token);
+        if decode_point(&self.unblinded)?!=key*point { return Err(QuestionError::InvalidVoteToken) }
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error)?;
+        let withdrawn = transaction.exec_first::<Option<Timestamp>, _, _>("select WithdrawnTimestamp from QUESTIONS where QuestionID=?", (self.question_id.0,)).map_err(internal_error)?.ok_or(QuestionError::QuestionDoesNotExist)?;
+        if withdrawn.is_some() { return Err(QuestionError::QuestionWithdrawn) }
+        // Claim the token before publishing, so it can't be used by a concurrent request. The primary key makes a
+        // concurrent insert of the same token wait for this transaction, and then be ignored if it commits.
+        transaction.exec_drop("insert ignore into AnonymousVotes (QuestionId,Token,Up,BulletinBoardHash) values (?,?,?,?)", (self.question_id.0, token, self.up, [0u8;32])).map_err(internal_error)?;
+        if transaction.affected_rows()!=1 { return Err(QuestionError::AlreadyVoted) }
+        let timestamp = timestamp_now().map_err(internal_error)?;
+        let for_bb = AnonymousVotePostedToBulletinBoard{ command: self.clone(), public_key: encode_point(&(key*RISTRETTO_BASEPOINT_POINT)), proof: EqualLogProof::prove(&key,&point), timestamp };
+        let bb_hash = LogInBulletinBoard::AnonymousVote(for_bb).log_in_bulletin_board().await.map_err(bulletin_board_error)?;
+        transaction.exec_drop("update AnonymousVotes set BulletinBoardHash=? where QuestionId=? and Token=?", (bb_hash.0, self.question_id.0, token)).map_err(internal_error)?;
+        transaction.exec_drop("update QUESTIONS set TotalVotes=TotalVotes+1,NetVotes=NetVotes+? where QuestionID=?", (if self.up { 1 } else { -1 }, self.question_id.0)).map_err(internal_error)?;
+        transaction.commit().map_err(internal_error)?;
+        Ok(bb_hash)
+    }
+}
+
+/// Get the bulletin board entries for the anonymous votes on a question, so the tally can be checked.
+pub async fn get_anonymous_votes(question_id:QuestionID) -> mysql::Result<Vec<HashValue>> {
+    let mut conn = get_rta_database_connection().await?;
+    conn.exec_map("select BulletinBoardHash from AnonymousVotes where QuestionId=?",(question_id.0,),|(h,)|hash_from_value(h))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blind_token_round_trip() {
+        let question_id = HashValue([3u8;32]);
+        let key = random_scalar();
+        let public_key = key*RISTRETTO_BASEPOINT_POINT;
+        let token = [9u8;32];
+        let point = token_point(question_id,&token);
+        // client blinds
+        let r = random_scalar();
+        let blinded = r*point;
+        // server evaluates
+        let evaluated = key*blinded;
+        let proof = EqualLogProof::prove(&key,&blinded);
+        assert!(proof.verify(&public_key,&blinded,&evaluated));
+        assert!(!proof.verify(&(random_scalar()*RISTRETTO_BASEPOINT_POINT),&blinded,&evaluated));
+        // client unblinds
+        let unblinded = r.invert()*evaluated;
+        assert_eq!(unblinded,key*point);
+        let for_bb = AnonymousVotePostedToBulletinBoard{
+            command: AnonymousVoteCommand{ question_id, token: hex::encode(token), unblinded: encode_point(&unblinded), up: true },
+            public_key: encode_point(&public_key),
+            proof: EqualLogProof::prove(&key,&point),
+            timestamp: 0,
+        };
+        assert!(for_bb.verify());
+        let wrong_question = AnonymousVotePostedToBulletinBoard{ command: AnonymousVoteCommand{ question_id: HashValue([4u8;32]), ..for_bb.command.clone() }, ..for_bb };
+        assert!(!wrong_question.verify());
+    }
+}
@@ -0,0 +1,36 @@
+use clap::Parser;
+use merkle_tree_bulletin_board::hash_history::timestamp_now;
+use right_to_ask_api::signing::{endorse_new_server_key, get_server_key_history};
+
+/// Program to endorse a new server signing key with the current one (from config.toml), and print
+/// the changes to make to config.toml to switch over to the new key.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// The new public key, base 64 encoded SPKI, as in the public field of the [signing] section of config.toml
+    #[clap(long)]
+    public: String,
+    /// When the new key will start being used, seconds since 1970. Default now.
+    #[clap(long)]
+    valid_from: Option<u64>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let valid_from = match args.valid_from { Some(t) => t, None => timestamp_now()? };
+    let endorsement = endorse_new_server_key(&args.public,valid_from)?;
+    let current = get_server_key_history().pop().expect("No current server key");
+    println!("Add the current key to the list of previous keys in config.toml :\n");
+    println!("[[previous_signing_keys]]");
+    println!("public = \"{}\"",current.public_key_spki);
+    if let Some(current_valid_from) = current.valid_from { println!("valid_from = {}",current_valid_from); }
+    println!("valid_until = {}",valid_from);
+    if let Some(current_endorsement) = &current.endorsement { println!("endorsement = \"{}\"",current_endorsement); }
+    println!("\nand replace the [signing] section with :\n");
+    println!("[signing]");
+    println!("public = \"{}\"",args.public);
+    println!("private = \"stick-the-new-private-key-here\"");
+    println!("valid_from = {}",valid_from);
+    println!("endorsement = \"{}\"",endorsement);
+    Ok(())
+}
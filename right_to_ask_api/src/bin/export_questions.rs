@@ -0,0 +1,43 @@
+use std::fs::File;
+use std::io::Write;
+use anyhow::anyhow;
+use clap::Parser;
+use merkle_tree_bulletin_board::hash::HashValue;
+use right_to_ask_api::export::{ExportFormat, ExportRoot, QuestionExporter};
+
+/// Program to export all uncensored questions, with their answers, votes and Hansard links, for research.
+/// The export is a snapshot as of a published bulletin board root, which is printed to stderr.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// The format of the export, ndjson or csv.
+    #[clap(long, default_value = "ndjson")]
+    format: ExportFormat,
+    /// The published root to pin the export to, in hex. Default the most recently published root.
+    #[clap(long)]
+    root: Option<HashValue>,
+    /// Also export, after the others, questions modified since the root, as they are now.
+    #[clap(long)]
+    include_modified: bool,
+    /// The file to write the export to. Default standard output.
+    #[clap(long)]
+    output: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let root = ExportRoot::resolve(args.root).await.map_err(|e|anyhow!("Could not find root : {}",e))?;
+    eprintln!("Exporting questions pinned to published root {} at {}",root.root,root.timestamp);
+    let mut out : Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let mut exporter = QuestionExporter::new(root,args.format,args.include_modified).await.map_err(|e|anyhow!("Could not list questions : {}",e))?;
+    while let Some(batch) = exporter.next_batch().await.map_err(|e|anyhow!("Could not export questions : {}",e))? {
+        out.write_all(&batch)?;
+    }
+    out.flush()?;
+    eprintln!("Ran successfully");
+    Ok(())
+}
@@ -10,11 +10,14 @@ use mysql::prelude::Queryable;
 use mysql::TxOpts;
 use mysql_common::value::convert::{ConvIr, FromValue, FromValueError};
 use mysql_common::value::Value;
-use crate::database::{get_bulletin_board, get_rta_database_connection, LogInBulletinBoard, remove_question_from_comparison_database};
+use crate::database::{get_bulletin_board, get_rta_database_connection, LogInBulletinBoard};
 use crate::question::{bulletin_board_error, hash_from_value, internal_error, LastQuestionUpdate, modify_question_database_version_and_time, QuestionError, QuestionID, QuestionInfo};
 use crate::signing::ClientSigned;
 use serde::{Serialize, Deserialize};
 use crate::person::UserID;
+use crate::notifications::{raise_notification_event, NotificationEvent};
+use crate::administration::{ModerationAction, ModerationAuditEntry};
+use crate::moderation_queue::check_claimed_by;
 
 /// Why a question could be censored.
 #[derive(Debug,Copy,Clone,Serialize,Deserialize,Eq,PartialEq)]
@@ -35,6 +38,24 @@ pub enum CensorshipReason {
     Spam,
 }
 
+impl CensorshipReason {
+    /// How serious the allegation is, from 1 to 10, used to prioritise the moderation queue. See [crate::moderation_queue].
+    pub fn severity(self) -> u32 {
+        match self {
+            CensorshipReason::ThreateningViolence => 10,
+            CensorshipReason::EncouragesHarm => 9,
+            CensorshipReason::IncludesPrivateInformation => 8,
+            CensorshipReason::TargetedHarassment => 8,
+            CensorshipReason::IncitesHatredOrDiscrimination => 7,
+            CensorshipReason::Illegal => 7,
+            CensorshipReason::DefamatoryInsinuation => 5,
+            CensorshipReason::Impersonation => 5,
+            CensorshipReason::Spam => 2,
+            CensorshipReason::NotAQuestion => 1,
+        }
+    }
+}
+
 /// Whether a question is censored or not... or things inbetween.
 /// * The state always starts off as NotFlagged.
 /// * When a question is reported/flagged, NotFlagged->Flagged, and StructureChanged->StructureChangedThenFlagged
@@ -195,8 +216,10 @@ pub struct CensorQuestionCommand {
 }
 
 impl CensorQuestionCommand {
-    pub async fn censor_question(&self) -> Result<HashValue,QuestionError> {
-        println!("Got censorship request : {:?}",self);
+    /// Perform the censorship (or allowance). `moderator` is the name of the administrator who signed the command,
+    /// and is recorded in the bulletin board and the moderation audit log.
+    pub async fn censor_question(&self,moderator:&str) -> Result<HashValue,QuestionError> {
+        println!("Got censorship request from moderator {} : {:?}",moderator,self);
         let question_info = QuestionInfo::lookup(self.question_id).await?.ok_or_else(||QuestionError::QuestionDoesNotExist)?;  // Makes sure the question exists and is not censored already.
         if question_info.version!=self.version { return Err(QuestionError::LastUpdateIsNotCurrent); }
         let timestamp = timestamp_now().map_err(internal_error)?;
@@ -206,16 +229,21 @@ impl CensorQuestionCommand {
         }
         let version = if self.censor_logs && self.reason.is_some() { // work out exactly what we want to censor, and put it in "removed".
             let history = QuestionHistory::lookup(self.question_id).await?;
+            let mut amendments : HashMap<HashValue,HashValue> = HashMap::new(); // map from an amendment to the answer it amends.
             for h in &history.history {
                 match &h.action {
                     Some(LogInBulletinBoard::NewQuestion(_)) => { removed.push(CensoredBulletinBoardQuestionElement{id:h.id,prior:None})}
                     Some(LogInBulletinBoard::EditQuestion(q)) => { removed.push(CensoredBulletinBoardQuestionElement{id:h.id,prior:Some(q.prior)})}
+                    Some(LogInBulletinBoard::AmendAnswer(a)) => {
+                        removed.push(CensoredBulletinBoardQuestionElement{id:h.id,prior:Some(a.prior)});
+                        amendments.insert(h.id,a.command.parsed.answer_version);
+                    }
                     _ => {} // don't censor user flags or censorship!
                 }
             }
-            if !self.just_answer.is_empty() {
-                removed.retain(|e|self.just_answer.contains(&e.id));
-                if removed.len()!=self.just_answer.len() { return Err(QuestionError::CouldNotCensorQuestions)}
+            if !self.just_answer.is_empty() { // censor the entries containing the answers, and any amendments to them.
+                removed.retain(|e|self.just_answer.contains(&e.id) || amendments.get(&e.id).is_some_and(|a|self.just_answer.contains(a)));
+                if removed.iter().filter(|e|self.just_answer.contains(&e.id)).count()!=self.just_answer.len() { return Err(QuestionError::CouldNotCensorQuestions)}
             }
             history.history[0].id
         } else {
@@ -225,29 +253,43 @@ impl CensorQuestionCommand {
             command : self.clone(),
             prior : version,
             removed : removed.clone(),
+            moderator : Some(moderator.to_string()),
         };
-        let response = LogInBulletinBoard::CensorQuestion(for_bb).log_in_bulletin_board().await.map_err(bulletin_board_error)?;
         let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
         let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error)?;
+        // Lock the question, so no other moderation of it can happen until this is committed.
+        let (current_version,outstanding_flags) : (Value,usize) = transaction.exec_first("select Version,NumFlags from QUESTIONS where QuestionID=? for update",(self.question_id.0,)).map_err(internal_error)?.ok_or(QuestionError::QuestionDoesNotExist)?;
+        if hash_from_value(current_version)!=self.version { return Err(QuestionError::LastUpdateIsNotCurrent); }
+        check_claimed_by(&mut transaction,self.question_id,moderator,timestamp)?;
+        if self.num_flags>outstanding_flags { return Err(QuestionError::FlagsAlreadyModerated); }
+        let response = LogInBulletinBoard::CensorQuestion(for_bb).log_in_bulletin_board().await.map_err(bulletin_board_error)?;
         modify_question_database_version_and_time(&mut transaction,self.question_id,response,Some(version),timestamp).await?;
         transaction.exec_drop("update QUESTIONS set NumFlags=NumFlags-? where QuestionID=?", (self.num_flags,self.question_id.0)).map_err(internal_error)?;
+        transaction.exec_drop("update QUESTIONS set FlaggedTimestamp=NULL where QuestionID=? and NumFlags=0", (self.question_id.0,)).map_err(internal_error)?;
+        transaction.exec_drop("delete from ModerationClaims where QuestionId=?", (self.question_id.0,)).map_err(internal_error)?;
         if self.reason.is_none() { // allow it if reason is not specified.
             transaction.exec_drop("update QUESTIONS set CensorshipStatus='Allowed' where QuestionID=?", (self.question_id.0,)).map_err(internal_error)?;
         } else {
             if self.just_answer.is_empty() { // censor the whole question
                 transaction.exec_drop("update QUESTIONS set CensorshipStatus='Censored' where QuestionID=?", (self.question_id.0,)).map_err(internal_error)?;
+                raise_notification_event(&mut transaction,self.question_id,NotificationEvent::QuestionCensored,None,None,timestamp)?;
             } else {
                 for answer_id in &self.just_answer {
                     transaction.exec_drop("update Answer set CensorshipStatus='Censored' where version=?", (answer_id.0,)).map_err(internal_error)?;
                 }
             }
         }
+        let action = if self.reason.is_none() { ModerationAction::AllowQuestion } else if self.just_answer.is_empty() { ModerationAction::CensorQuestion } else { ModerationAction::CensorAnswers };
+        let mut audit = ModerationAuditEntry::new(moderator,action).map_err(internal_error)?;
+        audit.question_id = Some(self.question_id);
+        audit.reason = self.reason;
+        audit.bulletin_board_hash = Some(response);
+        audit.record(&mut transaction).map_err(internal_error)?;
         transaction.commit().map_err(internal_error)?;
         // TODO it would make sense to put some message in the BB saying that the just posted entry did not make it into the database for some reason if there were an error above.
         for remove in removed { // don't censor things until stored in the database otherwise we will be unhappy.
             get_bulletin_board().await.censor_leaf(remove.id).map_err(bulletin_board_error)?;
         }
-        if self.reason.is_none() && self.just_answer.is_empty() { remove_question_from_comparison_database(self.question_id).await.map_err(internal_error)?; }
         Ok(response)
     }
 }
@@ -259,7 +301,10 @@ pub struct CensorQuestionCommandPostedToBulletinBoard {
     /// This will be a link to the prior node in the database.
     pub prior : LastQuestionUpdate,
     #[serde(skip_serializing_if = "Vec::is_empty",default)]
-    pub removed : Vec<CensoredBulletinBoardQuestionElement>
+    pub removed : Vec<CensoredBulletinBoardQuestionElement>,
+    /// The name of the administrator who made the decision. None for entries made before this was recorded.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub moderator : Option<String>,
 }
 
 /// Censoring an element in the bulletin board disrupts the linked list. This provides the prior elements for disrupted elements.
@@ -310,17 +355,18 @@ impl ReportQuestionCommand {
         modify_question_database_version_and_time(&mut transaction,command.parsed.question_id,response,Some(question_info.version),timestamp).await?;
          */
         let user_id : UserID = transaction.exec_first("select id from USERS where UID=?",(&command.signed_message.user,)).map_err(internal_error)?.ok_or(QuestionError::NoSuchUser)?;
+        let timestamp = timestamp_now().map_err(internal_error)?;
         let insert_result = if let Some(answer) = command.parsed.just_answer {
-            transaction.exec_drop("INSERT INTO AnswerReportedReasons (QuestionId,reason,answer,user_id) VALUES (?,?,?,?)",(command.parsed.question_id.0,command.parsed.reason,answer.0,user_id))
+            transaction.exec_drop("INSERT INTO AnswerReportedReasons (QuestionId,reason,answer,user_id,timestamp) VALUES (?,?,?,?,?)",(command.parsed.question_id.0,command.parsed.reason,answer.0,user_id,timestamp))
         } else {
-            transaction.exec_drop("INSERT INTO QuestionReportedReasons (QuestionId,reason,user_id) VALUES (?,?,?)",(command.parsed.question_id.0,command.parsed.reason,user_id))
+            transaction.exec_drop("INSERT INTO QuestionReportedReasons (QuestionId,reason,user_id,timestamp) VALUES (?,?,?,?)",(command.parsed.question_id.0,command.parsed.reason,user_id,timestamp))
         };
         match insert_result {
             Ok(()) => {},
             Err(MySqlError(e)) if e.code== (mysql::ServerError::ER_DUP_ENTRY as u16) => {return Err(QuestionError::AlreadyReported)},
             Err(e) => {return Err(internal_error(e))}
         }
-        transaction.exec_drop("update QUESTIONS set NumFlags=NumFlags+1, CensorshipStatus = IF(CensorshipStatus='NotFlagged','Flagged', IF(CensorshipStatus='StructureChanged','StructureChangedThenFlagged', CensorshipStatus))  where QuestionId=?",(&command.parsed.question_id.0,)).map_err(internal_error)?;
+        transaction.exec_drop("update QUESTIONS set FlaggedTimestamp=IF(NumFlags=0,?,FlaggedTimestamp), NumFlags=NumFlags+1, CensorshipStatus = IF(CensorshipStatus='NotFlagged','Flagged', IF(CensorshipStatus='StructureChanged','StructureChangedThenFlagged', CensorshipStatus))  where QuestionId=?",(timestamp,&command.parsed.question_id.0)).map_err(internal_error)?;
         transaction.commit().map_err(internal_error)?;
         Ok(()) // Should return response if want to post report questions on the bulletin board
     }
@@ -337,7 +383,7 @@ pub struct ReportedQuestionSummary {
 }
 
 impl ReportedQuestionSummary {
-    /// Get a list of all the reported questions since last moderation.
+    /// Get a list of all the reported questions since last moderation. See [crate::moderation_queue] for a prioritised list.
     pub async fn get_reported_questions()  -> mysql::Result<Vec<ReportedQuestionSummary>> {
         let mut conn = get_rta_database_connection().await?;
         let elements : Vec<ReportedQuestionSummary> = conn.exec_map("SELECT QuestionID,Question,NumFlags,CensorshipStatus from QUESTIONS where NumFlags>0 ORDER BY NumFlags DESC",(),|(id,question_text,num_flags,censorship_status)|ReportedQuestionSummary{id:hash_from_value(id), question_text, num_flags, censorship_status })?;
@@ -356,11 +402,11 @@ pub struct ReportedQuestionReasonSummary {
 /// The number of people that gave a specific reason for censoring.
 #[derive(Serialize,Deserialize,Debug,Clone)]
 pub struct SingleReasonSummary {
-    reason : CensorshipReason,
-    count : usize,
+    pub(crate) reason : CensorshipReason,
+    pub(crate) count : usize,
     /// if this pertains to a specific answer, the identifier for the answer.
     #[serde(skip_serializing_if = "Option::is_none",default)]
-    answer : Option<HashValue>,
+    pub(crate) answer : Option<HashValue>,
 }
 
 impl ReportedQuestionReasonSummary {
@@ -426,6 +472,11 @@ impl QuestionHistory {
                             Some(c.prior)
                         }
                         LogInBulletinBoard::PlainTextVoteQuestion(v) => Some(v.prior),
+                        LogInBulletinBoard::WithdrawQuestion(w) => Some(w.prior),
+                        LogInBulletinBoard::AmendAnswer(a) => Some(a.prior),
+                        LogInBulletinBoard::ProposeDuplicate(p) => Some(p.prior),
+                        LogInBulletinBoard::MarkDuplicate(m) => Some(m.prior),
+                        LogInBulletinBoard::SetLifecycle(l) => Some(l.prior),
                         _ => { println!("Unexpected action found in Bulletin board"); return Err(QuestionError::BulletinBoardHistoryIsCorrupt) }
                     };
                     Some(found_action)
@@ -5,18 +5,202 @@ use lettre::address::AddressError;
 use lettre::message::Mailbox;
 use lettre::transport::smtp::authentication::Credentials;
 use serde::{Serialize,Deserialize};
+use merkle_tree_bulletin_board::hash_history::Timestamp;
+use crate::administration::AdministratorRole;
+use crate::rate_limit::{RateLimitedAction, RateLimitSubject};
 
 const CONFIG_FILE_NAME: &str = if cfg!(test) {"test_config.toml"} else {"config.toml"};
 
 #[derive(Deserialize)]
 pub struct Config {
     pub(crate) signing : Base64EncodedKeyPair,
+    /// Keys the server used to sign with before the current one, oldest first.
+    #[serde(default)]
+    pub(crate) previous_signing_keys : Vec<PreviousServerKey>,
     pub(crate) database : DatabaseURLs,
     pub(crate) search_cache_size : std::num::NonZeroUsize,
     #[serde(default)]
     pub(crate) require_validated_email: bool, // this will be removed in the future when it is required.
     #[serde(default)]
     pub(crate) email : Option<EmailConfig>,
+    /// The people allowed to use the `/admin/*` and `/moderation/*` APIs.
+    #[serde(default)]
+    pub(crate) administrators : Vec<AdministratorConfig>,
+    #[serde(default)]
+    pub(crate) replay_protection : ReplayProtectionConfig,
+    #[serde(default)]
+    pub(crate) rate_limits : RateLimitsConfig,
+    #[serde(default)]
+    pub(crate) proof_of_work : ProofOfWorkConfig,
+    /// Names that may not be used in a UID or display name, in addition to MPs' names. See [crate::reserved_names].
+    #[serde(default="default_reserved_names")]
+    pub(crate) reserved_names : Vec<String>,
+    /// How long, in seconds, between proving control of the email address for an account recovery and being able to install the new key. Default 3 days.
+    #[serde(default="default_account_recovery_delay_seconds")]
+    pub(crate) account_recovery_delay_seconds : u64,
+    /// Secret from which each question's anonymous vote token key is derived. If absent, anonymous voting is disabled. See [crate::anonymous_voting].
+    #[serde(default)]
+    pub(crate) vote_token_secret : Option<String>,
+    #[serde(default)]
+    pub(crate) notifications : NotificationsConfig,
+    /// How often, in seconds, to recompute the answer statistics. Default 1 hour. See [crate::statistics].
+    #[serde(default="default_statistics_refresh_seconds")]
+    pub(crate) statistics_refresh_seconds : u64,
+    /// How long, in seconds, a moderator's claim on a question in the moderation queue lasts. Default 15 minutes. See [crate::moderation_queue].
+    #[serde(default="default_moderation_claim_seconds")]
+    pub(crate) moderation_claim_seconds : u64,
+}
+
+fn default_reserved_names() -> Vec<String> { ["RightToAsk","Administrator","Moderator"].iter().map(|s|s.to_string()).collect() }
+
+fn default_account_recovery_delay_seconds() -> u64 { 3*24*60*60 }
+
+fn default_statistics_refresh_seconds() -> u64 { 60*60 }
+
+fn default_moderation_claim_seconds() -> u64 { 15*60 }
+
+/// How client signed messages are protected against being replayed.
+#[derive(Deserialize)]
+pub(crate) struct ReplayProtectionConfig {
+    /// How far, in seconds, a client's timestamp may be from the server's time. Default 300.
+    #[serde(default="default_freshness_window_seconds")]
+    pub freshness_window_seconds : u64,
+    /// If true (the default), accept messages without a timestamp and nonce, from clients that predate api_level 1.
+    /// This should be set to false once all clients have been upgraded.
+    #[serde(default="default_accept_legacy_signatures")]
+    pub accept_legacy_signatures : bool,
+}
+
+fn default_freshness_window_seconds() -> u64 { 300 }
+fn default_accept_legacy_signatures() -> bool { true }
+
+impl Default for ReplayProtectionConfig {
+    fn default() -> Self {
+        ReplayProtectionConfig{ freshness_window_seconds: default_freshness_window_seconds(), accept_legacy_signatures: default_accept_legacy_signatures() }
+    }
+}
+
+/// How notifications to subscribers are delivered. See [crate::notifications].
+#[derive(Deserialize)]
+pub(crate) struct NotificationsConfig {
+    /// How often, in seconds, to look for new events and deliver notifications. Default 60.
+    #[serde(default="default_notification_poll_interval_seconds")]
+    pub poll_interval_seconds : u64,
+    /// How many times to try to deliver an email or webhook notification before giving up. Default 5.
+    #[serde(default="default_max_delivery_attempts")]
+    pub max_delivery_attempts : u32,
+}
+
+fn default_notification_poll_interval_seconds() -> u64 { 60 }
+fn default_max_delivery_attempts() -> u32 { 5 }
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        NotificationsConfig{ poll_interval_seconds: default_notification_poll_interval_seconds(), max_delivery_attempts: default_max_delivery_attempts() }
+    }
+}
+
+/// The proof of work needed to register. See [crate::proof_of_work].
+#[derive(Deserialize)]
+pub(crate) struct ProofOfWorkConfig {
+    /// If false (the default), registrations without a proof of work are accepted, from clients that predate it.
+    /// This should be set to true once all clients have been upgraded.
+    #[serde(default)]
+    pub required : bool,
+    /// The number of leading zero bits required when registrations are at or below the target rate. Default 18.
+    #[serde(default="default_min_difficulty")]
+    pub min_difficulty : u32,
+    /// The most leading zero bits ever required. Default 26.
+    #[serde(default="default_max_difficulty")]
+    pub max_difficulty : u32,
+    /// Each doubling of the number of registrations in the last hour above this adds one to the difficulty. Default 60.
+    #[serde(default="default_target_registrations_per_hour")]
+    pub target_registrations_per_hour : usize,
+}
+
+fn default_min_difficulty() -> u32 { 18 }
+fn default_max_difficulty() -> u32 { 26 }
+fn default_target_registrations_per_hour() -> usize { 60 }
+
+impl Default for ProofOfWorkConfig {
+    fn default() -> Self {
+        ProofOfWorkConfig{ required: false, min_difficulty: default_min_difficulty(), max_difficulty: default_max_difficulty(), target_registrations_per_hour: default_target_registrations_per_hour() }
+    }
+}
+
+/// Limits on how often a user or client IP address may perform write operations. See [crate::rate_limit].
+#[derive(Deserialize)]
+pub(crate) struct RateLimitsConfig {
+    /// Default true.
+    #[serde(default="default_rate_limits_enabled")]
+    pub enabled : bool,
+    /// The limits for a client IP address are this many times the limits for a user, as many people may share an address. Default 10.
+    #[serde(default="default_ip_multiplier")]
+    pub ip_multiplier : f64,
+    /// If true, take the client IP address from the Forwarded or X-Forwarded-For header. Only set this when behind a reverse proxy that sets them. Default false.
+    #[serde(default)]
+    pub trust_forwarded_headers : bool,
+    #[serde(default="default_new_registration_limit")]
+    pub new_registration : TokenBucketConfig,
+    #[serde(default="default_new_question_limit")]
+    pub new_question : TokenBucketConfig,
+    #[serde(default="default_edit_question_limit")]
+    pub edit_question : TokenBucketConfig,
+    #[serde(default="default_vote_limit")]
+    pub vote : TokenBucketConfig,
+    #[serde(default="default_report_question_limit")]
+    pub report_question : TokenBucketConfig,
+    #[serde(default="default_export_limit")]
+    pub export : TokenBucketConfig,
+}
+
+/// A token bucket : up to `burst` operations may be done at once, after which they are allowed at `per_hour` per hour.
+#[derive(Deserialize,Copy,Clone,Debug)]
+pub(crate) struct TokenBucketConfig {
+    pub burst : f64,
+    pub per_hour : f64,
+}
+
+fn default_rate_limits_enabled() -> bool { true }
+fn default_ip_multiplier() -> f64 { 10.0 }
+fn default_new_registration_limit() -> TokenBucketConfig { TokenBucketConfig{ burst: 3.0, per_hour: 3.0 } }
+fn default_new_question_limit() -> TokenBucketConfig { TokenBucketConfig{ burst: 10.0, per_hour: 20.0 } }
+fn default_edit_question_limit() -> TokenBucketConfig { TokenBucketConfig{ burst: 20.0, per_hour: 60.0 } }
+fn default_vote_limit() -> TokenBucketConfig { TokenBucketConfig{ burst: 60.0, per_hour: 300.0 } }
+fn default_report_question_limit() -> TokenBucketConfig { TokenBucketConfig{ burst: 10.0, per_hour: 30.0 } }
+fn default_export_limit() -> TokenBucketConfig { TokenBucketConfig{ burst: 0.2, per_hour: 0.5 } }
+
+impl Default for RateLimitsConfig {
+    fn default() -> Self {
+        RateLimitsConfig{
+            enabled: default_rate_limits_enabled(),
+            ip_multiplier: default_ip_multiplier(),
+            trust_forwarded_headers: false,
+            new_registration: default_new_registration_limit(),
+            new_question: default_new_question_limit(),
+            edit_question: default_edit_question_limit(),
+            vote: default_vote_limit(),
+            report_question: default_report_question_limit(),
+            export: default_export_limit(),
+        }
+    }
+}
+
+impl RateLimitsConfig {
+    pub(crate) fn limit_for(&self,action:RateLimitedAction,subject:&RateLimitSubject) -> TokenBucketConfig {
+        let per_user = match action {
+            RateLimitedAction::NewRegistration | RateLimitedAction::RegistrationChallenge => self.new_registration,
+            RateLimitedAction::NewQuestion => self.new_question,
+            RateLimitedAction::EditQuestion => self.edit_question,
+            RateLimitedAction::Vote => self.vote,
+            RateLimitedAction::ReportQuestion => self.report_question,
+            RateLimitedAction::Export => self.export,
+        };
+        match subject {
+            RateLimitSubject::User(_) => per_user,
+            RateLimitSubject::IP(_) => TokenBucketConfig{ burst: per_user.burst*self.ip_multiplier, per_hour: per_user.per_hour*self.ip_multiplier },
+        }
+    }
 }
 
 /// a wrapper around Mailbox allowing serde parsing.
@@ -54,6 +238,38 @@ pub struct EmailConfig {
 pub(crate) struct Base64EncodedKeyPair {
     pub public : String, // public key
     pub private : String, // private key
+    /// When this key came into use. May be omitted for the very first server key.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub valid_from : Option<Timestamp>,
+    /// The signature by the previous server key of [crate::signing::server_key_endorsement_message] for this key.
+    /// Omitted for the very first server key.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub endorsement : Option<String>,
+}
+
+/// A server key that is no longer used for signing, but is still needed to verify old receipts.
+#[derive(Serialize,Deserialize)]
+pub(crate) struct PreviousServerKey {
+    /// The base 64 encoded SPKI public key, as in [Base64EncodedKeyPair].
+    pub public : String,
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub valid_from : Option<Timestamp>,
+    /// When the next key took over.
+    pub valid_until : Timestamp,
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub endorsement : Option<String>,
+}
+
+/// Someone allowed to use the administrative and moderation APIs. They sign commands as a
+/// [crate::signing::ClientSigned] message with `name` as the user.
+#[derive(Deserialize)]
+pub(crate) struct AdministratorConfig {
+    /// The name used in the `user` field of signed commands. This is independent of the USERS table.
+    pub name : String,
+    /// The base64 encoded raw 32 byte public key, same format as a user's public key.
+    pub public_key : String,
+    /// What this administrator is allowed to do.
+    pub roles : Vec<AdministratorRole>,
 }
 
 #[derive(Deserialize)]
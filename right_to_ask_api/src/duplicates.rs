@@ -0,0 +1,200 @@
+//! Linking questions that ask the same thing, so votes are not split between them.
+//!
+//! A moderator may mark a question as a duplicate of another (the canonical question). The author of a question may
+//! propose that it is a duplicate, which a moderator then confirms (by marking it) or rejects. Both are recorded in the
+//! bulletin board as part of the question's history.
+//!
+//! A duplicate is not deleted. It keeps its own answers and votes, but [crate::question::QuestionInfo::duplicate_of]
+//! points to the canonical question, /get_question redirects to the canonical question by default, and the votes on
+//! all duplicates are added to those of the canonical question when ranking similar questions, counting a user who
+//! voted on several of them once.
+//!
+//! Duplicates always point directly at a canonical question that is not itself a duplicate. Marking a question that
+//! already has duplicates re-points them at the new canonical question, recording the change in each one's history.
+
+use merkle_tree_bulletin_board::hash_history::{Timestamp, timestamp_now};
+use mysql::prelude::Queryable;
+use mysql::{Transaction, TxOpts};
+use serde::{Serialize, Deserialize};
+use crate::administration::{ModerationAction, ModerationAuditEntry};
+use crate::database::{get_rta_database_connection, LogInBulletinBoard};
+use crate::question::{bulletin_board_error, hash_from_value, internal_error, modify_question_database_version_and_time, LastQuestionUpdate, QuestionError, QuestionID, QuestionInfo};
+use crate::signing::ClientSigned;
+
+/// The question that a question is a duplicate of, or the question itself if it is not a duplicate. None if no such question.
+pub(crate) fn canonical_question(conn:&mut impl Queryable,question_id:QuestionID) -> Result<Option<QuestionID>,QuestionError> {
+    let found : Option<mysql::Value> = conn.exec_first("select COALESCE(DuplicateOf,QuestionId) from QUESTIONS where QuestionId=? and CensorshipStatus!='Censored'",(question_id.0,)).map_err(internal_error)?;
+    Ok(found.map(hash_from_value))
+}
+
+/// Resolve the canonical question that `question_id` is to become a duplicate of. The canonical question is locked
+/// until the end of the transaction, so it can't meanwhile become a duplicate itself.
+fn resolve_target(transaction:&mut impl Queryable,question_id:QuestionID,duplicate_of:QuestionID) -> Result<QuestionID,QuestionError> {
+    let canonical = canonical_question(transaction,duplicate_of)?.ok_or(QuestionError::QuestionDoesNotExist)?;
+    if canonical==question_id { return Err(QuestionError::CannotBeDuplicateOfItself); }
+    let canonical_is_duplicate : Option<mysql::Value> = transaction.exec_first("select DuplicateOf from QUESTIONS where QuestionId=? and CensorshipStatus!='Censored' for update",(canonical.0,)).map_err(internal_error)?;
+    match canonical_is_duplicate {
+        Some(mysql::Value::NULL) => Ok(canonical),
+        Some(_) => Err(QuestionError::QuestionIsDuplicate), // changed since canonical_question.
+        None => Err(QuestionError::QuestionDoesNotExist),
+    }
+}
+
+/// The author of a question proposes that it is a duplicate of another question. A moderator needs to confirm it.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct ProposeDuplicateCommand {
+    pub question_id : QuestionID,
+    /// The hash value defining the last update done to the question. This is checked to prevent clashes with edits.
+    pub version : LastQuestionUpdate,
+    /// The question it duplicates. If that is itself a duplicate, its canonical question is used instead.
+    pub duplicate_of : QuestionID,
+}
+
+/// The structure posted to the bulletin board in response to a ProposeDuplicateCommand.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct ProposeDuplicateCommandPostedToBulletinBoard {
+    pub command : ClientSigned<ProposeDuplicateCommand>,
+    pub timestamp : Timestamp,
+    /// This will be a link to the prior node in the database.
+    pub prior : LastQuestionUpdate,
+}
+
+impl ProposeDuplicateCommand {
+    /// Record the proposal, returning the new version of the question.
+    pub async fn propose(command:&ClientSigned<ProposeDuplicateCommand>) -> Result<LastQuestionUpdate,QuestionError> {
+        let question_info = QuestionInfo::lookup(command.parsed.question_id).await?.ok_or(QuestionError::QuestionDoesNotExist)?;
+        if question_info.version!=command.parsed.version { return Err(QuestionError::LastUpdateIsNotCurrent); }
+        if question_info.defining.author != command.signed_message.user { return Err(QuestionError::OnlyAuthorCanProposeDuplicate); }
+        if question_info.withdrawn.is_some() { return Err(QuestionError::QuestionWithdrawn); }
+        if question_info.duplicate_of.is_some() { return Err(QuestionError::QuestionIsDuplicate); }
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error)?;
+        let canonical = resolve_target(&mut transaction,command.parsed.question_id,command.parsed.duplicate_of)?;
+        let timestamp = timestamp_now().map_err(internal_error)?;
+        let for_bb = ProposeDuplicateCommandPostedToBulletinBoard {
+            command: command.clone(),
+            timestamp,
+            prior : command.parsed.version,
+        };
+        let version = LogInBulletinBoard::ProposeDuplicate(for_bb).log_in_bulletin_board().await.map_err(bulletin_board_error)?;
+        modify_question_database_version_and_time(&mut transaction,command.parsed.question_id,version,Some(command.parsed.version),timestamp).await?;
+        transaction.exec_drop("update QUESTIONS set ProposedDuplicateOf=? where QuestionID=?", (canonical.0,command.parsed.question_id.0)).map_err(internal_error)?;
+        transaction.commit().map_err(internal_error)?;
+        Ok(version)
+    }
+}
+
+/// Mark a question as a duplicate, or not. A moderator command.
+///
+/// This is also how a proposal from the author is confirmed (mark it as a duplicate of the proposed question) or
+/// rejected (set duplicate_of to None). Either way the proposal is cleared.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct MarkDuplicateCommand {
+    pub question_id : QuestionID,
+    /// The hash value defining the last update done to the question. This is checked to prevent clashes with edits.
+    pub version : LastQuestionUpdate,
+    /// The question it duplicates, or None to say it is not a duplicate. If this is itself a duplicate, its canonical question is used instead.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub duplicate_of : Option<QuestionID>,
+}
+
+/// The structure posted to the bulletin board in response to a MarkDuplicateCommand.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct MarkDuplicateCommandPostedToBulletinBoard {
+    pub command : MarkDuplicateCommand,
+    /// The canonical question actually used, which may differ from command.duplicate_of if that was a duplicate.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub canonical : Option<QuestionID>,
+    /// The name of the administrator who made the decision.
+    pub moderator : String,
+    pub timestamp : Timestamp,
+    /// This will be a link to the prior node in the database.
+    pub prior : LastQuestionUpdate,
+    /// Set if this question was a duplicate of `repointed_from`, and was re-pointed at `canonical` because
+    /// `repointed_from` was marked as a duplicate of it. `command` is then made by the server rather than the moderator.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub repointed_from : Option<QuestionID>,
+}
+
+impl MarkDuplicateCommand {
+    /// Mark the question, returning the new version. `moderator` is the name of the administrator who signed the command.
+    pub async fn mark_duplicate(&self,moderator:&str) -> Result<LastQuestionUpdate,QuestionError> {
+        let question_info = QuestionInfo::lookup(self.question_id).await?.ok_or(QuestionError::QuestionDoesNotExist)?;
+        if question_info.version!=self.version { return Err(QuestionError::LastUpdateIsNotCurrent); }
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error)?;
+        let canonical = match self.duplicate_of {
+            Some(duplicate_of) => Some(resolve_target(&mut transaction,self.question_id,duplicate_of)?),
+            None => None,
+        };
+        // Duplicates of this question are re-pointed at the new canonical question, to keep duplicates pointing directly at a canonical question.
+        let repointed : Vec<(QuestionID,LastQuestionUpdate)> = if canonical.is_some() {
+            transaction.exec_map("select QuestionId,Version from QUESTIONS where DuplicateOf=? for update",(self.question_id.0,),|(question_id,version)|(hash_from_value(question_id),hash_from_value(version))).map_err(internal_error)?
+        } else { vec![] };
+        let timestamp = timestamp_now().map_err(internal_error)?;
+        let version = self.record(&mut transaction,canonical,moderator,timestamp,None).await?;
+        for (duplicate,prior) in repointed {
+            let command = MarkDuplicateCommand{ question_id: duplicate, version: prior, duplicate_of: canonical };
+            command.record(&mut transaction,canonical,moderator,timestamp,Some(self.question_id)).await?;
+        }
+        transaction.commit().map_err(internal_error)?;
+        println!("Question {} marked as duplicate of {:?} by {}",self.question_id,canonical,moderator);
+        Ok(version)
+    }
+
+    /// Publish the change in the bulletin board and make it in the database, returning the new version.
+    async fn record(&self,transaction:&mut Transaction<'_>,canonical:Option<QuestionID>,moderator:&str,timestamp:Timestamp,repointed_from:Option<QuestionID>) -> Result<LastQuestionUpdate,QuestionError> {
+        let for_bb = MarkDuplicateCommandPostedToBulletinBoard {
+            command: self.clone(),
+            canonical,
+            moderator: moderator.to_string(),
+            timestamp,
+            prior : self.version,
+            repointed_from,
+        };
+        let version = LogInBulletinBoard::MarkDuplicate(for_bb).log_in_bulletin_board().await.map_err(bulletin_board_error)?;
+        modify_question_database_version_and_time(transaction,self.question_id,version,Some(self.version),timestamp).await?;
+        transaction.exec_drop("update QUESTIONS set DuplicateOf=?,ProposedDuplicateOf=NULL where QuestionID=?", (canonical.map(|c|c.0),self.question_id.0)).map_err(internal_error)?;
+        let mut audit = ModerationAuditEntry::new(moderator,ModerationAction::MarkDuplicate).map_err(internal_error)?;
+        audit.question_id = Some(self.question_id);
+        audit.bulletin_board_hash = Some(version);
+        audit.record(transaction).map_err(internal_error)?;
+        Ok(version)
+    }
+}
+
+/// A duplicate proposed by an author, waiting for a moderator.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct ProposedDuplicate {
+    pub question_id : QuestionID,
+    /// The current version of the question, needed for a [MarkDuplicateCommand].
+    pub version : LastQuestionUpdate,
+    pub proposed_duplicate_of : QuestionID,
+}
+
+/// Get the proposed duplicates waiting for a moderator, oldest first.
+pub async fn get_proposed_duplicates() -> mysql::Result<Vec<ProposedDuplicate>> {
+    let mut conn = get_rta_database_connection().await?;
+    conn.exec_map("select QuestionId,Version,ProposedDuplicateOf from QUESTIONS where ProposedDuplicateOf is not NULL and CensorshipStatus!='Censored' order by LastModifiedTimestamp",(),|(question_id,version,proposed_duplicate_of)|ProposedDuplicate{
+        question_id: hash_from_value(question_id),
+        version: hash_from_value(version),
+        proposed_duplicate_of: hash_from_value(proposed_duplicate_of),
+    })
+}
+
+/// Look up a question, or, if it is a duplicate, the question it duplicates unless that has been censored.
+pub async fn lookup_canonical_question(question_id:QuestionID) -> Result<Option<QuestionInfo>,QuestionError> {
+    let canonical = {
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        canonical_question(&mut conn,question_id)?
+    };
+    match canonical {
+        // If the canonical question has been censored since, show the duplicate itself, which is not censored.
+        Some(canonical) if canonical!=question_id => match QuestionInfo::lookup(canonical).await {
+            Err(QuestionError::Censored) | Ok(None) => QuestionInfo::lookup(question_id).await,
+            res => res,
+        },
+        Some(canonical) => QuestionInfo::lookup(canonical).await,
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,257 @@
+//! Bulk export of questions for researchers and journalists.
+//!
+//! Every question that is not censored is exported with its non-defining fields, vote totals, answers and Hansard
+//! links, as either newline delimited JSON (one [ExportedQuestion] per line) or CSV (one [ExportedQuestionCSV] per row).
+//!
+//! An export is a snapshot labelled by a published root of the bulletin board (see [ExportRoot]), so a dataset can be
+//! cited by that root. Only questions created by the time of the root are included, and only as they were at the
+//! root: a question modified since the root was published (including while the export is being produced) is left
+//! out, as its content as of the root is no longer stored. So each exported question's `version` is a bulletin board
+//! entry that can be checked against the root with /get_proof_chain. Exporting right after publishing a new root
+//! (with /admin/order_new_published_root) leaves out few questions.
+//!
+//! If `include_modified` is set, the modified questions are instead exported in their current form after all the
+//! others, with `modified_after_root` set. They are not part of the snapshot.
+//!
+//! Anonymous votes are not in the bulletin board, so vote totals are as of the export rather than the root. Content
+//! censored since the root is left out.
+
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use anyhow::anyhow;
+use merkle_tree_bulletin_board::hash::HashValue;
+use merkle_tree_bulletin_board::hash_history::{HashSource, Timestamp};
+use mysql::prelude::Queryable;
+use serde::{Serialize, Deserialize};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+use crate::database::{get_bulletin_board, get_rta_database_connection};
+use crate::lifecycle::QuestionLifecycle;
+use crate::question::{bulletin_board_error, hash_from_value, internal_error, QuestionError, QuestionID, QuestionInfo, MAX_QUESTIONS_IN_BATCH_LOOKUP};
+
+/// The format of an export. Written and parsed (case insensitively) as `ndjson` or `csv`, both on the command line
+/// and in an HTTP query.
+#[derive(SerializeDisplay,DeserializeFromStr,Debug,Copy,Clone,Eq,PartialEq,Default)]
+pub enum ExportFormat {
+    /// Newline delimited JSON.
+    #[default]
+    NDJSON,
+    CSV,
+}
+
+impl ExportFormat {
+    /// The MIME type of an export in this format.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::NDJSON => "application/x-ndjson",
+            ExportFormat::CSV => "text/csv",
+        }
+    }
+}
+
+impl Display for ExportFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportFormat::NDJSON => write!(f,"ndjson"),
+            ExportFormat::CSV => write!(f,"csv"),
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ndjson" => Ok(ExportFormat::NDJSON),
+            "csv" => Ok(ExportFormat::CSV),
+            _ => Err(anyhow!("Unknown export format {}, expecting ndjson or csv",s)),
+        }
+    }
+}
+
+/// The published bulletin board root that an export is pinned to.
+#[derive(Serialize,Deserialize,Debug,Copy,Clone)]
+pub struct ExportRoot {
+    pub root : HashValue,
+    /// When the root was published.
+    pub timestamp : Timestamp,
+}
+
+impl ExportRoot {
+    /// Check that `requested` is a published root, or get the most recently published root if None.
+    pub async fn resolve(requested:Option<HashValue>) -> Result<ExportRoot,QuestionError> {
+        let bb = get_bulletin_board().await;
+        let root = match requested {
+            Some(root) => {
+                if !bb.get_all_published_roots().map_err(bulletin_board_error)?.contains(&root) { return Err(QuestionError::NotAPublishedRoot); }
+                root
+            }
+            None => bb.get_most_recent_published_root().map_err(bulletin_board_error)?.ok_or(QuestionError::NoPublishedRoot)?,
+        };
+        match bb.get_hash_info(root).map_err(bulletin_board_error)?.source {
+            HashSource::Root(history) => Ok(ExportRoot{ root, timestamp: history.timestamp }),
+            _ => Err(QuestionError::NotAPublishedRoot),
+        }
+    }
+}
+
+/// A question as exported in NDJSON.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct ExportedQuestion {
+    #[serde(flatten)]
+    pub question : QuestionInfo,
+    /// The root the export is pinned to.
+    pub pinned_root : HashValue,
+    /// The question has been modified since the root was published, so its version cannot be checked against it.
+    /// Only exported if `include_modified` is set.
+    #[serde(default)]
+    pub modified_after_root : bool,
+}
+
+/// A question as exported in CSV. Lists are JSON encoded, in the same form as in [ExportedQuestion].
+#[derive(Serialize,Debug,Clone)]
+pub struct ExportedQuestionCSV {
+    pub question_id : QuestionID,
+    pub version : HashValue,
+    pub pinned_root : HashValue,
+    pub modified_after_root : bool,
+    pub author : String,
+    pub question_text : String,
+    pub timestamp : Timestamp,
+    pub last_modified : Timestamp,
+    pub background : Option<String>,
+    pub mp_who_should_ask_the_question : String,
+    pub entity_who_should_answer_the_question : String,
+    pub topic_tags : String,
+    pub total_votes : u32,
+    pub net_votes : i32,
+    pub lifecycle : QuestionLifecycle,
+    pub withdrawn : Option<Timestamp>,
+    pub is_followup_to : Option<QuestionID>,
+    pub duplicate_of : Option<QuestionID>,
+    pub answer_accepted : bool,
+    pub answers : String,
+    pub hansard_link : String,
+}
+
+impl From<ExportedQuestion> for ExportedQuestionCSV {
+    fn from(e: ExportedQuestion) -> Self {
+        let q = e.question;
+        let json = |v:serde_json::Result<String>|v.unwrap_or_default();
+        ExportedQuestionCSV{
+            question_id: q.question_id,
+            version: q.version,
+            pinned_root: e.pinned_root,
+            modified_after_root: e.modified_after_root,
+            author: q.defining.author,
+            question_text: q.defining.question_text,
+            timestamp: q.defining.timestamp,
+            last_modified: q.last_modified,
+            background: q.non_defining.background,
+            mp_who_should_ask_the_question: json(serde_json::to_string(&q.non_defining.mp_who_should_ask_the_question)),
+            entity_who_should_answer_the_question: json(serde_json::to_string(&q.non_defining.entity_who_should_answer_the_question)),
+            topic_tags: json(serde_json::to_string(&q.non_defining.topic_tags)),
+            total_votes: q.total_votes,
+            net_votes: q.net_votes,
+            lifecycle: q.lifecycle,
+            withdrawn: q.withdrawn,
+            is_followup_to: q.non_defining.is_followup_to,
+            duplicate_of: q.duplicate_of,
+            answer_accepted: q.non_defining.answer_accepted,
+            answers: json(serde_json::to_string(&q.non_defining.answers)),
+            hansard_link: json(serde_json::to_string(&q.non_defining.hansard_link)),
+        }
+    }
+}
+
+/// Produces an export a batch of questions at a time, so it can be streamed.
+pub struct QuestionExporter {
+    root : ExportRoot,
+    format : ExportFormat,
+    include_modified : bool,
+    /// Questions not yet exported.
+    remaining : VecDeque<QuestionID>,
+    /// Questions found to be modified since the root, exported after the others if include_modified.
+    modified : VecDeque<QuestionID>,
+    /// Whether the CSV header line has been produced.
+    started : bool,
+}
+
+impl QuestionExporter {
+    /// Prepare to export the uncensored questions created by the time of the root. Questions modified since the root
+    /// are left out unless `include_modified`, in which case they come last.
+    pub async fn new(root:ExportRoot,format:ExportFormat,include_modified:bool) -> Result<QuestionExporter,QuestionError> {
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let remaining : Vec<QuestionID> = conn.exec_map("select QuestionId from QUESTIONS where CensorshipStatus!='Censored' and CreatedTimestamp<=? order by CreatedTimestamp,QuestionId",(root.timestamp,),hash_from_value).map_err(internal_error)?;
+        Ok(QuestionExporter{ root, format, include_modified, remaining: remaining.into(), modified: VecDeque::new(), started: false })
+    }
+
+    pub fn root(&self) -> ExportRoot { self.root }
+
+    /// Get the next part of the export, or None if finished.
+    pub async fn next_batch(&mut self) -> Result<Option<Vec<u8>>,QuestionError> {
+        if self.started && self.remaining.is_empty() && self.modified.is_empty() { return Ok(None); }
+        let in_snapshot = !self.started || !self.remaining.is_empty();
+        let ids : Vec<QuestionID> = if in_snapshot { self.remaining.drain(..self.remaining.len().min(MAX_QUESTIONS_IN_BATCH_LOOKUP)).collect() } else { self.modified.drain(..self.modified.len().min(MAX_QUESTIONS_IN_BATCH_LOOKUP)).collect() };
+        // A question may be censored since the list was made, in which case it is just left out.
+        let mut questions : Vec<ExportedQuestion> = vec![];
+        for question in QuestionInfo::lookup_many(&ids).await? {
+            // timestamps are in seconds, so a change in the same second as the root may be after it.
+            let modified_after_root = question.last_modified>=self.root.timestamp;
+            if in_snapshot && modified_after_root {
+                if self.include_modified { self.modified.push_back(question.question_id); }
+                continue;
+            }
+            questions.push(ExportedQuestion{ modified_after_root, pinned_root: self.root.root, question });
+        }
+        let mut res : Vec<u8> = vec![];
+        match self.format {
+            ExportFormat::NDJSON => {
+                for question in questions {
+                    serde_json::to_writer(&mut res,&question).map_err(internal_error)?;
+                    res.push(b'\n');
+                }
+            }
+            ExportFormat::CSV => {
+                let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(&mut res);
+                if !self.started { writer.write_record(CSV_HEADERS).map_err(internal_error)?; }
+                for question in questions {
+                    writer.serialize(ExportedQuestionCSV::from(question)).map_err(internal_error)?;
+                }
+                writer.flush().map_err(internal_error)?;
+            }
+        }
+        self.started = true;
+        Ok(Some(res))
+    }
+}
+
+/// The header line of a CSV export. Written explicitly so an export with no questions still has one.
+const CSV_HEADERS : [&str;21] = ["question_id","version","pinned_root","modified_after_root","author","question_text","timestamp","last_modified","background","mp_who_should_ask_the_question","entity_who_should_answer_the_question","topic_tags","total_votes","net_votes","lifecycle","withdrawn","is_followup_to","duplicate_of","answer_accepted","answers","hansard_link"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_headers_match_fields() {
+        let hash = HashValue([0;32]);
+        let row = ExportedQuestionCSV{ question_id: hash, version: hash, pinned_root: hash, modified_after_root: false, author: String::new(), question_text: String::new(), timestamp: 0, last_modified: 0, background: None, mp_who_should_ask_the_question: String::new(), entity_who_should_answer_the_question: String::new(), topic_tags: String::new(), total_votes: 0, net_votes: 0, lifecycle: QuestionLifecycle::default(), withdrawn: None, is_followup_to: None, duplicate_of: None, answer_accepted: false, answers: String::new(), hansard_link: String::new() };
+        let mut written : Vec<u8> = vec![];
+        {
+            let mut writer = csv::Writer::from_writer(&mut written);
+            writer.serialize(row).unwrap();
+        }
+        let header = String::from_utf8(written).unwrap().lines().next().unwrap().to_string();
+        assert_eq!(header,CSV_HEADERS.join(","));
+    }
+
+    #[test]
+    fn format_is_case_insensitive() {
+        for (text,format) in [("csv",ExportFormat::CSV),("CSV",ExportFormat::CSV),("ndjson",ExportFormat::NDJSON),("NDJSON",ExportFormat::NDJSON)] {
+            assert_eq!(text.parse::<ExportFormat>().unwrap(),format);
+            assert_eq!(serde_json::from_str::<ExportFormat>(&format!("\"{}\"",text)).unwrap(),format);
+        }
+        assert!("json".parse::<ExportFormat>().is_err());
+    }
+}
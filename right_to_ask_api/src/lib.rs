@@ -14,6 +14,20 @@ pub mod committee;
 pub mod common_file;
 pub mod censorship;
 pub mod minister;
+pub mod administration;
+pub mod rate_limit;
+pub mod proof_of_work;
+pub mod reserved_names;
+pub mod topic_tags;
+pub mod question_list;
+pub mod anonymous_voting;
+pub mod duplicates;
+pub mod lifecycle;
+pub mod notifications;
+pub mod mp_dashboard;
+pub mod statistics;
+pub mod export;
+pub mod moderation_queue;
 mod mp_non_authoritative;
 mod parse_non_authoritative_mp_data;
 
@@ -0,0 +1,275 @@
+//! The real world status of a question, from being posed to being answered.
+//!
+//! Each question has a [QuestionLifecycle], stored in QUESTIONS.Lifecycle, starting at Open. It is changed by a
+//! [SetLifecycleCommand], which is recorded in the bulletin board as part of the question's history, and in the
+//! LifecycleTransitions table so the progress of a question can be shown.
+//!
+//! Only some transitions are allowed (see [QuestionLifecycle::can_change_to]), and each can only be made by
+//! certain people (see [QuestionLifecycle::who_may_change_to]).
+//!
+//! A question also advances automatically when an edit adds an answer (to Answered) or a Hansard link (to Asked),
+//! if that is an allowed transition (see [QuestionLifecycle::advanced_by]). The edit's bulletin board entry is then
+//! recorded as the reason in LifecycleTransitions.
+
+use std::fmt;
+use anyhow::anyhow;
+use merkle_tree_bulletin_board::hash_history::{Timestamp, timestamp_now};
+use mysql::prelude::Queryable;
+use mysql::TxOpts;
+use mysql_common::value::convert::{ConvIr, FromValue, FromValueError};
+use mysql_common::value::Value;
+use serde::{Serialize, Deserialize};
+use crate::database::{get_rta_database_connection, LogInBulletinBoard};
+use crate::person::{get_user_id, UserUID};
+use crate::question::{bulletin_board_error, check_user_has_badge_for_mp, hash_from_value, internal_error, modify_question_database_version_and_time, LastQuestionUpdate, PersonID, QuestionError, QuestionID, QuestionInfo};
+use crate::signing::ClientSigned;
+
+/// Where a question is up to.
+#[derive(Debug,Copy,Clone,Serialize,Deserialize,Eq,PartialEq,Default)]
+pub enum QuestionLifecycle {
+    /// Waiting for someone to ask it.
+    #[default]
+    Open,
+    /// An MP has said they will ask it in parliament.
+    CommittedToAsk,
+    /// It has been asked in parliament.
+    Asked,
+    /// It has been answered, in the app or in parliament.
+    Answered,
+    /// The author considers it finished with. It may be reopened.
+    Closed,
+}
+
+/// Someone who may change the lifecycle of a question.
+#[derive(Debug,Copy,Clone,Eq,PartialEq)]
+pub enum LifecycleRole {
+    /// The author of the question
+    Author,
+    /// Someone with an MP or MPStaff badge for an MP listed in [crate::question::QuestionNonDefiningFields::mp_who_should_ask_the_question].
+    AskingMP,
+    /// Someone with an MP or MPStaff badge for an MP listed in [crate::question::QuestionNonDefiningFields::entity_who_should_answer_the_question].
+    AnsweringMP,
+}
+
+impl QuestionLifecycle {
+    /// Whether a question may go directly from self to next.
+    pub fn can_change_to(self,next:QuestionLifecycle) -> bool {
+        use QuestionLifecycle::*;
+        matches!((self,next),
+            (Open,CommittedToAsk|Asked|Answered|Closed) |
+            (CommittedToAsk,Open|Asked|Answered|Closed) |
+            (Asked,Answered|Closed) |
+            (Answered,Closed) |
+            (Closed,Open))
+    }
+
+    /// Who may change a question from self to next, assuming [Self::can_change_to].
+    pub fn who_may_change_to(self,next:QuestionLifecycle) -> &'static [LifecycleRole] {
+        use QuestionLifecycle::*;
+        use LifecycleRole::*;
+        match (self,next) {
+            (_,CommittedToAsk) => &[AskingMP],
+            (CommittedToAsk,Open) => &[Author,AskingMP],
+            (_,Asked) => &[Author,AskingMP],
+            (_,Answered) => &[Author,AskingMP,AnsweringMP],
+            _ => &[Author],
+        }
+    }
+
+    /// The status a question moves to when an edit adds an answer and/or a Hansard link, or None if it stays the same.
+    pub fn advanced_by(self,added_answer:bool,added_hansard_link:bool) -> Option<QuestionLifecycle> {
+        use QuestionLifecycle::*;
+        let next = if added_answer { Answered } else if added_hansard_link { Asked } else { return None };
+        if self.can_change_to(next) { Some(next) } else { None }
+    }
+}
+
+/* Boilerplate to make it easy to transfer QuestionLifecycle to SQL */
+
+impl fmt::Display for QuestionLifecycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<QuestionLifecycle> for Value {
+    fn from(s: QuestionLifecycle) -> Self {
+        Value::Bytes(s.to_string().into_bytes())
+    }
+}
+
+impl TryFrom<&str> for QuestionLifecycle {
+    type Error = anyhow::Error;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.as_bytes() {
+            b"Open" => Ok(QuestionLifecycle::Open),
+            b"CommittedToAsk" => Ok(QuestionLifecycle::CommittedToAsk),
+            b"Asked" => Ok(QuestionLifecycle::Asked),
+            b"Answered" => Ok(QuestionLifecycle::Answered),
+            b"Closed" => Ok(QuestionLifecycle::Closed),
+            _ => Err(anyhow!("Invalid lifecycle {}",value)),
+        }
+    }
+}
+
+impl ConvIr<QuestionLifecycle> for QuestionLifecycle {
+    fn new(v: Value) -> Result<Self, FromValueError> {
+        match v {
+            Value::Bytes(bytes) => match bytes.as_slice() {
+                b"Open" => Ok(QuestionLifecycle::Open),
+                b"CommittedToAsk" => Ok(QuestionLifecycle::CommittedToAsk),
+                b"Asked" => Ok(QuestionLifecycle::Asked),
+                b"Answered" => Ok(QuestionLifecycle::Answered),
+                b"Closed" => Ok(QuestionLifecycle::Closed),
+                _ => Err(FromValueError(Value::Bytes(bytes))),
+            },
+            v => Err(FromValueError(v)),
+        }
+    }
+
+    fn commit(self) -> Self { self }
+    fn rollback(self) -> Value { self.into() }
+}
+
+impl FromValue for QuestionLifecycle {
+    type Intermediate = Self;
+}
+
+/// Does the user have an MP badge for one of the MPs in the list?
+fn has_badge_for_any_mp(conn:&mut impl Queryable,uid:&UserUID,people:&[PersonID]) -> Result<bool,QuestionError> {
+    for person in people {
+        if let PersonID::MP(mp) = person {
+            match check_user_has_badge_for_mp(conn,uid,mp) {
+                Ok(()) => return Ok(true),
+                Err(QuestionError::UserDoesNotHaveCorrectMPBadge) | Err(QuestionError::InvalidMP) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Change the lifecycle status of a question.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct SetLifecycleCommand {
+    pub question_id : QuestionID,
+    /// The hash value defining the last update done to the question. This is checked to prevent clashes with edits.
+    pub version : LastQuestionUpdate,
+    pub lifecycle : QuestionLifecycle,
+}
+
+/// The structure posted to the bulletin board in response to a SetLifecycleCommand.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct SetLifecycleCommandPostedToBulletinBoard {
+    pub command : ClientSigned<SetLifecycleCommand>,
+    /// The status before the change.
+    pub from : QuestionLifecycle,
+    pub timestamp : Timestamp,
+    /// This will be a link to the prior node in the database.
+    pub prior : LastQuestionUpdate,
+}
+
+impl SetLifecycleCommand {
+    /// Change the status, returning the new version of the question.
+    pub async fn set_lifecycle(command:&ClientSigned<SetLifecycleCommand>) -> Result<LastQuestionUpdate,QuestionError> {
+        let question_info = QuestionInfo::lookup(command.parsed.question_id).await?.ok_or(QuestionError::QuestionDoesNotExist)?;
+        if question_info.version!=command.parsed.version { return Err(QuestionError::LastUpdateIsNotCurrent); }
+        if question_info.withdrawn.is_some() { return Err(QuestionError::QuestionWithdrawn); }
+        let from = question_info.lifecycle;
+        let to = command.parsed.lifecycle;
+        if !from.can_change_to(to) { return Err(QuestionError::InvalidLifecycleTransition); }
+        if to==QuestionLifecycle::Answered && question_info.non_defining.answers.is_empty() && question_info.non_defining.hansard_link.is_empty() { return Err(QuestionError::CantMarkAnsweredWithoutAnswer); }
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let user = &command.signed_message.user;
+        let mut allowed = false;
+        for role in from.who_may_change_to(to) {
+            allowed = match role {
+                LifecycleRole::Author => question_info.defining.author==*user,
+                LifecycleRole::AskingMP => has_badge_for_any_mp(&mut conn,user,&question_info.non_defining.mp_who_should_ask_the_question)?,
+                LifecycleRole::AnsweringMP => has_badge_for_any_mp(&mut conn,user,&question_info.non_defining.entity_who_should_answer_the_question)?,
+            };
+            if allowed { break; }
+        }
+        if !allowed { return Err(QuestionError::NotAllowedToChangeLifecycle); }
+        let user_id = get_user_id(user,QuestionError::NoSuchUser,QuestionError::InternalError,&mut conn)?;
+        let timestamp = timestamp_now().map_err(internal_error)?;
+        let for_bb = SetLifecycleCommandPostedToBulletinBoard {
+            command: command.clone(),
+            from,
+            timestamp,
+            prior : command.parsed.version,
+        };
+        let version = LogInBulletinBoard::SetLifecycle(for_bb).log_in_bulletin_board().await.map_err(bulletin_board_error)?;
+        let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error)?;
+        modify_question_database_version_and_time(&mut transaction,command.parsed.question_id,version,Some(command.parsed.version),timestamp).await?;
+        transaction.exec_drop("update QUESTIONS set Lifecycle=? where QuestionID=?", (to,command.parsed.question_id.0)).map_err(internal_error)?;
+        transaction.exec_drop("insert into LifecycleTransitions (QuestionId,FromStatus,ToStatus,UserId,timestamp,BulletinBoardHash) values (?,?,?,?,?,?)", (command.parsed.question_id.0,from,to,user_id,timestamp,version.0)).map_err(internal_error)?;
+        transaction.commit().map_err(internal_error)?;
+        Ok(version)
+    }
+}
+
+/// Advance the lifecycle of a question being edited by `uid` (see [QuestionLifecycle::advanced_by]), as part of the
+/// edit's transaction. `version` is the edit's bulletin board entry.
+pub(crate) fn advance_lifecycle_for_edit(transaction:&mut impl Queryable,question_id:QuestionID,added_answer:bool,added_hansard_link:bool,uid:&UserUID,timestamp:Timestamp,version:LastQuestionUpdate) -> Result<(),QuestionError> {
+    let from : QuestionLifecycle = transaction.exec_first("select Lifecycle from QUESTIONS where QuestionID=? for update",(question_id.0,)).map_err(internal_error)?.ok_or(QuestionError::QuestionDoesNotExist)?;
+    if let Some(to) = from.advanced_by(added_answer,added_hansard_link) {
+        let user_id = get_user_id(uid,QuestionError::NoSuchUser,QuestionError::InternalError,transaction)?;
+        transaction.exec_drop("update QUESTIONS set Lifecycle=? where QuestionID=?", (to,question_id.0)).map_err(internal_error)?;
+        transaction.exec_drop("insert into LifecycleTransitions (QuestionId,FromStatus,ToStatus,UserId,timestamp,BulletinBoardHash) values (?,?,?,?,?,?)", (question_id.0,from,to,user_id,timestamp,version.0)).map_err(internal_error)?;
+    }
+    Ok(())
+}
+
+/// A change to the lifecycle of a question, as recorded in the LifecycleTransitions table.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct LifecycleTransition {
+    pub from : QuestionLifecycle,
+    pub to : QuestionLifecycle,
+    /// The user who made the change.
+    pub user : UserUID,
+    pub timestamp : Timestamp,
+    /// The bulletin board entry for the change.
+    pub version : LastQuestionUpdate,
+}
+
+/// Get the changes to the lifecycle of a question, oldest first.
+pub async fn get_lifecycle_transitions(question_id:QuestionID) -> mysql::Result<Vec<LifecycleTransition>> {
+    let mut conn = get_rta_database_connection().await?;
+    conn.exec_map("select FromStatus,ToStatus,USERS.UID,timestamp,BulletinBoardHash from LifecycleTransitions inner join USERS on LifecycleTransitions.UserId=USERS.id where QuestionId=? order by timestamp",(question_id.0,),|(from,to,user,timestamp,version)|LifecycleTransition{from,to,user,timestamp,version:hash_from_value(version)})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use QuestionLifecycle::*;
+
+    #[test]
+    fn test_transitions() {
+        assert!(Open.can_change_to(CommittedToAsk));
+        assert!(CommittedToAsk.can_change_to(Asked));
+        assert!(Asked.can_change_to(Answered));
+        assert!(Answered.can_change_to(Closed));
+        assert!(Closed.can_change_to(Open));
+        assert!(!Answered.can_change_to(Open));
+        assert!(!Asked.can_change_to(CommittedToAsk));
+        assert!(!Open.can_change_to(Open));
+        assert_eq!(Open.who_may_change_to(CommittedToAsk),&[LifecycleRole::AskingMP]);
+        assert_eq!(Answered.who_may_change_to(Closed),&[LifecycleRole::Author]);
+    }
+
+    #[test]
+    fn answers_and_hansard_links_advance_lifecycle() {
+        assert_eq!(Open.advanced_by(true,false),Some(Answered));
+        assert_eq!(CommittedToAsk.advanced_by(true,true),Some(Answered));
+        assert_eq!(Asked.advanced_by(true,false),Some(Answered));
+        assert_eq!(Open.advanced_by(false,true),Some(Asked));
+        assert_eq!(CommittedToAsk.advanced_by(false,true),Some(Asked));
+        // never goes backwards, or reopens a closed question.
+        assert_eq!(Asked.advanced_by(false,true),None);
+        assert_eq!(Answered.advanced_by(false,true),None);
+        assert_eq!(Answered.advanced_by(true,false),None);
+        assert_eq!(Closed.advanced_by(true,true),None);
+        assert_eq!(Open.advanced_by(false,false),None);
+    }
+}
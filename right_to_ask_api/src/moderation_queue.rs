@@ -0,0 +1,214 @@
+//! A queue of reported questions for moderators to review.
+//!
+//! Questions with outstanding flags are ordered by a priority score (see [priority]) combining the number of
+//! flags, the severity of the most serious reason given (see [CensorshipReason::severity]) and how long the question
+//! has been waiting.
+//!
+//! A moderator claims a question before reviewing it, so two moderators don't review the same reports and both
+//! subtract them from the flag count. Only questions with outstanding flags may be claimed. A claim lasts for
+//! `moderation_claim_seconds` in config.toml, and can be renewed by claiming again, or released. It is released
+//! automatically when the moderator censors or allows the question; a [crate::censorship::CensorQuestionCommand] is
+//! rejected unless the moderator holds a current claim on the question.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use merkle_tree_bulletin_board::hash_history::{Timestamp, timestamp_now};
+use mysql::prelude::Queryable;
+use mysql::TxOpts;
+use serde::{Serialize, Deserialize};
+use crate::censorship::{CensorshipReason, CensorshipStatus, SingleReasonSummary};
+use crate::config::CONFIG;
+use crate::database::get_rta_database_connection;
+use crate::question::{hash_from_value, internal_error, LastQuestionUpdate, QuestionError, QuestionID};
+
+/// How many seconds of waiting count as much as one flag of severity 1.
+const SECONDS_PER_PRIORITY_POINT : Timestamp = 60*60;
+
+/// The priority of a question in the queue. Each flag counts as much as the severity of the most serious reason
+/// given, and every hour waiting adds one.
+pub fn priority(num_flags:usize,max_severity:u32,waiting_seconds:Timestamp) -> u64 {
+    num_flags as u64*max_severity as u64+waiting_seconds/SECONDS_PER_PRIORITY_POINT
+}
+
+/// A question waiting for a moderator.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct ModerationQueueItem {
+    pub question_id : QuestionID,
+    pub question_text : String,
+    /// The current version, needed for a [crate::censorship::CensorQuestionCommand].
+    pub version : LastQuestionUpdate,
+    /// The number of flags since the last moderation, to be given in a [crate::censorship::CensorQuestionCommand].
+    pub num_flags : usize,
+    pub censorship_status : CensorshipStatus,
+    /// When the question was first flagged since the last moderation, if known.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub flagged : Option<Timestamp>,
+    /// The reasons given since the last moderation.
+    pub reasons : Vec<SingleReasonSummary>,
+    /// Higher is more urgent. See [priority].
+    pub priority : u64,
+    /// The moderator who has claimed this question, if any.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub claimed_by : Option<String>,
+    /// When the claim expires.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub claim_expires : Option<Timestamp>,
+}
+
+/// A request for the queue.
+#[derive(Serialize,Deserialize,Debug,Clone,Default)]
+pub struct ModerationQueueQuery {
+    /// If true, include questions claimed by other moderators. Questions claimed by the requester are always included.
+    #[serde(default)]
+    pub include_claimed : bool,
+}
+
+/// The columns of a question as selected by [ModerationQueueQuery::get_queue].
+type QueueRow = (mysql::Value,String,mysql::Value,usize,CensorshipStatus,Option<Timestamp>,Option<String>,Option<Timestamp>);
+
+impl ModerationQueueQuery {
+    /// Get the queue as seen by `moderator`, most urgent first.
+    pub async fn get_queue(&self,moderator:&str) -> Result<Vec<ModerationQueueItem>,QuestionError> {
+        let now = timestamp_now().map_err(internal_error)?;
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let rows : Vec<QueueRow> = conn.exec("select q.QuestionId,q.Question,q.Version,q.NumFlags,q.CensorshipStatus,q.FlaggedTimestamp,c.Moderator,c.ExpiresTimestamp from QUESTIONS q left join ModerationClaims c on c.QuestionId=q.QuestionId and c.ExpiresTimestamp>? where q.NumFlags>0 and q.CensorshipStatus!='Censored'",(now,)).map_err(internal_error)?;
+        // Reasons given since the question was flagged. Reports from before timestamps were recorded are all included.
+        let recent = "(r.timestamp is NULL or q.FlaggedTimestamp is NULL or r.timestamp>=q.FlaggedTimestamp)";
+        let mut reasons : HashMap<QuestionID,Vec<SingleReasonSummary>> = HashMap::new();
+        for (question,reason,count) in conn.exec::<(mysql::Value,CensorshipReason,usize),_,_>(format!("select r.QuestionId,r.reason,COUNT(r.user_id) from QuestionReportedReasons r inner join QUESTIONS q on r.QuestionId=q.QuestionId where q.NumFlags>0 and {recent} group by r.QuestionId,r.reason"),()).map_err(internal_error)? {
+            reasons.entry(hash_from_value(question)).or_default().push(SingleReasonSummary{reason,count,answer:None});
+        }
+        for (question,reason,answer,count) in conn.exec::<(mysql::Value,CensorshipReason,mysql::Value,usize),_,_>(format!("select r.QuestionId,r.reason,r.answer,COUNT(r.user_id) from AnswerReportedReasons r inner join QUESTIONS q on r.QuestionId=q.QuestionId where q.NumFlags>0 and {recent} group by r.QuestionId,r.reason,r.answer"),()).map_err(internal_error)? {
+            reasons.entry(hash_from_value(question)).or_default().push(SingleReasonSummary{reason,count,answer:Some(hash_from_value(answer))});
+        }
+        let mut queue : Vec<ModerationQueueItem> = vec![];
+        for (question_id,question_text,version,num_flags,censorship_status,flagged,claimed_by,claim_expires) in rows {
+            if !self.include_claimed && claimed_by.as_ref().is_some_and(|c|c!=moderator) { continue; }
+            let question_id = hash_from_value(question_id);
+            let reasons = reasons.remove(&question_id).unwrap_or_default();
+            let max_severity = reasons.iter().map(|r|r.reason.severity()).max().unwrap_or(1);
+            queue.push(ModerationQueueItem{
+                question_id,
+                question_text,
+                version: hash_from_value(version),
+                num_flags,
+                censorship_status,
+                flagged,
+                priority: priority(num_flags,max_severity,flagged.map(|f|now.saturating_sub(f)).unwrap_or(0)),
+                reasons,
+                claimed_by,
+                claim_expires,
+            });
+        }
+        queue.sort_by(queue_order);
+        Ok(queue)
+    }
+}
+
+/// The order of the queue: highest priority first, then longest flagged. Questions with no flagged time (flagged
+/// before these were recorded) come after others of the same priority.
+fn queue_order(a:&ModerationQueueItem,b:&ModerationQueueItem) -> Ordering {
+    b.priority.cmp(&a.priority)
+        .then(a.flagged.is_none().cmp(&b.flagged.is_none()))
+        .then(a.flagged.cmp(&b.flagged))
+}
+
+/// Check that no other moderator has a current claim on the question.
+pub(crate) fn check_not_claimed_by_another_moderator(conn:&mut impl Queryable,question_id:QuestionID,moderator:&str,now:Timestamp) -> Result<(),QuestionError> {
+    let claimed_by : Option<String> = conn.exec_first("select Moderator from ModerationClaims where QuestionId=? and ExpiresTimestamp>?",(question_id.0,now)).map_err(internal_error)?;
+    match claimed_by {
+        Some(claimed_by) if claimed_by!=moderator => Err(QuestionError::ClaimedByAnotherModerator),
+        _ => Ok(()),
+    }
+}
+
+/// Check that `moderator` holds a current claim on the question, as needed to censor or allow it.
+pub(crate) fn check_claimed_by(conn:&mut impl Queryable,question_id:QuestionID,moderator:&str,now:Timestamp) -> Result<(),QuestionError> {
+    let claimed_by : Option<String> = conn.exec_first("select Moderator from ModerationClaims where QuestionId=? and ExpiresTimestamp>?",(question_id.0,now)).map_err(internal_error)?;
+    check_claim_holder(claimed_by.as_deref(),moderator)
+}
+
+/// Check that `moderator` is `claimed_by`, the holder of the current claim on a question, if any.
+fn check_claim_holder(claimed_by:Option<&str>,moderator:&str) -> Result<(),QuestionError> {
+    match claimed_by {
+        None => Err(QuestionError::NotClaimed),
+        Some(claimed_by) if claimed_by==moderator => Ok(()),
+        Some(_) => Err(QuestionError::ClaimedByAnotherModerator),
+    }
+}
+
+/// Check that a question with `num_flags` outstanding flags (None if it does not exist or is censored) may be claimed.
+fn check_claimable(num_flags:Option<usize>) -> Result<(),QuestionError> {
+    match num_flags {
+        None => Err(QuestionError::QuestionDoesNotExist),
+        Some(0) => Err(QuestionError::NotFlagged),
+        Some(_) => Ok(()),
+    }
+}
+
+/// A moderator's claim on a question in the queue.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct ModerationClaim {
+    pub question_id : QuestionID,
+    pub moderator : String,
+    /// When the claim lapses unless renewed.
+    pub expires : Timestamp,
+}
+
+/// Claim a question, or renew a claim. Fails if another moderator has a current claim, or the question has no outstanding flags.
+pub async fn claim_question(question_id:QuestionID,moderator:&str) -> Result<ModerationClaim,QuestionError> {
+    let now = timestamp_now().map_err(internal_error)?;
+    let expires = now+CONFIG.moderation_claim_seconds;
+    let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+    let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error)?;
+    let flagged : Option<usize> = transaction.exec_first("select NumFlags from QUESTIONS where QuestionId=? and CensorshipStatus!='Censored' for update",(question_id.0,)).map_err(internal_error)?;
+    check_claimable(flagged)?;
+    check_not_claimed_by_another_moderator(&mut transaction,question_id,moderator,now)?;
+    transaction.exec_drop("replace into ModerationClaims (QuestionId,Moderator,ClaimedTimestamp,ExpiresTimestamp) values (?,?,?,?)",(question_id.0,moderator,now,expires)).map_err(internal_error)?;
+    transaction.commit().map_err(internal_error)?;
+    Ok(ModerationClaim{ question_id, moderator: moderator.to_string(), expires })
+}
+
+/// Give up a claim on a question, so another moderator may take it.
+pub async fn release_question(question_id:QuestionID,moderator:&str) -> Result<(),QuestionError> {
+    let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+    conn.exec_drop("delete from ModerationClaims where QuestionId=? and Moderator=?",(question_id.0,moderator)).map_err(internal_error)?;
+    if conn.affected_rows()==0 { Err(QuestionError::NotClaimed) } else { Ok(()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use merkle_tree_bulletin_board::hash::HashValue;
+
+    #[test]
+    fn test_priority() {
+        assert_eq!(priority(1,10,0),10);
+        assert_eq!(priority(3,2,0),6);
+        assert_eq!(priority(1,1,5*60*60),6);
+        assert!(priority(1,CensorshipReason::ThreateningViolence.severity(),0)>priority(3,CensorshipReason::Spam.severity(),0));
+    }
+
+    #[test]
+    fn unflagged_questions_cannot_be_claimed() {
+        assert_eq!(check_claimable(None),Err(QuestionError::QuestionDoesNotExist));
+        assert_eq!(check_claimable(Some(0)),Err(QuestionError::NotFlagged));
+        assert_eq!(check_claimable(Some(2)),Ok(()));
+    }
+
+    #[test]
+    fn moderation_needs_the_claim() {
+        assert_eq!(check_claim_holder(Some("alice"),"alice"),Ok(()));
+        assert_eq!(check_claim_holder(Some("bob"),"alice"),Err(QuestionError::ClaimedByAnotherModerator));
+        assert_eq!(check_claim_holder(None,"alice"),Err(QuestionError::NotClaimed));
+    }
+
+    #[test]
+    fn queue_puts_unknown_flagged_time_last() {
+        let item = |n:u8,priority:u64,flagged:Option<Timestamp>| ModerationQueueItem{ question_id: HashValue([n;32]), question_text: String::new(), version: HashValue([0;32]), num_flags: 1, censorship_status: CensorshipStatus::Flagged, flagged, reasons: vec![], priority, claimed_by: None, claim_expires: None };
+        let mut queue = [item(1,5,None),item(2,5,Some(200)),item(3,9,None),item(4,5,Some(100)),item(5,1,Some(10))];
+        queue.sort_by(queue_order);
+        let order : Vec<u8> = queue.iter().map(|i|i.question_id.0[0]).collect();
+        assert_eq!(order,vec![3,4,2,1,5]);
+    }
+}
@@ -0,0 +1,193 @@
+//! A view for MPs and their staff of the questions directed at them.
+//!
+//! The signer must have an MP or MPStaff badge for the MP. The dashboard lists the questions (not withdrawn or
+//! censored) in which the MP, any ministry they hold, or any of the given committees is asked to ask or answer the
+//! question. Questions are ranked by the net votes from users in the MP's electorate. Anonymous votes
+//! (see [crate::anonymous_voting]) are not attributable to an electorate, so only count towards the overall totals.
+//!
+//! The MP lists do not record committee membership, so the committees are taken from the request.
+
+use std::cmp::Ordering;
+use mysql::prelude::Queryable;
+use mysql::Value;
+use serde::{Serialize, Deserialize};
+use crate::committee::CommitteeId;
+use crate::database::get_rta_database_connection;
+use crate::minister::MinisterId;
+use crate::mp::{MPId, MPSpec};
+use crate::person::UserUID;
+use crate::question::{check_user_has_badge_for_mp, hash_from_value, internal_error, QuestionError, QuestionID};
+use crate::regions::{Electorate, Jurisdiction};
+
+/// The maximum number of questions returned in a dashboard.
+const MAX_DASHBOARD_QUESTIONS : usize = 500;
+
+/// A request for an MP's dashboard.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct MPDashboardQuery {
+    pub mp : MPId,
+    /// Committees the MP is on.
+    #[serde(skip_serializing_if = "Vec::is_empty",default)]
+    pub committees : Vec<CommitteeId>,
+    /// If true, only list questions with no (uncensored) answer.
+    #[serde(default)]
+    pub unanswered_only : bool,
+}
+
+/// A question directed at the MP.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct MPDashboardQuestion {
+    pub question_id : QuestionID,
+    /// The MP is in [crate::question::QuestionNonDefiningFields::mp_who_should_ask_the_question].
+    pub asked_to_ask : bool,
+    /// The MP, one of their ministries, or one of the committees is in [crate::question::QuestionNonDefiningFields::entity_who_should_answer_the_question].
+    pub asked_to_answer : bool,
+    /// The question has at least one uncensored answer.
+    pub answered : bool,
+    /// The number of users in the MP's electorate who have voted on the question.
+    pub electorate_total_votes : u32,
+    /// Up votes minus down votes from users in the MP's electorate.
+    pub electorate_net_votes : i32,
+    pub total_votes : u32,
+    pub net_votes : i32,
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct MPDashboard {
+    /// The ministries the MP holds that questions have been directed to.
+    #[serde(skip_serializing_if = "Vec::is_empty",default)]
+    pub ministries : Vec<MinisterId>,
+    /// The questions, most net votes from the MP's electorate first.
+    pub questions : Vec<MPDashboardQuestion>,
+}
+
+impl MPDashboardQuestion {
+    /// The dashboard order: most net votes from the electorate first, then most votes from the electorate, then
+    /// most net votes overall.
+    fn ranking(&self,other:&Self) -> Ordering {
+        other.electorate_net_votes.cmp(&self.electorate_net_votes)
+            .then(other.electorate_total_votes.cmp(&self.electorate_total_votes))
+            .then(other.net_votes.cmp(&self.net_votes))
+    }
+}
+
+/// The query for a dashboard, and its parameters. `who` is the conditions on PersonForQuestion p for the question to
+/// be directed to the MP, each with the parameter for its placeholder. The query orders the same way as
+/// [MPDashboardQuestion::ranking], so the limit keeps the top questions.
+fn dashboard_query(who:&[(&str,Value)],electorate:&Electorate,unanswered_only:bool) -> (String,Vec<Value>) {
+    let who_params : Vec<Value> = who.iter().map(|(_,v)|v.clone()).collect();
+    let who = who.iter().map(|(c,_)|*c).collect::<Vec<_>>().join(" or ");
+    let voters = "from HAS_VOTED v inner join UserElectorate ue on ue.user_id=v.VoterId inner join ElectorateDefinition e on e.id=ue.electorate_id where v.QuestionId=q.QuestionId and e.Chamber=? and COALESCE(e.Electorate,'')=?";
+    let answered = "exists (select * from Answer a where a.QuestionId=q.QuestionId and a.CensorshipStatus!='Censored')";
+    let query = format!("select q.QuestionId,\
+        exists (select * from PersonForQuestion p where p.QuestionId=q.QuestionId and p.ROLE='Q' and ({who})),\
+        exists (select * from PersonForQuestion p where p.QuestionId=q.QuestionId and p.ROLE='A' and ({who})),\
+        {answered},\
+        (select count(distinct v.VoterId) {voters}) as ElectorateTotalVotes,\
+        (select CAST(COALESCE(SUM(IF(v.Up,1,-1)),0) AS SIGNED) {voters} and v.Up is not NULL) as ElectorateNetVotes,\
+        q.TotalVotes,q.NetVotes \
+        from QUESTIONS q where exists (select * from PersonForQuestion p where p.QuestionId=q.QuestionId and ({who})) \
+        and q.CensorshipStatus!='Censored' and q.WithdrawnTimestamp is NULL {unanswered} \
+        order by ElectorateNetVotes DESC, ElectorateTotalVotes DESC, q.NetVotes DESC limit {limit}",
+        unanswered = if unanswered_only { format!("and not {answered}") } else { String::new() },
+        limit = MAX_DASHBOARD_QUESTIONS);
+    let electorate_params : Vec<Value> = vec![electorate.chamber.into(),electorate.region.clone().unwrap_or_default().into()];
+    let params : Vec<Value> = [&who_params,&who_params,&electorate_params,&electorate_params,&who_params].into_iter().flatten().cloned().collect();
+    (query,params)
+}
+
+/// The columns of a row as selected by [MPDashboardQuery::get_dashboard].
+type DashboardRow = (Value,bool,bool,bool,u32,i32,u32,i32);
+
+impl MPDashboardQuery {
+    /// Get the dashboard for the MP, checking that `uid` has an MP or MPStaff badge for them.
+    pub async fn get_dashboard(&self,uid:&UserUID) -> Result<MPDashboard,QuestionError> {
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        check_user_has_badge_for_mp(&mut conn,uid,&self.mp)?;
+        let mps = MPSpec::get().map_err(internal_error)?;
+        let mp = mps.find(&self.mp).ok_or(QuestionError::InvalidMP)?;
+        let mut ministries : Vec<(usize,MinisterId)> = vec![];
+        for (id,jurisdiction,name) in conn.exec::<(usize,Jurisdiction,String),_,_>("select id,Jurisdiction,Name from Minister_IDs",()).map_err(internal_error)? {
+            let minister = MinisterId{ jurisdiction, name };
+            if mp.is_in_role(&minister) { ministries.push((id,minister)); }
+        }
+        // Who the question may be directed to, as a condition on PersonForQuestion p.
+        let mut who : Vec<(&str,Value)> = vec![("p.MP=?",self.mp.get_id_from_database_if_there(&mut conn).map_err(internal_error)?.into())];
+        for (id,_) in &ministries {
+            who.push(("p.Minister=?",(*id).into()));
+        }
+        for committee in &self.committees {
+            if let Some(id) = committee.get_id_from_database_if_there(&mut conn).map_err(internal_error)? {
+                who.push(("p.Committee=?",id.into()));
+            }
+        }
+        let (query,params) = dashboard_query(&who,&self.mp.electorate,self.unanswered_only);
+        let rows : Vec<DashboardRow> = conn.exec(query,params).map_err(internal_error)?;
+        let mut questions : Vec<MPDashboardQuestion> = rows.into_iter().map(|(question_id,asked_to_ask,asked_to_answer,answered,electorate_total_votes,electorate_net_votes,total_votes,net_votes)|MPDashboardQuestion{
+            question_id: hash_from_value(question_id),
+            asked_to_ask,
+            asked_to_answer,
+            answered,
+            electorate_total_votes,
+            electorate_net_votes,
+            total_votes,
+            net_votes,
+        }).collect();
+        questions.sort_by(MPDashboardQuestion::ranking);
+        Ok(MPDashboard{ ministries: ministries.into_iter().map(|(_,m)|m).collect(), questions })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use merkle_tree_bulletin_board::hash::HashValue;
+    use crate::mp::MP;
+    use crate::question::check_mp_badges;
+    use crate::regions::Chamber;
+
+    fn question(electorate_net_votes:i32,electorate_total_votes:u32,net_votes:i32) -> MPDashboardQuestion {
+        MPDashboardQuestion{ question_id: HashValue([0;32]), asked_to_ask: true, asked_to_answer: false, answered: false, electorate_total_votes, electorate_net_votes, total_votes: 0, net_votes }
+    }
+
+    #[test]
+    fn ranked_by_electorate_votes() {
+        let mut questions = [question(1,5,100),question(3,3,0),question(1,7,0),question(1,5,200),question(-2,2,500)];
+        questions.sort_by(MPDashboardQuestion::ranking);
+        let order : Vec<(i32,u32,i32)> = questions.iter().map(|q|(q.electorate_net_votes,q.electorate_total_votes,q.net_votes)).collect();
+        assert_eq!(order,vec![(3,3,0),(1,7,0),(1,5,200),(1,5,100),(-2,2,500)]);
+    }
+
+    #[test]
+    fn query_parameters_match_placeholders() {
+        let electorate = Electorate{ chamber: Chamber::Australian_House_Of_Representatives, region: Some("Bean".to_string()) };
+        let who : Vec<(&str,Value)> = vec![("p.MP=?",Value::from(1)),("p.Minister=?",Value::from(2)),("p.Committee=?",Value::from(3))];
+        for unanswered_only in [false,true] {
+            let (query,params) = dashboard_query(&who,&electorate,unanswered_only);
+            let segments : Vec<&str> = query.split('?').collect();
+            assert_eq!(segments.len()-1,params.len());
+            for (before,param) in segments.iter().zip(params.iter()) {
+                let expected = if before.ends_with("p.MP=") { Value::from(1) }
+                    else if before.ends_with("p.Minister=") { Value::from(2) }
+                    else if before.ends_with("p.Committee=") { Value::from(3) }
+                    else if before.ends_with("e.Chamber=") { Value::from(Chamber::Australian_House_Of_Representatives) }
+                    else if before.ends_with("COALESCE(e.Electorate,'')=") { Value::from("Bean") }
+                    else { panic!("Unexpected placeholder after {}",before) };
+                assert_eq!(*param,expected,"placeholder after {}",before);
+            }
+        }
+    }
+
+    #[test]
+    fn dashboard_needs_a_badge_for_the_mp() {
+        let electorate = Electorate{ chamber: Chamber::Australian_House_Of_Representatives, region: Some("Bean".to_string()) };
+        let mp = MP{ first_name: "Jane".to_string(), surname: "Citizen".to_string(), electorate: electorate.clone(), email: "Jane.Citizen.MP@aph.gov.au".to_string(), role: String::new(), party: String::new(), non_authoritative: None };
+        let mps = MPSpec{ mps: vec![mp.clone()], federal_electorates_by_state: vec![], vic_districts: vec![] };
+        let id = MPId{ first_name: mp.first_name.clone(), surname: mp.surname.clone(), electorate: electorate.clone() };
+        assert_eq!(check_mp_badges(&mps,&id,&[mp.badge_name()]),Ok(()));
+        assert_eq!(check_mp_badges(&mps,&id,&[]),Err(QuestionError::UserDoesNotHaveCorrectMPBadge));
+        assert_eq!(check_mp_badges(&mps,&id,&["John Smith @aph.gov.au".to_string()]),Err(QuestionError::UserDoesNotHaveCorrectMPBadge));
+        let unknown = MPId{ first_name: "John".to_string(), ..id };
+        assert_eq!(check_mp_badges(&mps,&unknown,&[mp.badge_name()]),Err(QuestionError::InvalidMP));
+    }
+}
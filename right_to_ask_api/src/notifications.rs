@@ -0,0 +1,522 @@
+//! Telling users about things that happen to questions they are interested in, so they don't have to poll.
+//!
+//! Users subscribe to a question, an MP, an electorate or a topic tag, choosing a [NotificationSink] for delivery.
+//!
+//! When something happens to a question (see [NotificationEvent]), an entry is added to the NotificationOutbox table
+//! in the same transaction as the change itself, so events are not lost if the server stops. [process_notifications],
+//! run periodically by the server, then
+//! * works out who is subscribed to each new outbox entry, adding a row for each user and sink to NotificationDeliveries,
+//! * delivers pending email and webhook notifications, retrying failures up to `max_delivery_attempts` times, waiting
+//!   twice as long after each failure (see [retry_delay]).
+//!
+//! Webhooks may only be delivered to public addresses (see [is_public_address]), checked both when subscribing and
+//! when delivering, so they can't be used to reach services on the server's own network. Notifications that a question
+//! was censored never include its text.
+//!
+//! Pull notifications stay pending until the user fetches and acknowledges them with a [GetNotificationsCommand].
+
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use anyhow::anyhow;
+use merkle_tree_bulletin_board::hash::HashValue;
+use merkle_tree_bulletin_board::hash_history::{Timestamp, timestamp_now};
+use mysql::prelude::Queryable;
+use mysql::TxOpts;
+use mysql_common::value::convert::{ConvIr, FromValue, FromValueError};
+use mysql_common::value::Value;
+use reqwest::Url;
+use reqwest::redirect::Policy;
+use url::Host;
+use serde::{Serialize, Deserialize};
+use crate::censorship::CensorshipStatus;
+use crate::config::CONFIG;
+use crate::database::get_rta_database_connection;
+use crate::mp::{MPId, MPSpec};
+use crate::person::{get_user_id, EmailAddress, UserID, UserUID};
+use crate::question::{hash_from_value, internal_error, QuestionError, QuestionID};
+use crate::regions::{Chamber, Electorate};
+use crate::signing::ServerSigned;
+use crate::topic_tags::{canonicalise_topic_tag, TopicTag};
+
+/// The maximum number of subscriptions a user may have.
+pub const MAX_SUBSCRIPTIONS_PER_USER : usize = 100;
+/// The maximum number of notifications returned by [GetNotificationsCommand], or handled at once by [process_notifications].
+const NOTIFICATION_BATCH_SIZE : usize = 100;
+
+/// Something that happened to a question that subscribers may want to know about.
+#[derive(Debug,Copy,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub enum NotificationEvent {
+    /// An answer was added. The related hash is the version of the answer.
+    AnswerAdded,
+    /// A Hansard link was added. The url is the link.
+    HansardLinkAdded,
+    /// A moderator censored the question.
+    QuestionCensored,
+    /// A follow up question was posted. The related hash is the id of the follow up question.
+    FollowUpPosted,
+}
+
+impl NotificationEvent {
+    fn description(self) -> &'static str {
+        match self {
+            NotificationEvent::AnswerAdded => "An answer has been added.",
+            NotificationEvent::HansardLinkAdded => "It has been linked to a record of parliamentary proceedings.",
+            NotificationEvent::QuestionCensored => "It has been removed by a moderator.",
+            NotificationEvent::FollowUpPosted => "A follow up question has been posted.",
+        }
+    }
+}
+
+/// How a notification is delivered.
+#[derive(Debug,Copy,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub enum NotificationSink {
+    /// Emailed to the user's verified email address.
+    Email,
+    /// POSTed as JSON, signed by the server, to the subscription's webhook_url.
+    Webhook,
+    /// Kept until fetched with a [GetNotificationsCommand].
+    Pull,
+}
+
+/* Boilerplate to make it easy to transfer NotificationEvent and NotificationSink to SQL */
+
+impl fmt::Display for NotificationEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<NotificationEvent> for Value {
+    fn from(s: NotificationEvent) -> Self {
+        Value::Bytes(s.to_string().into_bytes())
+    }
+}
+
+impl TryFrom<&str> for NotificationEvent {
+    type Error = anyhow::Error;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.as_bytes() {
+            b"AnswerAdded" => Ok(NotificationEvent::AnswerAdded),
+            b"HansardLinkAdded" => Ok(NotificationEvent::HansardLinkAdded),
+            b"QuestionCensored" => Ok(NotificationEvent::QuestionCensored),
+            b"FollowUpPosted" => Ok(NotificationEvent::FollowUpPosted),
+            _ => Err(anyhow!("Invalid notification event {}",value)),
+        }
+    }
+}
+
+impl ConvIr<NotificationEvent> for NotificationEvent {
+    fn new(v: Value) -> Result<Self, FromValueError> {
+        match v {
+            Value::Bytes(bytes) => match bytes.as_slice() {
+                b"AnswerAdded" => Ok(NotificationEvent::AnswerAdded),
+                b"HansardLinkAdded" => Ok(NotificationEvent::HansardLinkAdded),
+                b"QuestionCensored" => Ok(NotificationEvent::QuestionCensored),
+                b"FollowUpPosted" => Ok(NotificationEvent::FollowUpPosted),
+                _ => Err(FromValueError(Value::Bytes(bytes))),
+            },
+            v => Err(FromValueError(v)),
+        }
+    }
+
+    fn commit(self) -> Self { self }
+    fn rollback(self) -> Value { self.into() }
+}
+
+impl FromValue for NotificationEvent {
+    type Intermediate = Self;
+}
+
+impl fmt::Display for NotificationSink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<NotificationSink> for Value {
+    fn from(s: NotificationSink) -> Self {
+        Value::Bytes(s.to_string().into_bytes())
+    }
+}
+
+impl TryFrom<&str> for NotificationSink {
+    type Error = anyhow::Error;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.as_bytes() {
+            b"Email" => Ok(NotificationSink::Email),
+            b"Webhook" => Ok(NotificationSink::Webhook),
+            b"Pull" => Ok(NotificationSink::Pull),
+            _ => Err(anyhow!("Invalid notification sink {}",value)),
+        }
+    }
+}
+
+impl ConvIr<NotificationSink> for NotificationSink {
+    fn new(v: Value) -> Result<Self, FromValueError> {
+        match v {
+            Value::Bytes(bytes) => match bytes.as_slice() {
+                b"Email" => Ok(NotificationSink::Email),
+                b"Webhook" => Ok(NotificationSink::Webhook),
+                b"Pull" => Ok(NotificationSink::Pull),
+                _ => Err(FromValueError(Value::Bytes(bytes))),
+            },
+            v => Err(FromValueError(v)),
+        }
+    }
+
+    fn commit(self) -> Self { self }
+    fn rollback(self) -> Value { self.into() }
+}
+
+impl FromValue for NotificationSink {
+    type Intermediate = Self;
+}
+
+/// Record that something happened to a question. This should be done in the same transaction as the change itself.
+pub(crate) fn raise_notification_event(conn:&mut impl Queryable,question_id:QuestionID,event:NotificationEvent,related:Option<HashValue>,url:Option<&str>,timestamp:Timestamp) -> Result<(),QuestionError> {
+    conn.exec_drop("insert into NotificationOutbox (QuestionId,Event,Related,Url,timestamp) values (?,?,?,?,?)",(question_id.0,event,related.map(|h|h.0),url,timestamp)).map_err(internal_error)
+}
+
+/// What a user is subscribed to.
+#[derive(Serialize,Deserialize,Debug,Clone,Eq,PartialEq)]
+pub enum SubscriptionTarget {
+    Question(QuestionID),
+    /// Questions the MP is asked to ask or answer, or has answered.
+    MP(MPId),
+    /// Questions involving an MP for this electorate. If the region is None, any MP in the chamber.
+    Electorate(Electorate),
+    Tag(TopicTag),
+}
+
+/// A subscription to notifications.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct Subscription {
+    /// Used to unsubscribe. Ignored when subscribing.
+    #[serde(default)]
+    pub id : u64,
+    pub target : SubscriptionTarget,
+    pub sink : NotificationSink,
+    /// Required for, and only used by, [NotificationSink::Webhook]. Must be https.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub webhook_url : Option<String>,
+}
+
+impl Subscription {
+    /// Subscribe the given user, returning the id of the new subscription.
+    pub async fn subscribe(&self,uid:&UserUID) -> Result<u64,QuestionError> {
+        let webhook_url = match (self.sink,&self.webhook_url) {
+            (NotificationSink::Webhook,Some(url)) => {
+                resolve_webhook(url).await?;
+                Some(url)
+            }
+            (NotificationSink::Webhook,None) => return Err(QuestionError::InvalidWebhookURL),
+            _ => None,
+        };
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let user_id = get_user_id(uid,QuestionError::NoSuchUser,QuestionError::InternalError,&mut conn)?;
+        if self.sink==NotificationSink::Email && EmailAddress::get_verified_for_user(&mut conn,user_id).map_err(internal_error)?.is_none() { return Err(QuestionError::NoVerifiedEmail); }
+        let existing : usize = conn.exec_first("select count(*) from Subscriptions where UserId=?",(user_id,)).map_err(internal_error)?.unwrap_or(0);
+        if existing>=MAX_SUBSCRIPTIONS_PER_USER { return Err(QuestionError::TooManySubscriptions); }
+        let (mut question,mut mp,mut electorate,mut tag) : (Option<Vec<u8>>,Option<usize>,Option<u64>,Option<u32>) = (None,None,None,None);
+        match &self.target {
+            SubscriptionTarget::Question(q) => {
+                let found : Option<usize> = conn.exec_first("select 1 from QUESTIONS where QuestionId=? and CensorshipStatus!='Censored'",(q.0,)).map_err(internal_error)?;
+                if found.is_none() { return Err(QuestionError::QuestionDoesNotExist); }
+                question = Some(q.0.to_vec());
+            }
+            SubscriptionTarget::MP(mp_id) => {
+                if !MPSpec::get().map_err(internal_error)?.contains(mp_id) { return Err(QuestionError::InvalidMP); }
+                mp = Some(mp_id.get_id_from_database(&mut conn).map_err(internal_error)?);
+            }
+            SubscriptionTarget::Electorate(e) => {
+                let region = e.region.clone().unwrap_or_default(); // blank rather than null so the unique index works.
+                conn.exec_drop("insert ignore into ElectorateDefinition (Chamber,Electorate) values (?,?)",(e.chamber.to_string(),&region)).map_err(internal_error)?;
+                electorate = conn.exec_first("select id from ElectorateDefinition where Chamber=? and Electorate=?",(e.chamber.to_string(),&region)).map_err(internal_error)?;
+            }
+            SubscriptionTarget::Tag(t) => {
+                tag = Some(conn.exec_first("select id from TopicTags where Tag=?",(canonicalise_topic_tag(t),)).map_err(internal_error)?.ok_or(QuestionError::UnknownTopicTag)?);
+            }
+        }
+        conn.exec_drop("insert into Subscriptions (UserId,QuestionId,MP,ElectorateId,TagId,Sink,WebhookURL) values (?,?,?,?,?,?,?)",(user_id,question,mp,electorate,tag,self.sink,webhook_url)).map_err(internal_error)?;
+        Ok(conn.last_insert_id())
+    }
+}
+
+/// Whether a webhook may be delivered to an address. Loopback, private, link local and other special purpose addresses
+/// are not allowed, so webhooks can't be used to reach services inside the server's network.
+fn is_public_address(ip:IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a,b,_,_] = ip.octets();
+            let shared = a==100 && (b&0xc0)==64; // 100.64.0.0/10, carrier grade NAT.
+            !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified() || ip.is_broadcast() || ip.is_documentation() || ip.is_multicast() || shared || a==0 || a>=240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_address(IpAddr::V4(ip)),
+            None => !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() || ip.is_unique_local() || ip.is_unicast_link_local()),
+        }
+    }
+}
+
+/// Check that a webhook URL is https and that its host resolves only to public addresses (see [is_public_address]).
+/// Returns the host name, if not an IP address, and the addresses to deliver to.
+async fn resolve_webhook(url:&str) -> Result<(Option<String>,Vec<SocketAddr>),QuestionError> {
+    let url = Url::parse(url).map_err(|_|QuestionError::InvalidWebhookURL)?;
+    if url.scheme()!="https" { return Err(QuestionError::InvalidWebhookURL); }
+    let port = url.port_or_known_default().unwrap_or(443);
+    let (domain,addresses) = match url.host().ok_or(QuestionError::InvalidWebhookURL)? {
+        Host::Domain(domain) => (Some(domain.to_string()),tokio::net::lookup_host((domain,port)).await.map_err(|_|QuestionError::InvalidWebhookURL)?.collect::<Vec<_>>()),
+        Host::Ipv4(ip) => (None,vec![SocketAddr::new(IpAddr::V4(ip),port)]),
+        Host::Ipv6(ip) => (None,vec![SocketAddr::new(IpAddr::V6(ip),port)]),
+    };
+    if addresses.is_empty() || !addresses.iter().all(|a|is_public_address(a.ip())) { return Err(QuestionError::InvalidWebhookURL); }
+    Ok((domain,addresses))
+}
+
+/// The columns of a row of the Subscriptions table, as selected by [get_subscriptions].
+type SubscriptionRow = (u64,Option<Value>,Option<usize>,Option<Chamber>,Option<String>,Option<TopicTag>,NotificationSink,Option<String>);
+
+/// Get a user's subscriptions.
+pub async fn get_subscriptions(uid:&UserUID) -> Result<Vec<Subscription>,QuestionError> {
+    let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+    let user_id = get_user_id(uid,QuestionError::NoSuchUser,QuestionError::InternalError,&mut conn)?;
+    let rows : Vec<SubscriptionRow> = conn.exec("select Subscriptions.id,QuestionId,MP,ElectorateDefinition.Chamber,ElectorateDefinition.Electorate,Tag,Sink,WebhookURL from Subscriptions left join ElectorateDefinition on Subscriptions.ElectorateId=ElectorateDefinition.id left join TopicTags on Subscriptions.TagId=TopicTags.id where UserId=? order by Subscriptions.id",(user_id,)).map_err(internal_error)?;
+    let mut res = vec![];
+    for (id,question,mp,chamber,region,tag,sink,webhook_url) in rows {
+        let electorate = chamber.map(|chamber|Electorate{chamber,region:region.filter(|r|!r.is_empty())});
+        let target = if let Some(question) = question { SubscriptionTarget::Question(hash_from_value(question)) }
+            else if let Some(mp) = mp { SubscriptionTarget::MP(MPId::read_from_database(&mut conn,mp).map_err(internal_error)?.ok_or(QuestionError::InternalError)?) }
+            else if let Some(electorate) = electorate { SubscriptionTarget::Electorate(electorate) }
+            else if let Some(tag) = tag { SubscriptionTarget::Tag(tag) }
+            else { return Err(QuestionError::InternalError) };
+        res.push(Subscription{id,target,sink,webhook_url});
+    }
+    Ok(res)
+}
+
+/// Remove one of a user's subscriptions.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct UnsubscribeCommand {
+    /// As in [Subscription::id]
+    pub id : u64,
+}
+
+impl UnsubscribeCommand {
+    pub async fn unsubscribe(&self,uid:&UserUID) -> Result<(),QuestionError> {
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let user_id = get_user_id(uid,QuestionError::NoSuchUser,QuestionError::InternalError,&mut conn)?;
+        conn.exec_drop("delete from Subscriptions where id=? and UserId=?",(self.id,user_id)).map_err(internal_error)?;
+        if conn.affected_rows()==0 { Err(QuestionError::NoSuchSubscription) } else { Ok(()) }
+    }
+}
+
+/// A notification, as returned by [GetNotificationsCommand] or sent to a webhook.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct Notification {
+    /// Increases with each notification for a user. Used to acknowledge pull notifications.
+    pub id : u64,
+    pub question_id : QuestionID,
+    pub event : NotificationEvent,
+    /// See [NotificationEvent] for what this is.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub related : Option<HashValue>,
+    /// See [NotificationEvent] for what this is.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub url : Option<String>,
+    pub timestamp : Timestamp,
+}
+
+/// Fetch the user's pending pull notifications.
+#[derive(Serialize,Deserialize,Debug,Clone,Default)]
+pub struct GetNotificationsCommand {
+    /// Notifications with an id up to and including this have been received, and will not be returned again.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub acknowledge_up_to : Option<u64>,
+}
+
+/// The columns of a row of NotificationDeliveries joined with NotificationOutbox, used to make a [Notification].
+type NotificationRow = (u64,Value,NotificationEvent,Value,Option<String>,Timestamp);
+
+fn notification_from_row((id,question_id,event,related,url,timestamp):NotificationRow) -> Notification {
+    let related = if related==Value::NULL { None } else { Some(hash_from_value(related)) };
+    Notification{ id, question_id: hash_from_value(question_id), event, related, url, timestamp }
+}
+
+impl GetNotificationsCommand {
+    /// Acknowledge any notifications as requested, then return the oldest remaining ones (at most 100).
+    pub async fn get_notifications(&self,uid:&UserUID) -> Result<Vec<Notification>,QuestionError> {
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let user_id = get_user_id(uid,QuestionError::NoSuchUser,QuestionError::InternalError,&mut conn)?;
+        if let Some(acknowledge_up_to) = self.acknowledge_up_to {
+            conn.exec_drop("update NotificationDeliveries set Status='Delivered' where UserId=? and Sink='Pull' and Status='Pending' and id<=?",(user_id,acknowledge_up_to)).map_err(internal_error)?;
+        }
+        conn.exec_map(format!("select d.id,o.QuestionId,o.Event,o.Related,o.Url,o.timestamp from NotificationDeliveries d inner join NotificationOutbox o on d.OutboxId=o.id where d.UserId=? and d.Sink='Pull' and d.Status='Pending' order by d.id limit {}",NOTIFICATION_BATCH_SIZE),(user_id,),notification_from_row).map_err(internal_error)
+    }
+}
+
+/// The SQL condition for a subscription `s` matching the question given (five times) as a parameter.
+const SUBSCRIPTION_MATCHES_QUESTION : &str = "s.QuestionId=? \
+    or exists (select * from PersonForQuestion p where p.QuestionId=? and p.MP=s.MP) \
+    or exists (select * from Answer a where a.QuestionId=? and a.MP=s.MP) \
+    or exists (select * from PersonForQuestion p inner join MP_IDs m on p.MP=m.id inner join ElectorateDefinition e on e.id=s.ElectorateId where p.QuestionId=? and m.Chamber=e.Chamber and (e.Electorate='' or m.Electorate=e.Electorate)) \
+    or exists (select * from QuestionTags t where t.QuestionId=? and t.TagId=s.TagId)";
+
+/// Work out who should be told about new events in the outbox.
+fn fan_out_outbox(conn:&mut mysql::PooledConn) -> mysql::Result<usize> {
+    let mut transaction = conn.start_transaction(TxOpts::default())?;
+    let events : Vec<(u64,Vec<u8>)> = transaction.exec(format!("select id,QuestionId from NotificationOutbox where Processed=FALSE order by id limit {} for update",NOTIFICATION_BATCH_SIZE),())?;
+    for (id,question) in &events {
+        transaction.exec_drop(format!("insert into NotificationDeliveries (OutboxId,UserId,Sink,WebhookURL) select distinct ?,UserId,Sink,WebhookURL from Subscriptions s where {}",SUBSCRIPTION_MATCHES_QUESTION),(id,question,question,question,question,question))?;
+        transaction.exec_drop("update NotificationOutbox set Processed=TRUE where id=?",(id,))?;
+    }
+    transaction.commit()?;
+    Ok(events.len())
+}
+
+const NOTIFICATION_SUBJECT_LINE : &str = "RightToAsk question update";
+const NOTIFICATION_BODY_TEMPLATE : &str = include_str!("templates/NotificationBody.txt");
+const NOTIFICATION_BODY_TEMPLATE_HTML : &str = include_str!("templates/NotificationBody.html");
+
+fn escape_html(s:&str) -> String {
+    s.replace('&',"&amp;").replace('<',"&lt;").replace('>',"&gt;").replace('"',"&quot;")
+}
+
+/// The plain text and html bodies of a notification email. The text of a censored question is left out, giving just its id,
+/// whatever the event, as the question may have been censored after the event.
+fn email_bodies(notification:&Notification,question_text:&str,censorship_status:CensorshipStatus) -> (String,String) {
+    let question = if censorship_status==CensorshipStatus::Censored || matches!(notification.event,NotificationEvent::QuestionCensored) {
+        format!("Question {}",notification.question_id)
+    } else { question_text.to_string() };
+    let event = match &notification.url {
+        Some(url) => format!("{} {}",notification.event.description(),url),
+        None => notification.event.description().to_string(),
+    };
+    let body = NOTIFICATION_BODY_TEMPLATE.replace("[QUESTION]",&question).replace("[EVENT]",&event);
+    let body_html = NOTIFICATION_BODY_TEMPLATE_HTML.replace("[QUESTION]",&escape_html(&question)).replace("[EVENT]",&escape_html(&event));
+    (body,body_html)
+}
+
+async fn deliver_email(conn:&mut impl Queryable,user_id:UserID,notification:&Notification,question_text:&str,censorship_status:CensorshipStatus) -> anyhow::Result<()> {
+    let email = EmailAddress::get_verified_for_user(conn,user_id)?.ok_or_else(||anyhow!("User has no verified email"))?;
+    let (body,body_html) = email_bodies(notification,question_text,censorship_status);
+    email.send_notification(NOTIFICATION_SUBJECT_LINE,body,body_html).await.map_err(|e|anyhow!("{}",e))
+}
+
+async fn deliver_webhook(url:&str,notification:&Notification) -> anyhow::Result<()> {
+    // Checked again, as what the host resolves to may have changed since subscribing. The connection is made to the
+    // addresses checked, and redirects are not followed, so it can't be sent somewhere else.
+    let (domain,addresses) = resolve_webhook(url).await.map_err(|_|anyhow!("Webhook {} is not at a public address",url))?;
+    let signed = ServerSigned::new(notification)?;
+    let mut client = reqwest::Client::builder().timeout(Duration::from_secs(10)).redirect(Policy::none());
+    if let Some(domain) = domain { client = client.resolve_to_addrs(&domain,&addresses); }
+    client.build()?.post(url).json(&signed).send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// The longest wait between attempts to deliver a notification.
+const MAX_RETRY_DELAY_SECONDS : Timestamp = 24*60*60;
+
+/// How long to wait before trying again to deliver a notification after `attempts` failed attempts. Starts at
+/// `poll_interval` and doubles each time, up to [MAX_RETRY_DELAY_SECONDS].
+fn retry_delay(poll_interval:Timestamp,attempts:u32) -> Timestamp {
+    poll_interval.saturating_mul(1u64.checked_shl(attempts.saturating_sub(1)).unwrap_or(u64::MAX)).min(MAX_RETRY_DELAY_SECONDS)
+}
+
+/// The columns of a pending email or webhook delivery: user, sink, webhook, the question text and censorship status, previous attempts, and the notification.
+type PendingDeliveryRow = (UserID,NotificationSink,Option<String>,String,CensorshipStatus,u32,u64,Value,NotificationEvent,Value,Option<String>,Timestamp);
+
+/// Try to send pending email and webhook notifications that are due.
+async fn deliver_pending(conn:&mut mysql::PooledConn) -> anyhow::Result<usize> {
+    let now = timestamp_now()?;
+    let pending : Vec<PendingDeliveryRow> = conn.exec(format!("select d.UserId,d.Sink,d.WebhookURL,QUESTIONS.Question,QUESTIONS.CensorshipStatus,d.Attempts,d.id,o.QuestionId,o.Event,o.Related,o.Url,o.timestamp from NotificationDeliveries d inner join NotificationOutbox o on d.OutboxId=o.id inner join QUESTIONS on o.QuestionId=QUESTIONS.QuestionId where d.Status='Pending' and d.Sink!='Pull' and (d.NextAttemptTimestamp is NULL or d.NextAttemptTimestamp<=?) order by d.id limit {}",NOTIFICATION_BATCH_SIZE),(now,))?;
+    let num_pending = pending.len();
+    for (user_id,sink,webhook_url,question_text,censorship_status,attempts,id,question_id,event,related,url,timestamp) in pending {
+        let notification = notification_from_row((id,question_id,event,related,url,timestamp));
+        let res = match (sink,webhook_url) {
+            (NotificationSink::Email,_) => deliver_email(conn,user_id,&notification,&question_text,censorship_status).await,
+            (NotificationSink::Webhook,Some(webhook_url)) => deliver_webhook(&webhook_url,&notification).await,
+            _ => Err(anyhow!("No way to deliver")),
+        };
+        match res {
+            Ok(()) => conn.exec_drop("update NotificationDeliveries set Status='Delivered',Attempts=Attempts+1 where id=?",(id,))?,
+            Err(e) => {
+                println!("Could not deliver notification {} : {}",id,e);
+                let next_attempt = now+retry_delay(CONFIG.notifications.poll_interval_seconds,attempts+1);
+                conn.exec_drop("update NotificationDeliveries set Status=IF(Attempts+1>=?,'Failed','Pending'),Attempts=Attempts+1,NextAttemptTimestamp=? where id=?",(CONFIG.notifications.max_delivery_attempts,next_attempt,id))?
+            }
+        }
+    }
+    Ok(num_pending)
+}
+
+/// Work out who should be told about new events, and deliver email and webhook notifications.
+/// Called periodically by the server; see [notification_poll_interval].
+pub async fn process_notifications() -> anyhow::Result<()> {
+    let mut conn = get_rta_database_connection().await?;
+    fan_out_outbox(&mut conn)?;
+    deliver_pending(&mut conn).await?;
+    Ok(())
+}
+
+/// How often [process_notifications] should be called.
+pub fn notification_poll_interval() -> Duration {
+    Duration::from_secs(CONFIG.notifications.poll_interval_seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn censored_question_text_is_not_emailed() {
+        let mut notification = Notification{ id: 1, question_id: HashValue([5u8;32]), event: NotificationEvent::QuestionCensored, related: None, url: None, timestamp: 0 };
+        let (body,body_html) = email_bodies(&notification,"Something defamatory",CensorshipStatus::Censored);
+        assert!(!body.contains("defamatory"));
+        assert!(!body_html.contains("defamatory"));
+        assert!(body.contains(&notification.question_id.to_string()));
+        assert!(body.contains(NotificationEvent::QuestionCensored.description()));
+        notification.event = NotificationEvent::AnswerAdded;
+        assert!(email_bodies(&notification,"Why <b>?",CensorshipStatus::NotFlagged).1.contains("Why &lt;b&gt;?"));
+    }
+
+    #[test]
+    fn questions_censored_after_the_event_are_not_emailed() {
+        let notification = Notification{ id: 1, question_id: HashValue([5u8;32]), event: NotificationEvent::AnswerAdded, related: None, url: None, timestamp: 0 };
+        let (body,body_html) = email_bodies(&notification,"Something defamatory",CensorshipStatus::Censored);
+        assert!(!body.contains("defamatory"));
+        assert!(!body_html.contains("defamatory"));
+        assert!(body.contains(&notification.question_id.to_string()));
+        assert!(body.contains(NotificationEvent::AnswerAdded.description()));
+        for censorship_status in [CensorshipStatus::NotFlagged,CensorshipStatus::Flagged,CensorshipStatus::Allowed,CensorshipStatus::StructureChanged,CensorshipStatus::StructureChangedThenFlagged] {
+            assert!(email_bodies(&notification,"Something fine",censorship_status).0.contains("Something fine"));
+        }
+    }
+
+    #[test]
+    fn webhooks_only_go_to_public_addresses() {
+        for ip in ["127.0.0.1","10.1.2.3","172.16.0.1","192.168.1.1","169.254.169.254","100.64.0.1","0.0.0.0","255.255.255.255","::1","::","fe80::1","fd00::1","::ffff:127.0.0.1","::ffff:169.254.169.254"] {
+            assert!(!is_public_address(ip.parse().unwrap()),"{}",ip);
+        }
+        for ip in ["1.1.1.1","203.119.0.1","2606:4700:4700::1111","::ffff:8.8.8.8"] {
+            assert!(is_public_address(ip.parse().unwrap()),"{}",ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn webhook_urls_are_checked() {
+        for url in ["http://example.com/hook","https://127.0.0.1/hook","https://[::1]/hook","https://10.0.0.1:8443/hook","https://localhost/hook","not a url"] {
+            assert_eq!(resolve_webhook(url).await,Err(QuestionError::InvalidWebhookURL),"{}",url);
+        }
+        assert!(resolve_webhook("https://1.1.1.1/hook").await.is_ok());
+    }
+
+    #[test]
+    fn retries_back_off_exponentially() {
+        assert_eq!(retry_delay(60,1),60);
+        assert_eq!(retry_delay(60,2),120);
+        assert_eq!(retry_delay(60,5),960);
+        assert_eq!(retry_delay(60,20),MAX_RETRY_DELAY_SECONDS);
+        assert_eq!(retry_delay(60,100),MAX_RETRY_DELAY_SECONDS);
+    }
+}
@@ -23,8 +23,12 @@ use rand::Rng;
 use sha2::{Digest, Sha256};
 use crate::config::CONFIG;
 use crate::mp::MPSpec;
-use crate::signing::ClientSigned;
+use crate::signing::{ClientSigned, ClientSignedUnparsed, is_valid_public_key};
+use crate::question::hash_from_value;
 use crate::time_limited_hashmap::TimeLimitedHashMap;
+use crate::administration::{ModerationAction, ModerationAuditEntry};
+use crate::reserved_names::{check_display_name, is_reserved_uid, DisplayNameProblem};
+use crate::proof_of_work::{proof_of_work_required, record_registration, ProofOfWorkError, ProofOfWorkSolution};
 
 /// A unique ID identifying a person that is presented to the API. It can very rarely change.
 pub type UserUID = String;
@@ -55,7 +59,10 @@ pub struct NewRegistration {
     #[serde(default,skip_serializing_if = "Option::is_none")]
     state : Option<State>,
     #[serde(default,skip_serializing_if = "Vec::is_empty")]
-    electorates : Vec<Electorate>
+    electorates : Vec<Electorate>,
+    /// A solution to a challenge from `/get_registration_challenge`. Not stored in the bulletin board.
+    #[serde(default,skip_serializing)]
+    proof_of_work : Option<ProofOfWorkSolution>,
 }
 
 #[derive(Debug,Clone,Copy,Serialize,Deserialize,Eq,PartialEq)]
@@ -70,6 +77,54 @@ pub enum RegistrationError {
     CouldNotWriteToBulletinBoard,
     NoSuchUser, // when editing a user. Unlikely to ever occur except when a concurrent UID change is happening.
     IdenticalDataAlreadySubmitted,
+    /// A new public key is not base64 encoded raw 32 bytes of an ED25519 public key.
+    InvalidPublicKey,
+    /// Trying to rotate to the key currently in use.
+    NewKeySameAsOld,
+    /// Adding a device key that is already an active key for this user.
+    KeyAlreadyInUse,
+    /// Adding a device key when the user already has the maximum number of active keys.
+    TooManyKeys,
+    /// Revoking a key that is not an active key of the user.
+    NoSuchKey,
+    /// Revoking the only active key of the user.
+    CannotRevokeLastKey,
+    DeviceNameTooShort,
+    DeviceNameTooLong,
+    /// Trying to cancel or complete an account recovery when none has been requested.
+    NoAccountRecoveryPending,
+    /// Trying to complete an account recovery before the cooling off period has finished.
+    AccountRecoveryCoolingOff,
+    /// Too many registrations recently. See [crate::rate_limit].
+    RateLimited,
+    /// A proof of work is required but was not provided. See [crate::proof_of_work].
+    ProofOfWorkMissing,
+    /// The proof of work challenge is unknown, expired or already used.
+    ProofOfWorkUnknownChallenge,
+    /// The proof of work does not have enough leading zero bits.
+    ProofOfWorkInsufficient,
+    /// The UID is, or looks like, an MP's name or a name reserved in config.toml. See [crate::reserved_names].
+    UIDReserved,
+    /// The display name contains a name reserved in config.toml.
+    DisplayNameReserved,
+    /// The display name is close to an MP's name, and the user does not have that MP's badge.
+    DisplayNameTooSimilarToMP,
+}
+impl From<ProofOfWorkError> for RegistrationError {
+    fn from(error: ProofOfWorkError) -> Self {
+        match error {
+            ProofOfWorkError::UnknownChallenge => RegistrationError::ProofOfWorkUnknownChallenge,
+            ProofOfWorkError::InsufficientWork | ProofOfWorkError::NonceTooLong => RegistrationError::ProofOfWorkInsufficient,
+        }
+    }
+}
+impl From<DisplayNameProblem> for RegistrationError {
+    fn from(problem: DisplayNameProblem) -> Self {
+        match problem {
+            DisplayNameProblem::Reserved => RegistrationError::DisplayNameReserved,
+            DisplayNameProblem::TooSimilarToMP => RegistrationError::DisplayNameTooSimilarToMP,
+        }
+    }
 }
 fn bulletin_board_error(error:BulletinBoardError) -> RegistrationError {
     match error {
@@ -99,6 +154,30 @@ pub struct UserInfo {
     #[serde(default,skip_serializing_if = "Vec::is_empty")]
     electorates : Vec<Electorate>,
     badges : Vec<Badge>,
+    /// All the public keys this user has had, oldest first, so old signatures can be checked against the key valid at the time.
+    #[serde(default,skip_serializing_if = "Vec::is_empty")]
+    key_history : Vec<PublicKeyHistoryEntry>,
+}
+
+/// A public key that a user has had, and when it was valid.
+#[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub struct PublicKeyHistoryEntry {
+    public_key : PublicKey,
+    /// When the key became valid. None for keys registered before key history was recorded.
+    #[serde(default,skip_serializing_if = "Option::is_none")]
+    valid_from : Option<Timestamp>,
+    /// When the key was replaced. None if it is still current.
+    #[serde(default,skip_serializing_if = "Option::is_none")]
+    valid_until : Option<Timestamp>,
+    /// The bulletin board entry that introduced the key - a NewUser, RotateKey, AddDeviceKey or AccountRecovery entry. None for keys registered before key history was recorded.
+    #[serde(default,skip_serializing_if = "Option::is_none")]
+    bulletin_board_hash : Option<HashValue>,
+    /// The bulletin board entry that retired the key - a RotateKey, RevokeDeviceKey or AccountRecovery entry. None if it is still current, or was retired before this was recorded.
+    #[serde(default,skip_serializing_if = "Option::is_none")]
+    revocation_bulletin_board_hash : Option<HashValue>,
+    /// The name of the device for keys added by [AddDeviceKeyCommand]. None for the key the user registered with.
+    #[serde(default,skip_serializing_if = "Option::is_none")]
+    device_name : Option<String>,
 }
 
 #[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
@@ -204,8 +283,9 @@ impl NewRegistration {
             Ok(_) => {}
             Err(e) => { Err(e)?; } // returns immediately.
         }
-        let user_id = tx.exec_first("select LAST_INSERT_ID()",())?.ok_or_else(||anyhow!("no LAST_INSERT_ID() in NewRegistration::store_in_database()"))?;
+        let user_id : UserID = tx.exec_first("select LAST_INSERT_ID()",())?.ok_or_else(||anyhow!("no LAST_INSERT_ID() in NewRegistration::store_in_database()"))?;
         EditUserDetails::add_electorates(user_id,&self.electorates,&mut tx)?;
+        tx.exec_drop("insert into PublicKeyHistory (user_id,PublicKey,ValidFrom) values (?,?,?)",(user_id,&self.public_key,timestamp_now()?))?;
         tx.commit()?;
         Ok(true)
     }
@@ -214,13 +294,22 @@ impl NewRegistration {
         if self.uid.len()<1 { return Err(RegistrationError::UIDTooShort); }
         if !self.uid.chars().all(|c|c.is_ascii_alphanumeric()||c.is_ascii_punctuation()) { return Err(RegistrationError::UIDContainsIllegalCharacters); }
         if self.uid.len()>30 { return Err(RegistrationError::UIDTooLong); }
+        if is_reserved_uid(&self.uid) { return Err(RegistrationError::UIDReserved); }
         if let Some(dn) = self.display_name.as_ref() {
             if dn.len()<1 { return Err(RegistrationError::DisplayNameTooShort); }
             if dn.len()>60 { return Err(RegistrationError::DisplayNameTooLong); }
+            check_display_name(dn,&[])?; // a new user has no badges.
+        }
+        match &self.proof_of_work {
+            Some(solution) => solution.check(&self.uid)?,
+            None if proof_of_work_required() => return Err(RegistrationError::ProofOfWorkMissing),
+            None => {}
         }
         let uid_available = self.store_in_database().await.map_err(internal_error)?;
         if !uid_available { return Err(RegistrationError::UIDAlreadyTaken) }
         let hash = LogInBulletinBoard::NewUser(self.clone()).log_in_bulletin_board().await.map_err(bulletin_board_error)?;
+        record_key_history_bulletin_board_hash(&self.uid,&self.public_key,hash).await.map_err(internal_error)?;
+        record_registration();
         println!("Registered uid={} display_name={:?} state={:?} electorates={:?} public_key={}",self.uid,self.display_name,self.state,self.electorates,self.public_key);
         Ok(hash)
     }
@@ -243,17 +332,219 @@ pub async fn get_user_by_id(uid:&str) -> mysql::Result<Option<UserInfo>> {
     if let Some((user_id,display_name,state,public_key)) = conn.exec_first::<(u64,Option<String>,Option<State>,PublicKey),_,_>("SELECT id,DisplayName,AusState,PublicKey from USERS where UID=?",(uid,))? {
         let electorates = conn.exec_map("SELECT Chamber,Electorate from UserElectorate inner join ElectorateDefinition on UserElectorate.electorate_id = ElectorateDefinition.id where UserElectorate.user_id=?",(user_id,),|(chamber,location)|Electorate{ chamber, region: location })?;
         let badges = conn.exec_map("SELECT badge,what from BADGES where user_id=?",(user_id,),|(badge,name)|Badge{ badge, name })?;
+        let key_history = conn.exec_map("SELECT PublicKey,ValidFrom,ValidUntil,BulletinBoardHash,RevocationBulletinBoardHash,DeviceName from PublicKeyHistory where user_id=? order by id",(user_id,),|(public_key,valid_from,valid_until,bulletin_board_hash,revocation_bulletin_board_hash,device_name):PublicKeyHistoryRow|PublicKeyHistoryEntry{ public_key, valid_from, valid_until, bulletin_board_hash: bulletin_board_hash.map(hash_from_value), revocation_bulletin_board_hash: revocation_bulletin_board_hash.map(hash_from_value), device_name })?;
         Ok(Some(UserInfo{
             uid : uid.to_string(),
             display_name,
             public_key,
             state,
             electorates,
-            badges
+            badges,
+            key_history,
         }))
     } else {Ok(None)}
 }
 
+/// PublicKey,ValidFrom,ValidUntil,BulletinBoardHash,RevocationBulletinBoardHash,DeviceName from the PublicKeyHistory table.
+type PublicKeyHistoryRow = (PublicKey,Option<Timestamp>,Option<Timestamp>,Option<mysql::Value>,Option<mysql::Value>,Option<String>);
+
+/// Store the bulletin board entry that introduced a currently active key of a user.
+async fn record_key_history_bulletin_board_hash(uid:&str,public_key:&str,hash:HashValue) -> mysql::Result<()> {
+    let mut conn = get_rta_database_connection().await?;
+    conn.exec_drop("update PublicKeyHistory inner join USERS on PublicKeyHistory.user_id=USERS.id set BulletinBoardHash=? where USERS.UID=? and PublicKeyHistory.PublicKey=? and ValidUntil IS NULL",(hash.0,uid,public_key))
+}
+
+/// Store the bulletin board entry that retired the given rows of the PublicKeyHistory table.
+async fn record_key_revocation_bulletin_board_hash(ids:&[u64],hash:HashValue) -> mysql::Result<()> {
+    let mut conn = get_rta_database_connection().await?;
+    conn.exec_batch("update PublicKeyHistory set RevocationBulletinBoardHash=? where id=?",ids.iter().map(|id|(hash.0,*id)))
+}
+
+/// An active key of a user, from the PublicKeyHistory table.
+struct ActiveKey {
+    /// id in the PublicKeyHistory table
+    id : u64,
+    public_key : PublicKey,
+    device_name : Option<String>,
+}
+
+/// Get the currently active keys of a user, locking them for the transaction.
+fn get_active_keys(transaction:&mut Transaction,user_id:UserID) -> mysql::Result<Vec<ActiveKey>> {
+    transaction.exec_map("select id,PublicKey,DeviceName from PublicKeyHistory where user_id=? and ValidUntil IS NULL for update",(user_id,),|(id,public_key,device_name)|ActiveKey{id,public_key,device_name})
+}
+
+/// Change the public key of a user, revoking all their existing keys (including device keys), recording the change in the key history.
+/// Returns the ids in the PublicKeyHistory table of the revoked keys.
+fn replace_public_key(transaction:&mut Transaction,user_id:UserID,new_public_key:&str,timestamp:Timestamp) -> mysql::Result<Vec<u64>> {
+    let revoked : Vec<u64> = get_active_keys(transaction,user_id)?.into_iter().map(|k|k.id).collect();
+    transaction.exec_drop("update USERS set PublicKey=? where id=?",(new_public_key,user_id))?;
+    transaction.exec_drop("update PublicKeyHistory set ValidUntil=? where user_id=? and ValidUntil IS NULL",(timestamp,user_id))?;
+    transaction.exec_drop("insert into PublicKeyHistory (user_id,PublicKey,ValidFrom) values (?,?,?)",(user_id,new_public_key,timestamp))?;
+    Ok(revoked)
+}
+
+/// A command to replace a user's public key, for instance when they get a new phone.
+/// It is signed by the current key. After it is accepted, the new key is used instead of the signing key,
+/// with the same device name. Other device keys are unaffected.
+#[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub struct RotateKeyCommand {
+    pub new_public_key : PublicKey,
+}
+
+impl RotateKeyCommand {
+    /// Change the user's key, returning the bulletin board entry. The signature should already have been checked.
+    pub async fn rotate_key(command:&ClientSigned<RotateKeyCommand>) -> Result<HashValue,RegistrationError> {
+        let new_public_key = &command.parsed.new_public_key;
+        if !is_valid_public_key(new_public_key) { return Err(RegistrationError::InvalidPublicKey); }
+        let timestamp = timestamp_now().map_err(internal_error)?;
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error)?;
+        let user_id = get_user_id(&command.signed_message.user,RegistrationError::NoSuchUser,RegistrationError::InternalError,&mut transaction)?;
+        let active_keys = get_active_keys(&mut transaction,user_id).map_err(internal_error)?;
+        if active_keys.iter().any(|k|&k.public_key==new_public_key) { return Err(RegistrationError::NewKeySameAsOld); }
+        let old = active_keys.iter().find(|k|command.signed_message.is_signed_by(&k.public_key)).ok_or_else(||internal_error("Signing key is not an active key"))?;
+        let old_public_key = &old.public_key;
+        transaction.exec_drop("update PublicKeyHistory set ValidUntil=? where id=?",(timestamp,old.id)).map_err(internal_error)?;
+        transaction.exec_drop("insert into PublicKeyHistory (user_id,PublicKey,ValidFrom,DeviceName) values (?,?,?,?)",(user_id,new_public_key,timestamp,&old.device_name)).map_err(internal_error)?;
+        transaction.exec_drop("update USERS set PublicKey=? where id=? and PublicKey=?",(new_public_key,user_id,old_public_key)).map_err(internal_error)?;
+        transaction.commit().map_err(internal_error)?;
+        let version = LogInBulletinBoard::RotateKey(command.signed_message.clone()).log_in_bulletin_board().await.map_err(bulletin_board_error)?;
+        record_key_history_bulletin_board_hash(&command.signed_message.user,new_public_key,version).await.map_err(internal_error)?;
+        record_key_revocation_bulletin_board_hash(&[old.id],version).await.map_err(internal_error)?;
+        println!("Rotated key for uid={} from {} to {}",command.signed_message.user,old_public_key,new_public_key);
+        Ok(version)
+    }
+}
+
+/// The maximum number of active keys (devices) a user may have.
+const MAX_ACTIVE_KEYS : usize = 10;
+
+/// A command to add a key for another device, such as a tablet, so that a private key does not need to be copied between devices.
+/// It is signed by any of the user's current keys.
+#[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub struct AddDeviceKeyCommand {
+    /// A name for the device, such as "Tablet", to help the user choose which to revoke. 1 to 60 characters.
+    pub device_name : String,
+    pub public_key : PublicKey,
+}
+
+impl AddDeviceKeyCommand {
+    /// Add the key, returning the bulletin board entry. The signature should already have been checked.
+    pub async fn add_device_key(command:&ClientSigned<AddDeviceKeyCommand>) -> Result<HashValue,RegistrationError> {
+        let public_key = &command.parsed.public_key;
+        if !is_valid_public_key(public_key) { return Err(RegistrationError::InvalidPublicKey); }
+        if command.parsed.device_name.is_empty() { return Err(RegistrationError::DeviceNameTooShort); }
+        if command.parsed.device_name.len()>60 { return Err(RegistrationError::DeviceNameTooLong); }
+        let timestamp = timestamp_now().map_err(internal_error)?;
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error)?;
+        let user_id = get_user_id(&command.signed_message.user,RegistrationError::NoSuchUser,RegistrationError::InternalError,&mut transaction)?;
+        let active_keys = get_active_keys(&mut transaction,user_id).map_err(internal_error)?;
+        if active_keys.iter().any(|k|&k.public_key==public_key) { return Err(RegistrationError::KeyAlreadyInUse); }
+        if active_keys.len()>=MAX_ACTIVE_KEYS { return Err(RegistrationError::TooManyKeys); }
+        transaction.exec_drop("insert into PublicKeyHistory (user_id,PublicKey,ValidFrom,DeviceName) values (?,?,?,?)",(user_id,public_key,timestamp,&command.parsed.device_name)).map_err(internal_error)?;
+        transaction.commit().map_err(internal_error)?;
+        let version = LogInBulletinBoard::AddDeviceKey(command.signed_message.clone()).log_in_bulletin_board().await.map_err(bulletin_board_error)?;
+        record_key_history_bulletin_board_hash(&command.signed_message.user,public_key,version).await.map_err(internal_error)?;
+        println!("Added device key {} for uid={} device {}",public_key,command.signed_message.user,command.parsed.device_name);
+        Ok(version)
+    }
+}
+
+/// A command to revoke one of the user's keys, for instance for a lost or sold device.
+/// It is signed by any of the user's current keys, possibly the one being revoked. The last active key cannot be revoked.
+#[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub struct RevokeDeviceKeyCommand {
+    pub public_key : PublicKey,
+}
+
+impl RevokeDeviceKeyCommand {
+    /// Revoke the key, returning the bulletin board entry. The signature should already have been checked.
+    pub async fn revoke_device_key(command:&ClientSigned<RevokeDeviceKeyCommand>) -> Result<HashValue,RegistrationError> {
+        let timestamp = timestamp_now().map_err(internal_error)?;
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error)?;
+        let user_id = get_user_id(&command.signed_message.user,RegistrationError::NoSuchUser,RegistrationError::InternalError,&mut transaction)?;
+        let active_keys = get_active_keys(&mut transaction,user_id).map_err(internal_error)?;
+        let revoked = active_keys.iter().find(|k|k.public_key==command.parsed.public_key).ok_or(RegistrationError::NoSuchKey)?;
+        let remaining = active_keys.iter().find(|k|k.id!=revoked.id).ok_or(RegistrationError::CannotRevokeLastKey)?;
+        transaction.exec_drop("update PublicKeyHistory set ValidUntil=? where id=?",(timestamp,revoked.id)).map_err(internal_error)?;
+        // USERS.PublicKey is always one of the active keys.
+        transaction.exec_drop("update USERS set PublicKey=? where id=? and PublicKey=?",(&remaining.public_key,user_id,&revoked.public_key)).map_err(internal_error)?;
+        transaction.commit().map_err(internal_error)?;
+        let version = LogInBulletinBoard::RevokeDeviceKey(command.signed_message.clone()).log_in_bulletin_board().await.map_err(bulletin_board_error)?;
+        record_key_revocation_bulletin_board_hash(&[revoked.id],version).await.map_err(internal_error)?;
+        println!("Revoked device key {} for uid={}",revoked.public_key,command.signed_message.user);
+        Ok(version)
+    }
+}
+
+/// Account recovery, for someone who has lost their private key.
+///
+/// * The user sends a [RequestEmailValidation] with reason [EmailValidationReason::AccountRecovery], the new public key as the name,
+///   and the email address already verified for the account. It is signed by the new key.
+/// * The user sends the [EmailProof] with the emailed code, again signed by the new key.
+/// * The recovery is then pending. An email is sent to the verified address saying so.
+/// * During the cooling off period, the user may cancel it with a [CancelAccountRecovery] signed by the current key,
+///   in case the email account has been compromised.
+/// * After the cooling off period, the user sends a [CompleteAccountRecovery] signed by the new key, which installs the key
+///   and records the change on the bulletin board.
+#[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub struct CancelAccountRecovery {}
+
+impl CancelAccountRecovery {
+    /// Cancel a pending account recovery. The signature, by the current key, should already have been checked.
+    pub async fn cancel(command:&ClientSigned<CancelAccountRecovery>) -> Result<(),RegistrationError> {
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let user_id = get_user_id(&command.signed_message.user,RegistrationError::NoSuchUser,RegistrationError::InternalError,&mut conn)?;
+        conn.exec_drop("delete from PendingAccountRecovery where user_id=?",(user_id,)).map_err(internal_error)?;
+        if conn.affected_rows()==0 { return Err(RegistrationError::NoAccountRecoveryPending); }
+        println!("Account recovery cancelled for uid={}",command.signed_message.user);
+        Ok(())
+    }
+}
+
+/// See [CancelAccountRecovery] for the account recovery process. This completes it, and should be signed by the new key.
+#[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub struct CompleteAccountRecovery {}
+
+/// The structure posted to the bulletin board when an account recovery is completed.
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct AccountRecoveryPostedToBulletinBoard {
+    /// The original [RequestEmailValidation], signed by the new key. The email address is not included.
+    pub request : ClientSignedUnparsed,
+    /// The [CompleteAccountRecovery] command, signed by the new key.
+    pub completion : ClientSignedUnparsed,
+}
+
+impl CompleteAccountRecovery {
+    /// The new public key in a pending account recovery for the given user, if any. This is the key that should sign the CompleteAccountRecovery command.
+    pub async fn pending_public_key(uid:&str) -> mysql::Result<Option<PublicKey>> {
+        let mut conn = get_rta_database_connection().await?;
+        conn.exec_first("select NewPublicKey from PendingAccountRecovery inner join USERS on PendingAccountRecovery.user_id=USERS.id where USERS.UID=?",(uid,))
+    }
+
+    /// Install the new key, returning the bulletin board entry. The signature, by the new key, should already have been checked.
+    pub async fn complete(command:&ClientSigned<CompleteAccountRecovery>) -> Result<HashValue,RegistrationError> {
+        let timestamp = timestamp_now().map_err(internal_error)?;
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error)?;
+        let user_id = get_user_id(&command.signed_message.user,RegistrationError::NoSuchUser,RegistrationError::InternalError,&mut transaction)?;
+        let (new_public_key,effective,request) : (PublicKey,Timestamp,String) = transaction.exec_first("select NewPublicKey,Effective,Request from PendingAccountRecovery where user_id=? for update",(user_id,)).map_err(internal_error)?.ok_or(RegistrationError::NoAccountRecoveryPending)?;
+        if effective>timestamp { return Err(RegistrationError::AccountRecoveryCoolingOff); }
+        let request : ClientSignedUnparsed = serde_json::from_str(&request).map_err(internal_error)?;
+        let revoked = replace_public_key(&mut transaction,user_id,&new_public_key,timestamp).map_err(internal_error)?;
+        transaction.exec_drop("delete from PendingAccountRecovery where user_id=?",(user_id,)).map_err(internal_error)?;
+        transaction.commit().map_err(internal_error)?;
+        let for_bb = AccountRecoveryPostedToBulletinBoard{ request, completion: command.signed_message.clone() };
+        let version = LogInBulletinBoard::AccountRecovery(for_bb).log_in_bulletin_board().await.map_err(bulletin_board_error)?;
+        record_key_history_bulletin_board_hash(&command.signed_message.user,&new_public_key,version).await.map_err(internal_error)?;
+        record_key_revocation_bulletin_board_hash(&revoked,version).await.map_err(internal_error)?;
+        println!("Completed account recovery for uid={} new public key {}",command.signed_message.user,new_public_key);
+        Ok(version)
+    }
+}
+
 #[derive(serde::Deserialize)]
 /// A command to block or unblock a user
 pub struct BlockUserCommand {
@@ -261,7 +552,7 @@ pub struct BlockUserCommand {
     pub block : bool,
 }
 
-#[derive(serde::Serialize)]
+#[derive(Debug,serde::Serialize)]
 pub enum BlockUserError {
     InternalError,
     NoSuchUser,
@@ -269,12 +560,20 @@ pub enum BlockUserError {
     AlreadyUnblocked,
 }
 
+impl fmt::Display for BlockUserError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
 fn internal_error_block_user<T:Debug>(error:T) -> BlockUserError {
     eprintln!("Internal error {:?}",error);
     BlockUserError::InternalError
 }
 impl BlockUserCommand {
-    pub async fn apply(&self) -> Result<(),BlockUserError> {
+    /// Block or unblock the user. `moderator` is the name of the administrator who signed the command, and is recorded in the moderation audit log.
+    pub async fn apply(&self,moderator:&str) -> Result<(),BlockUserError> {
+        println!("Moderator {} set blocked={} for uid={}",moderator,self.block,self.uid);
         let mut conn = get_rta_database_connection().await.map_err(internal_error_block_user)?;
         let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error_block_user)?;
         let already_blocked : Option<bool> = transaction.exec_first("select blocked from USERS where UID=?",(&self.uid,)).map_err(internal_error_block_user)?;
@@ -285,6 +584,9 @@ impl BlockUserCommand {
             _ => {}
         }
         transaction.exec_drop("update USERS set Blocked = ? where UID=?",(self.block,&self.uid)).map_err(internal_error_block_user)?;
+        let mut audit = ModerationAuditEntry::new(moderator,if self.block { ModerationAction::BlockUser } else { ModerationAction::UnblockUser }).map_err(internal_error_block_user)?;
+        audit.uid = Some(self.uid.clone());
+        audit.record(&mut transaction).map_err(internal_error_block_user)?;
         transaction.commit().map_err(internal_error_block_user)?;
         Ok(())
     }
@@ -313,14 +615,17 @@ pub fn user_exists(uid:&UserUID,conn:&mut impl Queryable) -> mysql::Result<bool>
 }
 
 pub struct UserSigningInfo {
-    pub public_key : String,
+    /// All the currently active keys for the user, one per device. Any of them may sign.
+    pub public_keys : Vec<PublicKey>,
     pub blocked : bool,
     pub email_validated : bool,
 }
 pub async fn get_user_public_key_by_id(uid:&UserUID) -> mysql::Result<Option<UserSigningInfo>> {
     let mut conn = get_rta_database_connection().await?;
-    if let Some((public_key,blocked,email_validated)) = conn.exec_first::<(String,bool,bool),_,_>("SELECT PublicKey,Blocked,VerifiedEmail IS NOT NULL from USERS where UID=?",(uid,))? {
-        Ok(Some(UserSigningInfo{public_key,blocked,email_validated}))
+    if let Some((user_id,public_key,blocked,email_validated)) = conn.exec_first::<(UserID,PublicKey,bool,bool),_,_>("SELECT id,PublicKey,Blocked,VerifiedEmail IS NOT NULL from USERS where UID=?",(uid,))? {
+        let mut public_keys : Vec<PublicKey> = conn.exec_map("SELECT PublicKey from PublicKeyHistory where user_id=? and ValidUntil IS NULL",(user_id,),|(k,)|k)?;
+        if !public_keys.contains(&public_key) { public_keys.push(public_key); }
+        Ok(Some(UserSigningInfo{public_keys,blocked,email_validated}))
     } else {
         Ok(None)
     }
@@ -347,6 +652,8 @@ pub enum EmailValidationError {
     CouldNotSendEmail,
     NoSuchUser, // unlikely to ever occur if passed signature test.
     AlreadyValidated, // if you are trying to validate your account and you have already done so with that email.
+    NotTheVerifiedEmail, // account recovery must use the email address previously verified for the account.
+    InvalidPublicKey, // the new public key for account recovery is not valid.
 }
 
 impl fmt::Display for EmailValidationError {
@@ -374,7 +681,7 @@ fn bulletin_board_error_email(error:BulletinBoardError) -> EmailValidationError
 #[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
 pub struct RequestEmailValidation {
     why : EmailValidationReason,
-    /// the "name" of the badge. For an MP, the [MP::badge_name], for an organization the domain name, for an account recovery...TBD. Possibly the new key?
+    /// the "name" of the badge. For an MP, the [MP::badge_name], for an organization the domain name, for an account recovery the new public key.
     name : String,
 }
 
@@ -472,6 +779,64 @@ impl EmailAddress {
         conn.exec_drop("delete from EmailRateLimitHistory where email=?",(&self.canonicalise_for_equality_check(),)).map_err(internal_error_email)
     }
 
+    const ACCOUNT_RECOVERY_SUBJECT_LINE : &'static str = "RightToAsk account recovery requested";
+    const ACCOUNT_RECOVERY_BODY_TEMPLATE : &'static str = include_str!("templates/AccountRecoveryNotificationBody.txt");
+    const ACCOUNT_RECOVERY_BODY_TEMPLATE_HTML : &'static str = include_str!("templates/AccountRecoveryNotificationBody.html");
+
+    /// Tell the address on record for a user that a new key will replace theirs at time `effective` unless they cancel it.
+    async fn send_account_recovery_notification(&self,uid:&str,effective:Timestamp) -> Result<(),EmailValidationError> {
+        let hours = CONFIG.account_recovery_delay_seconds/3600;
+        let body = Self::ACCOUNT_RECOVERY_BODY_TEMPLATE.replace("[USERNAME]",uid).replace("[HOURS]",&hours.to_string()).replace("[EFFECTIVE]",&effective.to_string());
+        let body_html = Self::ACCOUNT_RECOVERY_BODY_TEMPLATE_HTML.replace("[USERNAME]",uid).replace("[HOURS]",&hours.to_string()).replace("[EFFECTIVE]",&effective.to_string());
+        self.send_email(Self::ACCOUNT_RECOVERY_SUBJECT_LINE,body,body_html).await
+    }
+
+    /// The verified email address of a user, if they have one.
+    pub(crate) fn get_verified_for_user(conn:&mut impl Queryable,user_id:UserID) -> mysql::Result<Option<EmailAddress>> {
+        Ok(conn.exec_first::<Option<String>,_,_>("select VerifiedEmail from USERS where id=?",(user_id,))?.flatten().map(|email|EmailAddress{email}))
+    }
+
+    /// Send a notification, such as of a new answer to a question the user is subscribed to, unless this address is on the do not email list.
+    pub(crate) async fn send_notification(&self,subject:&str,body:String,body_html:String) -> Result<(),EmailValidationError> {
+        self.check_is_not_in_do_not_email_list().await?;
+        self.send_email(subject,body,body_html).await
+    }
+
+    /// Send an email to this address, with plain text and html alternatives.
+    /// If there is no email configuration, print it to the console instead.
+    /// This does not check rate limits or the do not email list.
+    async fn send_email(&self,subject:&str,body:String,body_html:String) -> Result<(),EmailValidationError> {
+        let parsed_to : Mailbox = self.email.parse().map_err(|_|EmailValidationError::InvalidEmailAddress)?;
+        if let Some(email_config) = &CONFIG.email {
+            let parsed_to = if let Some(overriding) = &email_config.testing_email_override { overriding.mailbox() } else { parsed_to };
+            let email = Message::builder()
+                .from(email_config.verification_from_email.mailbox())
+                .reply_to(email_config.verification_reply_to_email.mailbox())
+                .to(parsed_to)
+                .subject(subject)
+                .multipart(MultiPart::alternative()
+                               .singlepart(SinglePart::plain(body))
+                               .singlepart(SinglePart::html(body_html))
+                          )
+                .map_err(internal_error_email)?;
+            if let Some(creds) = &email_config.smtp_credentials {
+                // synchronous version
+                // let mailer = lettre::SmtpTransport::starttls_relay(&email_config.relay).map_err(internal_error_email)?.credentials(creds).build();
+                use lettre::transport::smtp::AsyncSmtpTransport;
+                let mailer : AsyncSmtpTransport<lettre::Tokio1Executor> = AsyncSmtpTransport::<lettre::Tokio1Executor>::starttls_relay(&email_config.relay).map_err(internal_error_email)?.credentials(creds.clone()).build();
+                use lettre::AsyncTransport;
+                mailer.send(email).await.map_err(|e|{
+                    println!("Could not send email to {} because of {}",self.email,e);
+                    EmailValidationError::CouldNotSendEmail})?;
+            } else {
+                println!("No credentials for sending email found in config.toml. Can't send emails.")
+            }
+        } else {
+            println!("Consider this an email to {} with subject {} and body\n{}\nEnter email details in config.toml to actually send email",self.email,subject,body);
+        }
+        Ok(())
+    }
+
 
 
 }
@@ -557,7 +922,13 @@ impl RequestEmailValidation {
                     return Ok(RequestEmailValidationResult::AlreadyValidated(EmailProof::successful_verification(sig).await?));
                 }
             },
-            EmailValidationType::AccountRecovery => {}
+            EmailValidationType::AccountRecovery => {
+                if !is_valid_public_key(&sig.parsed.name) { return Err(EmailValidationError::InvalidPublicKey); }
+                match registered_email {
+                    Some(already_verified) if already_verified.matches(&sig.signed_message.unsigned) => {}
+                    _ => return Err(EmailValidationError::NotTheVerifiedEmail),
+                }
+            }
             EmailValidationType::AccountValidation => {
                 // Could check that it is not already validated.
                 if let Some(already_verified) = registered_email {
@@ -569,36 +940,9 @@ impl RequestEmailValidation {
         }
         let code : u32 = rand::thread_rng().gen_range(100000..1000000);
         sig.signed_message.unsigned.add_to_times_sent().await?;
-        let parsed_to : Mailbox = sig.signed_message.unsigned.email.parse().map_err(|_|EmailValidationError::InvalidEmailAddress)?;
-        if let Some(email_config) = &CONFIG.email {
-            let body = Self::EMAIL_BODY_TEMPLATE.replace("[USERNAME]",&sig.signed_message.user).replace("[CODE]",&code.to_string());
-            let body_html = Self::EMAIL_BODY_TEMPLATE_HTML.replace("[USERNAME]",&sig.signed_message.user).replace("[CODE]",&code.to_string());
-            let parsed_to = if let Some(overriding) = &email_config.testing_email_override { overriding.mailbox() } else { parsed_to };
-            let email = Message::builder()
-                .from(email_config.verification_from_email.mailbox())
-                .reply_to(email_config.verification_reply_to_email.mailbox())
-                .to(parsed_to)
-                .subject(Self::EMAIL_SUBJECT_LINE)
-                .multipart(MultiPart::alternative()
-                               .singlepart(SinglePart::plain(body))
-                               .singlepart(SinglePart::html(body_html))
-                          )
-                .map_err(internal_error_email)?;
-            if let Some(creds) = &email_config.smtp_credentials {
-                // synchronous version
-                // let mailer = lettre::SmtpTransport::starttls_relay(&email_config.relay).map_err(internal_error_email)?.credentials(creds).build();
-                use lettre::transport::smtp::AsyncSmtpTransport;
-                let mailer : AsyncSmtpTransport<lettre::Tokio1Executor> = AsyncSmtpTransport::<lettre::Tokio1Executor>::starttls_relay(&email_config.relay).map_err(internal_error_email)?.credentials(creds.clone()).build();
-                use lettre::AsyncTransport;
-                mailer.send(email).await.map_err(|e|{
-                    println!("Could not send email to {} because of {}",sig.signed_message.unsigned.email,e);
-                    EmailValidationError::CouldNotSendEmail})?;
-            } else {
-                println!("No credentials for sending email found in config.toml. Can't send emails.")
-            }
-        } else {
-            println!("Consider this an email to {} with code {}. Enter email details in config.toml to actually send email",sig.signed_message.unsigned.email,code);
-        }
+        let body = Self::EMAIL_BODY_TEMPLATE.replace("[USERNAME]",&sig.signed_message.user).replace("[CODE]",&code.to_string());
+        let body_html = Self::EMAIL_BODY_TEMPLATE_HTML.replace("[USERNAME]",&sig.signed_message.user).replace("[CODE]",&code.to_string());
+        sig.signed_message.unsigned.send_email(Self::EMAIL_SUBJECT_LINE,body,body_html).await?;
         let hash = {
             let data = serde_json::ser::to_string(&sig.signed_message).unwrap();
             let mut hasher = Sha256::default();
@@ -610,6 +954,12 @@ impl RequestEmailValidation {
         Ok(RequestEmailValidationResult::EmailSent(hash))
     }
 
+    /// If this is a request to recover an account, the new public key. The request (and the subsequent [EmailProof])
+    /// should be signed by this key rather than the lost one.
+    pub fn account_recovery_key(&self) -> Option<&PublicKey> {
+        if self.why==EmailValidationReason::AccountRecovery { Some(&self.name) } else { None }
+    }
+
     pub fn get_badge(sig : &ClientSigned<RequestEmailValidation,EmailAddress>) -> Result<Badge,EmailValidationError> {
         match &sig.parsed.why {
             EmailValidationReason::AsMP(principal) => {
@@ -696,6 +1046,11 @@ pub struct EmailProof {
 }
 
 impl EmailProof {
+    /// If this is a proof for an account recovery request, the new public key, which should have signed this proof.
+    pub fn account_recovery_key(&self) -> Option<PublicKey> {
+        EMAIL_VALIDATION_CODE_STORAGE.lock().unwrap().get(&self.hash).and_then(|(_,initial_request)|initial_request.parsed.account_recovery_key().cloned())
+    }
+
     /// Action the email proof. Assign the appropriate badge (or unassign as appropriate).
     pub async fn process(sig : &ClientSigned<EmailProof>) -> Result<Option<HashValue>, EmailValidationError> {
         if let Some((code,initial_request)) = EMAIL_VALIDATION_CODE_STORAGE.lock().unwrap().get(&sig.parsed.hash) {
@@ -709,6 +1064,7 @@ impl EmailProof {
     /// Call when someone has verified an email address.
     /// TODO it would be good to tell people they have been revoked, and by whom.
     pub async fn successful_verification(initial_request : &ClientSigned<RequestEmailValidation,EmailAddress>) -> Result<Option<HashValue>, EmailValidationError> {
+        let mut account_recovery_effective : Option<Timestamp> = None;
         let mut conn = get_rta_database_connection().await.map_err(internal_error_email)?;
         let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error_email)?;
         let user_id = get_user_id(&initial_request.signed_message.user,EmailValidationError::NoSuchUser,EmailValidationError::InternalError,&mut transaction)?;
@@ -726,7 +1082,14 @@ impl EmailProof {
                 badge.remove_from_database(revoked_user_id,&mut transaction).map_err(internal_error_email)?;
                 true
             },
-            EmailValidationType::AccountRecovery => {false} // TODO we haven't worked out how account recovery works yet.
+            EmailValidationType::AccountRecovery => {
+                let now = timestamp_now().map_err(internal_error_email)?;
+                let effective = now+CONFIG.account_recovery_delay_seconds;
+                let request = serde_json::to_string(&initial_request.signed_message.just_signed_part()).map_err(internal_error_email)?;
+                transaction.exec_drop("replace into PendingAccountRecovery (user_id,NewPublicKey,Requested,Effective,Request) values (?,?,?,?,?)",(user_id,&initial_request.parsed.name,now,effective,request)).map_err(internal_error_email)?;
+                account_recovery_effective=Some(effective);
+                false // stored on the bulletin board when completed.
+            }
             EmailValidationType::AccountValidation => {
                 let timestamp = timestamp_now().map_err(internal_error_email)?;
                 transaction.exec_drop("update USERS set VerifiedEmail=?,VerifiedEmailTimestamp=? where id=?",(&initial_request.signed_message.unsigned.email,timestamp,user_id)).map_err(internal_error_email)?;
@@ -734,6 +1097,10 @@ impl EmailProof {
             }
         };
         transaction.commit().map_err(internal_error_email)?;
+        if let Some(effective) = account_recovery_effective {
+            println!("Account recovery requested for uid={} new public key {} effective {}",initial_request.signed_message.user,initial_request.parsed.name,effective);
+            initial_request.signed_message.unsigned.send_account_recovery_notification(&initial_request.signed_message.user,effective).await?;
+        }
         if should_store_on_bulletin_board {
             let bb_hash = LogInBulletinBoard::EmailVerification(initial_request.signed_message.just_signed_part()).log_in_bulletin_board().await.map_err(bulletin_board_error_email)?;
             Ok(Some(bb_hash))
@@ -771,6 +1138,8 @@ impl EditUserDetails {
         if let Some(display_name) = &edits.parsed.display_name {
             if display_name.len()<1 { return Err(RegistrationError::DisplayNameTooShort); }
             if display_name.len()>60 { return Err(RegistrationError::DisplayNameTooLong); }
+            let mp_badge_names : Vec<String> = transaction.exec_map("SELECT what from BADGES where user_id=? and badge=?",(user_id,BadgeType::MP),|(name,)|name).map_err(internal_error)?;
+            check_display_name(display_name,&mp_badge_names)?;
             // Set display name
             transaction.exec_drop("update USERS set DisplayName=? where id=?", (display_name,user_id)).map_err(internal_error)?;
         }
@@ -797,12 +1166,21 @@ impl EditUserDetails {
 }
 
 
+/// Register a user with the default test key, for tests using the test databases.
+#[cfg(test)]
+pub async fn register_test_user(uid:&str) {
+    let registration = NewRegistration{ uid: uid.to_string(), display_name: None, public_key: crate::signing::DEFAULT_TESTING_PUBLIC_KEY.to_string(), state: None, electorates: vec![], proof_of_work: None };
+    registration.register().await.unwrap();
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::database::recreate_test_databases;
-    use crate::person::{EditUserDetails, get_user_by_id, NewRegistration, RegistrationError};
+    use merkle_tree_bulletin_board::hash::HashValue;
+    use mysql::prelude::Queryable;
+    use crate::database::{get_rta_database_connection, recreate_test_databases};
+    use crate::person::{AddDeviceKeyCommand, CancelAccountRecovery, register_test_user, RevokeDeviceKeyCommand, CompleteAccountRecovery, EditUserDetails, EMAIL_VALIDATION_CODE_STORAGE, EmailAddress, EmailProof, EmailValidationError, EmailValidationReason, get_user_by_id, NewRegistration, RegistrationError, RequestEmailValidation, RequestEmailValidationResult, RotateKeyCommand};
     use crate::regions::{Chamber, Electorate, State};
-    use crate::signing::{DEFAULT_TESTING_PUBLIC_KEY, make_test_signed};
+    use crate::signing::{DEFAULT_TESTING_PUBLIC_KEY, DEFAULT_TESTING_SECRET_KEY, make_test_key_pair, make_test_signed, make_test_signed_by_key, SignatureCheckError};
 
     async fn change_name_of_a_user(instance:usize) {
         println!("Starting change name {}",instance);
@@ -816,15 +1194,111 @@ mod tests {
         EditUserDetails::edit_user(&signed_change_name).await.unwrap();
         println!("Ending change name {}",instance);
     }
+    /// Request an email validation and send back the emailed code, both signed by `private_key`. The result is that of the proof.
+    async fn validate_email(uid:&str,why:EmailValidationReason,name:&str,email:&str,private_key:&str) -> Result<Option<HashValue>,EmailValidationError> {
+        let request = make_test_signed_by_key(uid,&RequestEmailValidation{ why, name: name.to_string() },private_key,EmailAddress{ email: email.to_string() });
+        let hash = match RequestEmailValidation::process(&request).await? {
+            RequestEmailValidationResult::EmailSent(hash) => hash,
+            RequestEmailValidationResult::AlreadyValidated(_) => panic!("Expected an email to be sent"),
+        };
+        let code = EMAIL_VALIDATION_CODE_STORAGE.lock().unwrap().get(&hash).unwrap().0;
+        EmailProof::process(&make_test_signed_by_key(uid,&EmailProof{ hash, code },private_key,())).await
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn can_add_and_revoke_device_keys() {
+        let _database = recreate_test_databases().await;
+        let uid = "device_user";
+        register_test_user(uid).await;
+        let (tablet_private_key,tablet_public_key) = make_test_key_pair(10);
+        let add = |private_key:&str,device_name:&str,public_key:&str| make_test_signed_by_key(uid,&AddDeviceKeyCommand{ device_name: device_name.to_string(), public_key: public_key.to_string() },private_key,());
+        let revoke = |private_key:&str,public_key:&str| make_test_signed_by_key(uid,&RevokeDeviceKeyCommand{ public_key: public_key.to_string() },private_key,());
+        AddDeviceKeyCommand::add_device_key(&add(DEFAULT_TESTING_SECRET_KEY,"Tablet",&tablet_public_key)).await.unwrap();
+        // either active key may sign.
+        for private_key in [DEFAULT_TESTING_SECRET_KEY,&tablet_private_key] {
+            assert!(revoke(private_key,&tablet_public_key).signed_message.check_signature(false).await.is_ok());
+        }
+        assert_eq!(AddDeviceKeyCommand::add_device_key(&add(&tablet_private_key,"Tablet again",&tablet_public_key)).await,Err(RegistrationError::KeyAlreadyInUse));
+        assert_eq!(AddDeviceKeyCommand::add_device_key(&add(&tablet_private_key,"Old phone",DEFAULT_TESTING_PUBLIC_KEY)).await,Err(RegistrationError::KeyAlreadyInUse));
+        let other_public_keys : Vec<String> = (11..19).map(|seed|make_test_key_pair(seed).1).collect();
+        for (i,public_key) in other_public_keys.iter().enumerate() {
+            AddDeviceKeyCommand::add_device_key(&add(&tablet_private_key,&format!("Device {}",i),public_key)).await.unwrap();
+        }
+        assert_eq!(AddDeviceKeyCommand::add_device_key(&add(&tablet_private_key,"One too many",&make_test_key_pair(19).1)).await,Err(RegistrationError::TooManyKeys));
+        assert_eq!(RevokeDeviceKeyCommand::revoke_device_key(&revoke(&tablet_private_key,&make_test_key_pair(19).1)).await,Err(RegistrationError::NoSuchKey));
+        // revoking the key in USERS.PublicKey moves it to one of the remaining keys.
+        let revoke_hash = RevokeDeviceKeyCommand::revoke_device_key(&revoke(&tablet_private_key,DEFAULT_TESTING_PUBLIC_KEY)).await.unwrap();
+        let ui = get_user_by_id(uid).await.unwrap().unwrap();
+        assert_ne!(DEFAULT_TESTING_PUBLIC_KEY,&ui.public_key);
+        assert!(ui.public_key==tablet_public_key || other_public_keys.contains(&ui.public_key));
+        assert!(ui.key_history[0].valid_until.is_some());
+        assert_eq!(Some(revoke_hash),ui.key_history[0].revocation_bulletin_board_hash);
+        assert!(ui.key_history[1..].iter().all(|k|k.valid_until.is_none() && k.revocation_bulletin_board_hash.is_none()));
+        assert!(matches!(revoke(DEFAULT_TESTING_SECRET_KEY,&tablet_public_key).signed_message.check_signature(false).await,Err(SignatureCheckError::BadSignature)));
+        assert!(revoke(&tablet_private_key,&tablet_public_key).signed_message.check_signature(false).await.is_ok());
+        // a key may revoke itself, but not the last one.
+        for (seed,public_key) in (11..19).zip(other_public_keys.iter()) {
+            RevokeDeviceKeyCommand::revoke_device_key(&revoke(&make_test_key_pair(seed).0,public_key)).await.unwrap();
+        }
+        assert_eq!(RevokeDeviceKeyCommand::revoke_device_key(&revoke(&tablet_private_key,&tablet_public_key)).await,Err(RegistrationError::CannotRevokeLastKey));
+        let ui = get_user_by_id(uid).await.unwrap().unwrap();
+        assert_eq!(tablet_public_key,ui.public_key);
+        assert_eq!(10,ui.key_history.iter().filter(|k|k.valid_until.is_some()).count());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn can_recover_account() {
+        let _database = recreate_test_databases().await;
+        let uid = "recovering_user";
+        let email = "recovering_user@example.com";
+        register_test_user(uid).await;
+        let (device_private_key,device_public_key) = make_test_key_pair(2);
+        AddDeviceKeyCommand::add_device_key(&make_test_signed(uid,&AddDeviceKeyCommand{ device_name: "Tablet".to_string(), public_key: device_public_key },()).await).await.unwrap();
+        assert_eq!(validate_email(uid,EmailValidationReason::AccountValidation,"",email,DEFAULT_TESTING_SECRET_KEY).await,Ok(None));
+        // The request and proof are signed by the new key, which the server checks instead of the user's keys.
+        let (new_private_key,new_public_key) = make_test_key_pair(3);
+        let request = make_test_signed_by_key(uid,&RequestEmailValidation{ why: EmailValidationReason::AccountRecovery, name: new_public_key.clone() },&new_private_key,EmailAddress{ email: email.to_string() });
+        assert_eq!(request.parsed.account_recovery_key(),Some(&new_public_key));
+        assert!(matches!(request.signed_message.check_signature(false).await,Err(SignatureCheckError::BadSignature)));
+        assert!(request.signed_message.check_signature_by_key(&new_public_key).is_ok());
+        let wrong_email = make_test_signed_by_key(uid,&RequestEmailValidation{ why: EmailValidationReason::AccountRecovery, name: new_public_key.clone() },&new_private_key,EmailAddress{ email: "someone_else@example.com".to_string() });
+        assert!(matches!(RequestEmailValidation::process(&wrong_email).await,Err(EmailValidationError::NotTheVerifiedEmail)));
+        assert_eq!(validate_email(uid,EmailValidationReason::AccountRecovery,&new_public_key,email,&new_private_key).await,Ok(None));
+        assert_eq!(CompleteAccountRecovery::pending_public_key(uid).await.unwrap(),Some(new_public_key.clone()));
+        let complete = || make_test_signed_by_key(uid,&CompleteAccountRecovery{},&new_private_key,());
+        assert_eq!(CompleteAccountRecovery::complete(&complete()).await,Err(RegistrationError::AccountRecoveryCoolingOff));
+        // the owner of the current key can cancel it.
+        CancelAccountRecovery::cancel(&make_test_signed(uid,&CancelAccountRecovery{},()).await).await.unwrap();
+        assert_eq!(CompleteAccountRecovery::complete(&complete()).await,Err(RegistrationError::NoAccountRecoveryPending));
+        assert_eq!(CancelAccountRecovery::cancel(&make_test_signed(uid,&CancelAccountRecovery{},()).await).await,Err(RegistrationError::NoAccountRecoveryPending));
+        // request again, and wait out the cooling off period.
+        assert_eq!(validate_email(uid,EmailValidationReason::AccountRecovery,&new_public_key,email,&new_private_key).await,Ok(None));
+        get_rta_database_connection().await.unwrap().exec_drop("update PendingAccountRecovery set Effective=0",()).unwrap();
+        let recovery_hash = CompleteAccountRecovery::complete(&complete()).await.unwrap();
+        for old_private_key in [DEFAULT_TESTING_SECRET_KEY,&device_private_key] {
+            let signed = make_test_signed_by_key::<CancelAccountRecovery,()>(uid,&CancelAccountRecovery{},old_private_key,());
+            assert!(matches!(signed.signed_message.check_signature(false).await,Err(SignatureCheckError::BadSignature)));
+        }
+        assert!(complete().signed_message.check_signature(false).await.is_ok());
+        let ui = get_user_by_id(uid).await.unwrap().unwrap();
+        assert_eq!(new_public_key,ui.public_key);
+        assert_eq!(3,ui.key_history.len());
+        assert!(ui.key_history[..2].iter().all(|k|k.valid_until.is_some() && k.revocation_bulletin_board_hash==Some(recovery_hash)));
+        assert_eq!(Some(recovery_hash),ui.key_history[2].bulletin_board_hash);
+        assert_eq!(None,ui.key_history[2].revocation_bulletin_board_hash);
+        assert_eq!(CompleteAccountRecovery::complete(&complete()).await,Err(RegistrationError::NoAccountRecoveryPending));
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
     async fn can_create_and_modify_user() {
-        recreate_test_databases().await;
+        let _database = recreate_test_databases().await;
         let registration = NewRegistration{
             uid: "test_user".to_string(),
             display_name: Some("Test User".to_string()),
             public_key: DEFAULT_TESTING_PUBLIC_KEY.to_string(),  // This is the test key used in the test website. Don't use it for a real user.
             state: Some(State::VIC),
             electorates: vec![Electorate{ chamber: Chamber::Australian_Senate, region: Some("VIC".to_string()) }],
+            proof_of_work: None,
         };
         registration.register().await.unwrap();
         assert_eq!(registration.register().await,Err(RegistrationError::UIDAlreadyTaken));
@@ -842,6 +1316,9 @@ mod tests {
         assert_eq!(Some(State::VIC),ui.state);
         assert_eq!(vec![Electorate{ chamber: Chamber::Australian_Senate, region: Some("VIC".to_string()) }],ui.electorates);
         assert_eq!(DEFAULT_TESTING_PUBLIC_KEY,&ui.public_key);
+        assert_eq!(1,ui.key_history.len());
+        assert_eq!(DEFAULT_TESTING_PUBLIC_KEY,&ui.key_history[0].public_key);
+        assert_eq!(None,ui.key_history[0].valid_until);
         let mut set = tokio::task::JoinSet::new();
         for i in 1..10 {
             set.spawn(async move { change_name_of_a_user(i).await });
@@ -851,5 +1328,26 @@ mod tests {
             set.spawn(async move { change_name_of_a_user(11).await });
         }
         while let Some(_x) = set.join_next().await { }
+        // rotate to a new key, signed by the old one.
+        let (new_private_key,new_public_key) = make_test_key_pair(1);
+        let rotate = RotateKeyCommand{ new_public_key: new_public_key.clone() };
+        let rotation_hash = RotateKeyCommand::rotate_key(&make_test_signed("test_user",&rotate,()).await).await.unwrap();
+        let signed_by_old = make_test_signed_by_key::<EditUserDetails,()>("test_user",&change_name,DEFAULT_TESTING_SECRET_KEY,());
+        assert!(matches!(signed_by_old.signed_message.check_signature(false).await,Err(SignatureCheckError::BadSignature)));
+        let signed_by_new = make_test_signed_by_key::<EditUserDetails,()>("test_user",&change_name,&new_private_key,());
+        assert!(signed_by_new.signed_message.check_signature(false).await.is_ok());
+        let ui = get_user_by_id("test_user").await.unwrap().unwrap();
+        assert_eq!(new_public_key,ui.public_key);
+        assert_eq!(2,ui.key_history.len());
+        assert_eq!(DEFAULT_TESTING_PUBLIC_KEY,&ui.key_history[0].public_key);
+        assert!(ui.key_history[0].valid_until.is_some());
+        assert!(ui.key_history[0].bulletin_board_hash.is_some());
+        assert_eq!(Some(rotation_hash),ui.key_history[0].revocation_bulletin_board_hash);
+        assert_eq!(new_public_key,ui.key_history[1].public_key);
+        assert_eq!(None,ui.key_history[1].valid_until);
+        assert_eq!(None,ui.key_history[1].revocation_bulletin_board_hash);
+        assert_eq!(Some(rotation_hash),ui.key_history[1].bulletin_board_hash);
+        let rotate_again = make_test_signed_by_key("test_user",&rotate,&new_private_key,());
+        assert_eq!(RotateKeyCommand::rotate_key(&rotate_again).await,Err(RegistrationError::NewKeySameAsOld));
     }
 }
\ No newline at end of file
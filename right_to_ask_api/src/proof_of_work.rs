@@ -0,0 +1,148 @@
+//! Hashcash style proof of work required to register a new user, making mass registration expensive
+//! without needing any third party captcha service.
+//!
+//! The client gets a [ProofOfWorkChallenge] from the server, then searches for a nonce such that
+//! SHA256(`challenge:uid:nonce`) starts with at least `difficulty` zero bits. The challenge and nonce are
+//! included in the [crate::person::NewRegistration] as a [ProofOfWorkSolution]. Each challenge may only be used once.
+//!
+//! The difficulty rises (each extra bit doubling the expected work) when registrations are coming in faster
+//! than the configured target rate. See [crate::config::ProofOfWorkConfig].
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use merkle_tree_bulletin_board::hash_history::{Timestamp, timestamp_now};
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
+use sha2::Digest;
+use crate::config::{CONFIG, ProofOfWorkConfig};
+use crate::time_limited_hashmap::TimeLimitedHashMap;
+
+/// How long a challenge may be used for after being issued.
+const CHALLENGE_LIFETIME : Duration = Duration::from_secs(30*60);
+
+/// The window over which the recent registration rate is measured.
+const REGISTRATION_RATE_WINDOW : Duration = Duration::from_secs(60*60);
+
+/// A challenge issued by the server.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct ProofOfWorkChallenge {
+    /// Random hex string.
+    pub challenge : String,
+    /// The required number of leading zero bits in the hash.
+    pub difficulty : u32,
+    /// The challenge may not be used after this time.
+    pub expires : Timestamp,
+}
+
+/// A solution to a [ProofOfWorkChallenge], included in a registration request.
+#[derive(Serialize,Deserialize,Debug,Clone,Eq,PartialEq)]
+pub struct ProofOfWorkSolution {
+    /// The challenge field of the [ProofOfWorkChallenge]
+    pub challenge : String,
+    /// Any string of at most 64 characters that makes the hash have enough leading zero bits.
+    pub nonce : String,
+}
+
+#[derive(Debug,Clone,Copy,Serialize,Deserialize,Eq,PartialEq)]
+pub enum ProofOfWorkError {
+    /// The challenge was never issued, has expired, or has already been used.
+    UnknownChallenge,
+    /// The hash does not have enough leading zero bits.
+    InsufficientWork,
+    NonceTooLong,
+}
+
+/// Challenges issued and not yet used, with their difficulty.
+static OUTSTANDING_CHALLENGES : Lazy<Mutex<TimeLimitedHashMap<String,u32>>> = Lazy::new(||Mutex::new(TimeLimitedHashMap::new(CHALLENGE_LIFETIME)));
+
+/// Times of recent successful registrations.
+static RECENT_REGISTRATIONS : Lazy<Mutex<VecDeque<Instant>>> = Lazy::new(||Mutex::new(VecDeque::new()));
+
+/// The number of leading zero bits in a hash.
+fn leading_zero_bits(hash:&[u8]) -> u32 {
+    let mut res = 0;
+    for b in hash {
+        res+=b.leading_zeros();
+        if *b!=0 { break; }
+    }
+    res
+}
+
+/// The hash that must have `difficulty` leading zero bits.
+fn work_hash(challenge:&str,uid:&str,nonce:&str) -> [u8;32] {
+    sha2::Sha256::digest(format!("{}:{}:{}",challenge,uid,nonce).as_bytes()).into()
+}
+
+/// The difficulty to use given the number of registrations in the last hour. Each doubling of the rate above the target adds one bit.
+fn difficulty_for_rate(config:&ProofOfWorkConfig,registrations_in_last_hour:usize) -> u32 {
+    let mut difficulty = config.min_difficulty;
+    let mut threshold = config.target_registrations_per_hour.max(1);
+    while registrations_in_last_hour>threshold && difficulty<config.max_difficulty {
+        difficulty+=1;
+        threshold*=2;
+    }
+    difficulty
+}
+
+fn registrations_in_last_hour() -> usize {
+    let mut recent = RECENT_REGISTRATIONS.lock().unwrap();
+    while recent.front().is_some_and(|t|t.elapsed()>REGISTRATION_RATE_WINDOW) { recent.pop_front(); }
+    recent.len()
+}
+
+/// Whether a proof of work is required in a registration request.
+pub fn proof_of_work_required() -> bool { CONFIG.proof_of_work.required }
+
+/// Make a new challenge, at a difficulty depending upon the recent registration rate.
+pub fn new_challenge() -> anyhow::Result<ProofOfWorkChallenge> {
+    let mut random = [0u8;16];
+    rand::thread_rng().fill_bytes(&mut random);
+    let challenge = hex::encode(random);
+    let difficulty = difficulty_for_rate(&CONFIG.proof_of_work,registrations_in_last_hour());
+    OUTSTANDING_CHALLENGES.lock().unwrap().insert(challenge.clone(),difficulty);
+    Ok(ProofOfWorkChallenge{ challenge, difficulty, expires: timestamp_now()?+CHALLENGE_LIFETIME.as_secs() })
+}
+
+impl ProofOfWorkSolution {
+    /// Check the solution for a registration of the given uid. The challenge is used up, even if the work is insufficient.
+    pub fn check(&self,uid:&str) -> Result<(),ProofOfWorkError> {
+        if self.nonce.len()>64 { return Err(ProofOfWorkError::NonceTooLong); }
+        let difficulty = {
+            let mut outstanding = OUTSTANDING_CHALLENGES.lock().unwrap();
+            let difficulty = *outstanding.get(&self.challenge).ok_or(ProofOfWorkError::UnknownChallenge)?;
+            outstanding.remove(&self.challenge);
+            difficulty
+        };
+        if leading_zero_bits(&work_hash(&self.challenge,uid,&self.nonce))>=difficulty { Ok(()) } else { Err(ProofOfWorkError::InsufficientWork) }
+    }
+}
+
+/// Note that a registration has succeeded, for the purpose of measuring the registration rate.
+pub fn record_registration() {
+    RECENT_REGISTRATIONS.lock().unwrap().push_back(Instant::now());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leading_zero_bits() {
+        assert_eq!(leading_zero_bits(&[0x80,0]),0);
+        assert_eq!(leading_zero_bits(&[0x01,0xff]),7);
+        assert_eq!(leading_zero_bits(&[0,0x10]),11);
+        assert_eq!(leading_zero_bits(&[0,0]),16);
+    }
+
+    #[test]
+    fn test_difficulty_for_rate() {
+        let config = ProofOfWorkConfig{ required: true, min_difficulty: 16, max_difficulty: 20, target_registrations_per_hour: 100 };
+        assert_eq!(difficulty_for_rate(&config,0),16);
+        assert_eq!(difficulty_for_rate(&config,100),16);
+        assert_eq!(difficulty_for_rate(&config,101),17);
+        assert_eq!(difficulty_for_rate(&config,401),19);
+        assert_eq!(difficulty_for_rate(&config,1000000),20);
+    }
+}
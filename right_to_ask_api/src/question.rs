@@ -22,15 +22,20 @@ use reqwest::Url;
 use sha2::{Digest, Sha256};
 use url::Host;
 use word_comparison::comparison_list::ScoredIDs;
+use crate::anonymous_voting::has_been_issued_vote_token;
 use crate::censorship::CensorshipStatus;
+use crate::lifecycle::{advance_lifecycle_for_edit, QuestionLifecycle};
+use crate::notifications::{raise_notification_event, NotificationEvent};
 use crate::committee::{CommitteeId, CommitteeIndexInDatabaseTable};
 use crate::common_file::COMMITTEES;
 use crate::config::CONFIG;
 use crate::database::{add_question_to_comparison_database, find_similar_text_question, get_rta_database_connection, LogInBulletinBoard};
 use crate::minister::{MinisterId, MinisterIndexInDatabaseTable};
 use crate::mp::{get_org_id_from_database, MPId, MPIndexInDatabaseTable, MPSpec, OrgIndexInDatabaseTable};
+use crate::regions::{Chamber, Electorate, Jurisdiction};
 use crate::person::{get_user_id, user_exists, UserID, UserUID};
 use crate::signing::ClientSigned;
+use crate::topic_tags::{add_topic_tags_for_question, check_may_add_topic_tags, check_topic_tags_exist, extra_topic_tags, questions_with_all_topic_tags, TopicTag};
 
 /// A question ID is a hash of the question text, the question writer, and the upload timestamp.
 /// It is NOT directly a node on the bulletin board; it is just using the bulletin board HashValue as that is a convenient way of representing a HashValue with serialization/deserialization/printing/debugging already handled.
@@ -79,6 +84,8 @@ pub enum QuestionError {
     InvalidUserSpecified,
     /// The question exists, but was censored.
     Censored,
+    /// Too many operations recently. See [crate::rate_limit].
+    RateLimited,
     /// The data in the bulletin board is not consistent and cannot be loaded.
     /// Note that old format data in the bulletin board can cause this.
     BulletinBoardHistoryIsCorrupt,
@@ -94,6 +101,62 @@ pub enum QuestionError {
     NoSuchUser,
     /// The user is reporting a question (or answer) for something already reported by that same user.
     AlreadyReported,
+    OnlyAuthorCanWithdraw,
+    /// A topic tag is not in the vocabulary. See [crate::topic_tags].
+    UnknownTopicTag,
+    TooManyTopicTags,
+    /// Only the author may add topic tags, unless topic_tags_permissions is Others.
+    OnlyAuthorCanChangeTopicTags,
+    /// The question has been withdrawn by its author, so cannot be changed or voted on.
+    QuestionWithdrawn,
+    /// vote_token_secret is not set in config.toml. See [crate::anonymous_voting].
+    AnonymousVotingNotEnabled,
+    /// The user has already been issued an anonymous vote token for this question, so cannot get another or vote in plain text.
+    VoteTokenAlreadyIssued,
+    /// An anonymous vote token or blinded point is malformed or not signed by the server.
+    InvalidVoteToken,
+    /// The amended answer is the same as the current answer.
+    AnswerUnchanged,
+    /// Only the author may propose that their question is a duplicate. Moderators use [crate::duplicates::MarkDuplicateCommand].
+    OnlyAuthorCanProposeDuplicate,
+    /// A question cannot be a duplicate of itself (or of one of its own duplicates).
+    CannotBeDuplicateOfItself,
+    /// The question has already been marked as a duplicate.
+    QuestionIsDuplicate,
+    /// The question cannot go directly from its current lifecycle status to the requested one.
+    InvalidLifecycleTransition,
+    /// The signer is not allowed to make this lifecycle change.
+    NotAllowedToChangeLifecycle,
+    /// A question can only be marked as answered if it has an answer or a Hansard link.
+    CantMarkAnsweredWithoutAnswer,
+    /// A webhook subscription needs an https URL.
+    InvalidWebhookURL,
+    /// Email notifications need a verified email address.
+    NoVerifiedEmail,
+    /// The user has [crate::notifications::MAX_SUBSCRIPTIONS_PER_USER] subscriptions already.
+    TooManySubscriptions,
+    /// The user has no subscription with the given id.
+    NoSuchSubscription,
+    /// The requested root is not a published root of the bulletin board.
+    NotAPublishedRoot,
+    /// Nothing has been published in the bulletin board yet.
+    NoPublishedRoot,
+    /// Another moderator has claimed this question in the moderation queue.
+    ClaimedByAnotherModerator,
+    /// The moderator does not have a claim on this question.
+    NotClaimed,
+    /// The question has no outstanding flags, so is not in the moderation queue.
+    NotFlagged,
+    /// A [crate::censorship::CensorQuestionCommand] gives more flags than are outstanding, as some have already been dealt with.
+    FlagsAlreadyModerated,
+    /// Trying to retract a vote on a question the user has not voted on.
+    NotVoted,
+    /// The vote was made before vote directions were recorded, so cannot be changed or retracted.
+    VoteDirectionUnknown,
+    /// More than [MAX_QUESTIONS_IN_BATCH_LOOKUP] questions were requested at once.
+    TooManyQuestionsRequested,
+    /// The cursor passed to [crate::question_list::QuestionListQuery] was not one returned by a previous query.
+    InvalidListCursor,
 }
 
 impl Display for QuestionError {
@@ -108,6 +171,8 @@ const MAX_BACKGROUND_LENGTH : usize = 280;
 const MAX_ANSWER_LENGTH : usize = 1000;
 const MAX_MPS_WHO_SHOULD_ASK_THE_QUESTION : usize = 10;
 const MAX_MPS_WHO_SHOULD_ANSWER_THE_QUESTION : usize = 10;
+/// The maximum number of questions that may be requested in one call to [QuestionInfo::lookup_many].
+pub const MAX_QUESTIONS_IN_BATCH_LOOKUP : usize = 100;
 
 
 /*************************************************************************
@@ -123,11 +188,11 @@ const MAX_MPS_WHO_SHOULD_ANSWER_THE_QUESTION : usize = 10;
 #[derive(Serialize,Deserialize,Debug,Clone)]
 pub struct QuestionDefiningFields {
     /// The UID of the person asking the question
-    author : UserUID,
+    pub(crate) author : UserUID,
     /// The actual text of the question.
-    question_text : String,
+    pub(crate) question_text : String,
     /// When the question was originally created.
-    timestamp : Timestamp,
+    pub(crate) timestamp : Timestamp,
 }
 
 impl QuestionDefiningFields {
@@ -224,6 +289,16 @@ impl PersonID {
         }
         Ok(res)
     }
+    /// The column in the PersonForQuestion table referring to this person, and the value it would have. None if the person is not in the database.
+    pub(crate) fn person_for_question_column(&self,conn:&mut impl Queryable) -> mysql::Result<Option<(&'static str,usize)>> {
+        Ok(match self {
+            PersonID::User(uid) => conn.exec_first("select id from USERS where UID=?",(uid,))?.map(|id|("UserId",id)),
+            PersonID::MP(mp_id) => mp_id.get_id_from_database_if_there(conn)?.map(|id|("MP",id)),
+            PersonID::Organisation(org) => conn.exec_first("select id from Organisations where OrgID=?",(org,))?.map(|id|("ORG",id)),
+            PersonID::Committee(committee_id) => committee_id.get_id_from_database_if_there(conn)?.map(|id|("Committee",id)),
+            PersonID::Minister(minister_id) => minister_id.get_id_from_database_if_there(conn)?.map(|id|("Minister",id)),
+        })
+    }
     /// Add the given people to a given question.
     fn add_for_question(conn:&mut impl Queryable,role:char,question:QuestionID,people:HashSet<&PersonID>) -> Result<(),QuestionError> {
         let mut references : Vec<(Option<UserID>,Option<MPIndexInDatabaseTable>,Option<OrgIndexInDatabaseTable>,Option<CommitteeIndexInDatabaseTable>,Option<MinisterIndexInDatabaseTable>)> = vec![];
@@ -285,7 +360,7 @@ impl PersonID {
 fn is_false(x:&bool) -> bool { !*x }
 fn is_not_flagged(x:&CensorshipStatus) -> bool { *x == CensorshipStatus::NotFlagged }
 
-#[derive(Serialize,Deserialize,Debug,Clone)]
+#[derive(Serialize,Deserialize,Debug,Clone,Default)]
 /// This contains the fields for the question that can be changed.
 ///
 /// It is used in two different ways with slightly different semantics
@@ -334,6 +409,12 @@ pub struct QuestionNonDefiningFields {
     /// Merge rule : may be changed from false to true.
     #[serde(skip_serializing_if = "is_false",default)]
     pub answer_accepted : bool,
+    /// The version of the answer the question-writer accepts, as in [QuestionAnswer::version]. The accepted answer also has [QuestionAnswer::accepted] set.
+    /// Validity : must be an uncensored answer to this question.
+    /// Permission: must be from the question-writer
+    /// Merge rule : overwrite. Also sets answer_accepted.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub accepted_answer : Option<HashValue>,
     /// Validity : domain must be aph.gov.au, parliament.vic.gov.au, etc. (preloaded permit-list - note that url sanitation is nontrivial).
     /// Permission: anyone can add
     /// Merge rule : same as mp_who_should_ask_the_question
@@ -344,6 +425,16 @@ pub struct QuestionNonDefiningFields {
     /// Merge rule:  Reject updates unless currently blank.  VT: Agree. Let's just have one at a time. People can make a linear chain. (Twitter actually allows a tree, and it's a complete pain. Lines are better.)
     #[serde(skip_serializing_if = "Option::is_none",default)]
     pub is_followup_to : Option<QuestionID>,
+    /// Validity: must be in the vocabulary of topic tags (see [crate::topic_tags]), at most MAX_TOPIC_TAGS_PER_QUESTION.
+    /// Permission: defined by topic_tags_permissions
+    /// Merge rule : same as mp_who_should_ask_the_question
+    /// In a [SimilarQuestionQuery], only questions with all these tags are returned.
+    #[serde(skip_serializing_if = "Vec::is_empty",default)]
+    pub topic_tags : Vec<TopicTag>,
+    /// Permission: must be from the question-writer
+    /// Merge rule: overwrite, unless it's 'NoChange'
+    #[serde(skip_serializing_if = "Permissions::is_no_change",default)]
+    pub topic_tags_permissions : Permissions,
 }
 
 pub type AnswerId = usize;
@@ -366,22 +457,17 @@ pub struct QuestionAnswer {
     /// set by server - client should not set this when sending to server.
     #[serde(skip_serializing_if = "Option::is_none",default)]
     pub version : Option<HashValue>,
+    /// If the answer has been amended with an [AmendAnswerCommand], when it was last amended. The original is in the question history.
+    /// set by server - client should not set this when sending to server.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub amended : Option<Timestamp>,
+    /// Whether the question's author has accepted this answer. See [QuestionNonDefiningFields::accepted_answer].
+    /// set by server - client should not set this when sending to server.
+    #[serde(skip_serializing_if = "is_false",default)]
+    pub accepted : bool,
 }
 
 impl QuestionAnswer {
-    /// Get the answers to a question.
-    fn get_for_question(conn:&mut impl Queryable,question:QuestionID) -> mysql::Result<Vec<QuestionAnswer>> {
-        let entries : Vec<(UserUID,MPIndexInDatabaseTable,Timestamp,String,CensorshipStatus,mysql::Value)> = conn.exec("SELECT USERS.UID,mp,timestamp,answer,CensorshipStatus,version from Answer inner join USERS ON Answer.AuthorId=USERS.id where QuestionId=? and CensorshipStatus!='Censored' order by timestamp",(&question.0,))?;
-        let mut res : Vec<QuestionAnswer> = vec![];
-        for (answered_by,mp,timestamp,answer,censorship_status,version) in entries {
-            if let Some(mp_id) = MPId::read_from_database(conn,mp)? {
-                res.push(QuestionAnswer{answered_by:Some(answered_by),mp:mp_id,answer,timestamp: Some(timestamp),censorship_status,version:opt_hash_from_value(version) })
-            } else {
-                eprintln!("Missing mp {} in question {} answer",mp,question);
-            }
-        }
-        Ok(res)
-    }
     /// Add a given answer to the database.
     fn add_for_question(&self,conn:&mut impl Queryable,question:QuestionID,timestamp:Timestamp,uid:&UserUID,version:HashValue) -> Result<(),QuestionError> {
         let mp = self.mp.get_id_from_database(conn).map_err(internal_error)?;
@@ -392,16 +478,25 @@ impl QuestionAnswer {
 
     fn check_legal(&self,conn:&mut impl Queryable,uid:&UserUID) -> Result<(),QuestionError> {
         if self.answer.len()>MAX_ANSWER_LENGTH { return Err(QuestionError::AnswerTooLong); }
-        if self.answered_by.is_some() || self.timestamp.is_some() || self.censorship_status!=CensorshipStatus::NotFlagged || self.version.is_some() { return Err(QuestionError::AnswerContainsUndesiredFields); }
-        let mps = MPSpec::get().map_err(internal_error)?;
-        if let Some(mp) = mps.find(&self.mp) {
-            let badges : usize = conn.exec_first("SELECT COUNT(badge) from BADGES inner join USERS ON BADGES.user_id=USERS.id where USERS.UID=? and BADGES.what=? and (BADGES.badge='MP' || BADGES.badge='MPStaff')",(uid,mp.badge_name())).map_err(internal_error)?.ok_or_else(||QuestionError::InternalError)?;
-            if badges==0 { return Err(QuestionError::UserDoesNotHaveCorrectMPBadge); }
-        } else  { return Err(QuestionError::InvalidMP); }
-        Ok(())
+        if self.answered_by.is_some() || self.timestamp.is_some() || self.censorship_status!=CensorshipStatus::NotFlagged || self.version.is_some() || self.amended.is_some() || self.accepted { return Err(QuestionError::AnswerContainsUndesiredFields); }
+        check_user_has_badge_for_mp(conn,uid,&self.mp)
     }
 
 }
+/// Check that the user has the MP or MPStaff badge for the given MP, and so may answer for them.
+pub(crate) fn check_user_has_badge_for_mp(conn:&mut impl Queryable,uid:&UserUID,mp:&MPId) -> Result<(),QuestionError> {
+    let mps = MPSpec::get().map_err(internal_error)?;
+    let badges : Vec<String> = conn.exec("SELECT BADGES.what from BADGES inner join USERS ON BADGES.user_id=USERS.id where USERS.UID=? and (BADGES.badge='MP' || BADGES.badge='MPStaff')",(uid,)).map_err(internal_error)?;
+    check_mp_badges(&mps,mp,&badges)
+}
+
+/// Check that `mp` is a current MP, and one of `badges` (the names on a user's MP or MPStaff badges) is for them.
+pub(crate) fn check_mp_badges(mps:&MPSpec,mp:&MPId,badges:&[String]) -> Result<(),QuestionError> {
+    let mp = mps.find(mp).ok_or(QuestionError::InvalidMP)?;
+    let badge_name = mp.badge_name();
+    if badges.contains(&badge_name) { Ok(()) } else { Err(QuestionError::UserDoesNotHaveCorrectMPBadge) }
+}
+
 ///  domain must be aph.gov.au, parliament.vic.gov.au, etc. (preloaded permit-list - note that url sanitation is nontrivial).
 #[derive(Serialize,Deserialize,Debug,Clone,Eq, PartialEq,Hash)]
 pub struct HansardLink {
@@ -489,6 +584,13 @@ impl QuestionNonDefiningFields {
         if !self.who_should_answer_the_question_permissions.is_no_change() {
             if !is_creator { return Err(QuestionError::OnlyAuthorCanChangePermissions); }
         }
+        if !self.topic_tags_permissions.is_no_change() && !is_creator { return Err(QuestionError::OnlyAuthorCanChangePermissions); }
+        if !self.topic_tags.is_empty() {
+            check_may_add_topic_tags(is_creator,existing.map(|e|e.non_defining.topic_tags_permissions))?;
+            extra_topic_tags(existing.map(|e|&e.non_defining.topic_tags[..]).unwrap_or(&[]),&self.topic_tags)?;
+            let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+            check_topic_tags_exist(&mut conn,&self.topic_tags)?;
+        }
         if !self.mp_who_should_ask_the_question.is_empty() {
             let existing = existing.iter().flat_map(|e|e.non_defining.mp_who_should_ask_the_question.iter()).collect::<HashSet<_>>();
             let extra : HashSet<_> = self.mp_who_should_ask_the_question.iter().filter(|m|!existing.contains(m)).collect();
@@ -503,19 +605,30 @@ impl QuestionNonDefiningFields {
             let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
             for e in extra { e.check_sane(&mut conn)? }
         }
+        self.check_answer_acceptance(is_creator,existing.map(|e|&e.non_defining))?;
+
+        if !self.hansard_link.is_empty() {
+            for link in &self.hansard_link {
+                link.check_ok()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the answer_accepted and accepted_answer fields of an update to the `existing` question (None if new).
+    fn check_answer_acceptance(&self,is_creator:bool,existing:Option<&QuestionNonDefiningFields>) -> Result<(),QuestionError> {
         if self.answer_accepted {
             if let Some(existing) = existing {
-                if !existing.non_defining.answer_accepted {
+                if !existing.answer_accepted {
                     if !is_creator { return Err(QuestionError::OnlyAuthorCanAcceptAnswer); }
-                    if !existing.non_defining.answers.is_empty() { return Err(QuestionError::CantAcceptAnswerIfNonePresent)}
+                    if existing.answers.is_empty() { return Err(QuestionError::CantAcceptAnswerIfNonePresent)}
                 }
             } else { return Err(QuestionError::CantAcceptAnswerIfNonePresent)}
         }
-
-        if !self.hansard_link.is_empty() {
-            for link in &self.hansard_link {
-                link.check_ok()?;
-            }
+        if let Some(accepted_answer) = self.accepted_answer {
+            if !is_creator { return Err(QuestionError::OnlyAuthorCanAcceptAnswer); }
+            let existing = existing.ok_or(QuestionError::CantAcceptAnswerIfNonePresent)?;
+            if !existing.answers.iter().any(|a|a.version==Some(accepted_answer)) { return Err(QuestionError::NotAnUncensoredAnswer); }
         }
         Ok(())
     }
@@ -535,6 +648,12 @@ impl QuestionNonDefiningFields {
         if !self.who_should_answer_the_question_permissions.is_no_change() {
             transaction.exec_drop("update QUESTIONS set CanOthersSetWhoShouldAnswer=? where QuestionID=?", (self.who_should_answer_the_question_permissions==Permissions::Others,question_id.0)).map_err(internal_error)?;
         }
+        if !self.topic_tags_permissions.is_no_change() {
+            transaction.exec_drop("update QUESTIONS set CanOthersSetTopicTags=? where QuestionID=?", (self.topic_tags_permissions==Permissions::Others,question_id.0)).map_err(internal_error)?;
+        }
+        if !self.topic_tags.is_empty() {
+            add_topic_tags_for_question(transaction,question_id,&self.topic_tags)?;
+        }
         if !self.mp_who_should_ask_the_question.is_empty() {
             let existing = PersonID::get_for_question(transaction,'Q',question_id).map_err(internal_error)?.into_iter().collect::<HashSet<_>>();
             let extra : HashSet<_> = self.mp_who_should_ask_the_question.iter().filter(|&m|!existing.contains(m)).collect();
@@ -549,22 +668,35 @@ impl QuestionNonDefiningFields {
         }
         if let Some(follow_up_to) = self.is_followup_to {
             transaction.exec_drop("update QUESTIONS set FollowUpTo=? where QuestionID=?", (follow_up_to.0,question_id.0)).map_err(internal_error)?;
+            raise_notification_event(transaction,follow_up_to,NotificationEvent::FollowUpPosted,Some(question_id),None,timestamp)?;
         }
         for a in &self.answers {
             a.add_for_question(transaction,question_id,timestamp,uid,new_version)?;
         }
-        if self.answer_accepted {
+        if !self.answers.is_empty() {
+            raise_notification_event(transaction,question_id,NotificationEvent::AnswerAdded,Some(new_version),None,timestamp)?;
+        }
+        if self.answer_accepted || self.accepted_answer.is_some() {
             // There could be optimization here checking if it was already set.
             transaction.exec_drop("update QUESTIONS set AnswerAccepted=true where QuestionID=?", (question_id.0,)).map_err(internal_error)?;
         }
+        if let Some(accepted_answer) = self.accepted_answer {
+            transaction.exec_drop("update Answer set Accepted=IF(version=?,TRUE,FALSE) where QuestionId=?", (accepted_answer.0,question_id.0)).map_err(internal_error)?;
+        }
+        let mut added_hansard_link = false;
         if !self.hansard_link.is_empty() {
             // remove duplicates
             let existing = HansardLink::get_for_question(transaction,question_id).map_err(internal_error)?.into_iter().collect::<HashSet<_>>();
             let extra : Vec<_> = self.hansard_link.iter().filter(|&m|!existing.contains(m)).collect();
             if !extra.is_empty() {
+                added_hansard_link = true;
                 HansardLink::add_for_question(transaction,question_id,&extra).map_err(internal_error)?;
             }
+            for link in extra {
+                raise_notification_event(transaction,question_id,NotificationEvent::HansardLinkAdded,None,Some(link.url.as_str()),timestamp)?;
+            }
         }
+        advance_lifecycle_for_edit(transaction,question_id,!self.answers.is_empty(),added_hansard_link,uid,timestamp,new_version)?;
         Ok(())
     }
 
@@ -749,6 +881,21 @@ pub struct QuestionInfo {
     /// upvotes-downvotes.
     pub(crate) net_votes : i32,
     pub(crate) censorship_status : CensorshipStatus,
+    /// If the author has withdrawn the question, when. See [WithdrawQuestionCommand].
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub(crate) withdrawn : Option<Timestamp>,
+    /// If a moderator has marked this as a duplicate, the canonical question. See [crate::duplicates].
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub(crate) duplicate_of : Option<QuestionID>,
+    /// A duplicate proposed by the author, waiting for a moderator to confirm.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub(crate) proposed_duplicate_of : Option<QuestionID>,
+    /// The questions marked as duplicates of this one.
+    #[serde(skip_serializing_if = "Vec::is_empty",default)]
+    pub(crate) duplicates : Vec<QuestionID>,
+    /// Where the question is up to. See [crate::lifecycle].
+    #[serde(default)]
+    pub(crate) lifecycle : QuestionLifecycle,
 }
 
 /// Convert v into a HashValue where you know v will be a 32 byte value
@@ -775,42 +922,104 @@ fn opt_hash_from_value(v:mysql::Value) -> Option<HashValue> {
 impl QuestionInfo {
     /// Get information about a question from the database.
     pub async fn lookup(question_id:QuestionID) -> Result<Option<QuestionInfo>,QuestionError> {
+        if let Some(info) = Self::lookup_many(&[question_id]).await?.pop() { return Ok(Some(info)); }
+        // lookup_many leaves out censored questions, which are an error here.
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let censorship_status : Option<CensorshipStatus> = conn.exec_first("SELECT CensorshipStatus from QUESTIONS where QuestionID=?",(question_id.0,)).map_err(internal_error)?;
+        if censorship_status==Some(CensorshipStatus::Censored) { Err(QuestionError::Censored) } else { Ok(None) }
+    }
+
+    /// Get information about a set of questions from the database, in the order requested.
+    /// Questions that do not exist or have been censored are omitted.
+    /// Unlike calling [QuestionInfo::lookup] for each, this uses a fixed number of queries, each covering all the questions.
+    pub async fn lookup_many(question_ids:&[QuestionID]) -> Result<Vec<QuestionInfo>,QuestionError> {
+        if question_ids.len()>MAX_QUESTIONS_IN_BATCH_LOOKUP { return Err(QuestionError::TooManyQuestionsRequested); }
+        if question_ids.is_empty() { return Ok(vec![]); }
         let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
-        // mysql crate only handles tuples up to 12 elements. We have 13.
+        let in_list = format!("({})",vec!["?";question_ids.len()].join(","));
+        let params : Vec<mysql::Value> = question_ids.iter().map(|q|q.0.to_vec().into()).collect();
+        // Load the people, answers, links and tags for all the questions, grouped by question.
+        let mut askers : HashMap<QuestionID,Vec<PersonID>> = HashMap::new();
+        let mut answerers : HashMap<QuestionID,Vec<PersonID>> = HashMap::new();
+        type PersonRow = (mysql::Value,String,Option<UserUID>,Option<Chamber>,Option<String>,Option<String>,Option<String>,Option<OrgID>,Option<Jurisdiction>,Option<String>,Option<Jurisdiction>,Option<String>);
+        let people : Vec<PersonRow> = conn.exec(format!("SELECT PersonForQuestion.QuestionId,ROLE,USERS.UID,MP_IDs.Chamber,MP_IDs.Electorate,MP_IDs.FirstName,MP_IDs.LastName,Organisations.OrgID,Committee_IDs.Jurisdiction,Committee_IDs.Name,Minister_IDs.Jurisdiction,Minister_IDs.Name from PersonForQuestion left join USERS on PersonForQuestion.UserId=USERS.id left join MP_IDs on PersonForQuestion.MP=MP_IDs.id left join Organisations on PersonForQuestion.ORG=Organisations.id left join Committee_IDs on PersonForQuestion.Committee=Committee_IDs.id left join Minister_IDs on PersonForQuestion.Minister=Minister_IDs.id where PersonForQuestion.QuestionId in {}",in_list),params.clone()).map_err(internal_error)?;
+        for (question,role,uid,chamber,region,first_name,surname,org,committee_jurisdiction,committee_name,minister_jurisdiction,minister_name) in people {
+            let question = hash_from_value(question);
+            let person = if let Some(uid) = uid { PersonID::User(uid) }
+                else if let (Some(chamber),Some(first_name),Some(surname)) = (chamber,first_name,surname) { PersonID::MP(MPId{ first_name, surname, electorate: Electorate{ chamber, region } }) }
+                else if let (Some(jurisdiction),Some(name)) = (committee_jurisdiction,committee_name) { PersonID::Committee(CommitteeId{ jurisdiction, name }) }
+                else if let Some(org) = org { PersonID::Organisation(org) }
+                else if let (Some(jurisdiction),Some(name)) = (minister_jurisdiction,minister_name) { PersonID::Minister(MinisterId{ jurisdiction, name }) }
+                else {
+                    eprintln!("Blank or missing person for question {} role {}",question,role);
+                    continue;
+                };
+            (if role=="Q" { &mut askers } else { &mut answerers }).entry(question).or_default().push(person);
+        }
+        let mut answers : HashMap<QuestionID,Vec<QuestionAnswer>> = HashMap::new();
+        type AnswerRow = (mysql::Value,UserUID,Chamber,Option<String>,String,String,Timestamp,String,CensorshipStatus,mysql::Value,Option<Timestamp>,bool);
+        let answer_rows : Vec<AnswerRow> = conn.exec(format!("SELECT Answer.QuestionId,USERS.UID,MP_IDs.Chamber,MP_IDs.Electorate,MP_IDs.FirstName,MP_IDs.LastName,timestamp,answer,Answer.CensorshipStatus,version,AmendedTimestamp,Accepted from Answer inner join USERS ON Answer.AuthorId=USERS.id inner join MP_IDs on Answer.MP=MP_IDs.id where Answer.QuestionId in {} and Answer.CensorshipStatus!='Censored' order by timestamp",in_list),params.clone()).map_err(internal_error)?;
+        for (question,answered_by,chamber,region,first_name,surname,timestamp,answer,censorship_status,version,amended,accepted) in answer_rows {
+            let mp = MPId{ first_name, surname, electorate: Electorate{ chamber, region } };
+            answers.entry(hash_from_value(question)).or_default().push(QuestionAnswer{answered_by:Some(answered_by),mp,answer,timestamp: Some(timestamp),censorship_status,version:opt_hash_from_value(version),amended,accepted });
+        }
+        let mut hansard_links : HashMap<QuestionID,Vec<HansardLink>> = HashMap::new();
+        for (question,url) in conn.exec::<(mysql::Value,String),_,_>(format!("SELECT QuestionId,url from HansardLink where QuestionId in {}",in_list),params.clone()).map_err(internal_error)? {
+            hansard_links.entry(hash_from_value(question)).or_default().push(HansardLink{url});
+        }
+        let mut topic_tags : HashMap<QuestionID,Vec<TopicTag>> = HashMap::new();
+        for (question,tag) in conn.exec::<(mysql::Value,TopicTag),_,_>(format!("select QuestionId,Tag from QuestionTags inner join TopicTags on QuestionTags.TagId=TopicTags.id where QuestionId in {} order by Tag",in_list),params.clone()).map_err(internal_error)? {
+            topic_tags.entry(hash_from_value(question)).or_default().push(tag);
+        }
+        let mut duplicates : HashMap<QuestionID,Vec<QuestionID>> = HashMap::new();
+        for (question,canonical) in conn.exec::<(mysql::Value,mysql::Value),_,_>(format!("select QuestionId,DuplicateOf from QUESTIONS where DuplicateOf in {}",in_list),params.clone()).map_err(internal_error)? {
+            duplicates.entry(hash_from_value(canonical)).or_default().push(hash_from_value(question));
+        }
+        // Now the questions themselves.
+        // mysql crate only handles tuples up to 12 elements. We have 19.
         // Use less pleasant HList another way to handle wide rows is to use HList (requires `mysql_common/frunk` feature)
         use mysql_common::frunk::{HList, hlist_pat};
-        type RowType = HList!(String, Timestamp, Timestamp, mysql::Value, String, Option<String>, bool, bool, bool,  mysql::Value, CensorshipStatus,u32,i32);
-        if let Some(hlist_pat![question_text,timestamp,last_modified,version,author,background,who_should_ask_the_question_permissions,who_should_answer_the_question_permissions,answer_accepted,is_followup_to,censorship_status,total_votes,net_votes]) = conn.exec_first::<RowType,_,_>("SELECT Question,CreatedTimestamp,LastModifiedTimestamp,Version,USERS.UID,Background,CanOthersSetWhoShouldAsk,CanOthersSetWhoShouldAnswer,AnswerAccepted,FollowUpTo,CensorshipStatus,TotalVotes,NetVotes from QUESTIONS inner join USERS ON CreatedById=USERS.id where QuestionID=?",(question_id.0,)).map_err(internal_error)? {
-            if censorship_status==CensorshipStatus::Censored { return Err(QuestionError::Censored); }
-            match opt_hash_from_value(version) {
-                None => Ok(None),
-                Some(version) => {
-                    Ok(Some(QuestionInfo{
-                        defining: QuestionDefiningFields { author, question_text, timestamp },
-                        non_defining: QuestionNonDefiningFields {
-                            background, // : convert_null_allowed_value_to_option(background),
-                            mp_who_should_ask_the_question : PersonID::get_for_question(&mut conn,'Q',question_id).map_err(internal_error)?,
-                            who_should_ask_the_question_permissions: if who_should_ask_the_question_permissions { Permissions::Others } else { Permissions::WriterOnly } ,
-                            entity_who_should_answer_the_question: PersonID::get_for_question(&mut conn,'A',question_id).map_err(internal_error)?,
-                            who_should_answer_the_question_permissions: if who_should_answer_the_question_permissions { Permissions::Others } else { Permissions::WriterOnly } ,
-                            answers: QuestionAnswer::get_for_question(&mut conn,question_id).map_err(internal_error)?,
-                            answer_accepted,
-                            hansard_link: HansardLink::get_for_question(&mut conn,question_id).map_err(internal_error)?,
-                            is_followup_to : opt_hash_from_value(is_followup_to),
-                        },
-                        question_id,
-                        version,
-                        last_modified,
-                        total_votes,
-                        net_votes,
-                        censorship_status,
-                    }))
-                }
-            }
-        } else { Ok(None) }
+        type RowType = HList!(mysql::Value, String, Timestamp, Timestamp, mysql::Value, String, Option<String>, bool, bool, bool,  mysql::Value, CensorshipStatus,u32,i32,Option<Timestamp>,bool,mysql::Value,mysql::Value,QuestionLifecycle);
+        let rows : Vec<RowType> = conn.exec(format!("SELECT QuestionID,Question,CreatedTimestamp,LastModifiedTimestamp,Version,USERS.UID,Background,CanOthersSetWhoShouldAsk,CanOthersSetWhoShouldAnswer,AnswerAccepted,FollowUpTo,CensorshipStatus,TotalVotes,NetVotes,WithdrawnTimestamp,CanOthersSetTopicTags,DuplicateOf,ProposedDuplicateOf,Lifecycle from QUESTIONS inner join USERS ON CreatedById=USERS.id where QuestionID in {} and CensorshipStatus!='Censored'",in_list),params).map_err(internal_error)?;
+        let mut found : HashMap<QuestionID,QuestionInfo> = HashMap::new();
+        for hlist_pat![question_id,question_text,timestamp,last_modified,version,author,background,who_should_ask_the_question_permissions,who_should_answer_the_question_permissions,answer_accepted,is_followup_to,censorship_status,total_votes,net_votes,withdrawn,topic_tags_permissions,duplicate_of,proposed_duplicate_of,lifecycle] in rows {
+            let question_id = hash_from_value(question_id);
+            let Some(version) = opt_hash_from_value(version) else { continue };
+            let answers_for_question = answers.remove(&question_id).unwrap_or_default();
+            found.insert(question_id,QuestionInfo{
+                defining: QuestionDefiningFields { author, question_text, timestamp },
+                non_defining: QuestionNonDefiningFields {
+                    background,
+                    mp_who_should_ask_the_question : askers.remove(&question_id).unwrap_or_default(),
+                    who_should_ask_the_question_permissions: if who_should_ask_the_question_permissions { Permissions::Others } else { Permissions::WriterOnly } ,
+                    entity_who_should_answer_the_question: answerers.remove(&question_id).unwrap_or_default(),
+                    who_should_answer_the_question_permissions: if who_should_answer_the_question_permissions { Permissions::Others } else { Permissions::WriterOnly } ,
+                    accepted_answer: answers_for_question.iter().find(|a|a.accepted).and_then(|a|a.version),
+                    answers: answers_for_question,
+                    answer_accepted,
+                    hansard_link: hansard_links.remove(&question_id).unwrap_or_default(),
+                    is_followup_to : opt_hash_from_value(is_followup_to),
+                    topic_tags: topic_tags.remove(&question_id).unwrap_or_default(),
+                    topic_tags_permissions: if topic_tags_permissions { Permissions::Others } else { Permissions::WriterOnly },
+                },
+                question_id,
+                version,
+                last_modified,
+                total_votes,
+                net_votes,
+                censorship_status,
+                withdrawn,
+                duplicate_of: opt_hash_from_value(duplicate_of),
+                proposed_duplicate_of: opt_hash_from_value(proposed_duplicate_of),
+                duplicates: duplicates.remove(&question_id).unwrap_or_default(),
+                lifecycle,
+            });
+        }
+        // If the same question was requested twice, only return it once.
+        Ok(question_ids.iter().filter_map(|q|found.remove(q)).collect())
     }
 
-    /// This should be replaced by something that gets a smaller list.
+    /// This should be replaced by something that gets a smaller list, such as [crate::question_list::QuestionListQuery].
     pub async fn get_list_of_all_questions() -> mysql::Result<Vec<QuestionID>> {
         let mut conn = get_rta_database_connection().await?;
         let elements : Vec<QuestionID> = conn.exec_map("SELECT QuestionID from QUESTIONS ORDER BY LastModifiedTimestamp DESC",(),|(v,)|hash_from_value(v))?;
@@ -884,6 +1093,7 @@ impl EditQuestionCommand {
     pub async fn edit(command:&ClientSigned<EditQuestionCommand>) -> Result<LastQuestionUpdate,QuestionError> {
         let question_info = QuestionInfo::lookup(command.parsed.question_id).await?.ok_or_else(||QuestionError::QuestionDoesNotExist)?;
         if question_info.version!=command.parsed.version { return Err(QuestionError::LastUpdateIsNotCurrent); }
+        if question_info.withdrawn.is_some() { return Err(QuestionError::QuestionWithdrawn); }
         let is_creator = question_info.defining.author == command.signed_message.user;
         command.parsed.edits.check_legal(is_creator,&command.signed_message.user,Some(&question_info)).await?;
         let timestamp = timestamp_now().map_err(internal_error)?;
@@ -901,8 +1111,106 @@ impl EditQuestionCommand {
     }
 }
 
+/// Withdraw a question. Only the author may do this.
+///
+/// The question is not deleted - it, its votes and its answers are still available - but it is
+/// marked as withdrawn, cannot be edited or voted on, and is no longer offered as a similar question.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct WithdrawQuestionCommand {
+    pub question_id : QuestionID,
+    /// The hash value defining the last update done to the question. This is checked to prevent clashes with edits.
+    pub version : LastQuestionUpdate,
+}
+
+/// The structure posted to the bulletin board in response to a WithdrawQuestionCommand.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct WithdrawQuestionCommandPostedToBulletinBoard {
+    pub command : ClientSigned<WithdrawQuestionCommand>,
+    pub timestamp : Timestamp,
+    /// This will be a link to the prior node in the database.
+    pub prior : LastQuestionUpdate,
+}
+
+impl WithdrawQuestionCommand {
+    /// Withdraw the question, returning the new version.
+    pub async fn withdraw(command:&ClientSigned<WithdrawQuestionCommand>) -> Result<LastQuestionUpdate,QuestionError> {
+        let question_info = QuestionInfo::lookup(command.parsed.question_id).await?.ok_or(QuestionError::QuestionDoesNotExist)?;
+        if question_info.version!=command.parsed.version { return Err(QuestionError::LastUpdateIsNotCurrent); }
+        if question_info.defining.author != command.signed_message.user { return Err(QuestionError::OnlyAuthorCanWithdraw); }
+        if question_info.withdrawn.is_some() { return Err(QuestionError::QuestionWithdrawn); }
+        let timestamp = timestamp_now().map_err(internal_error)?;
+        let for_bb = WithdrawQuestionCommandPostedToBulletinBoard {
+            command: command.clone(),
+            timestamp,
+            prior : command.parsed.version,
+        };
+        let version = LogInBulletinBoard::WithdrawQuestion(for_bb).log_in_bulletin_board().await.map_err(bulletin_board_error)?;
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error)?;
+        modify_question_database_version_and_time(&mut transaction,command.parsed.question_id,version,Some(command.parsed.version),timestamp).await?;
+        transaction.exec_drop("update QUESTIONS set WithdrawnTimestamp=? where QuestionID=?", (timestamp,command.parsed.question_id.0)).map_err(internal_error)?;
+        transaction.commit().map_err(internal_error)?;
+        println!("Question {} withdrawn by {}",command.parsed.question_id,command.signed_message.user);
+        Ok(version)
+    }
+}
+
+/// Amend an existing answer, e.g. to correct a factual error. The signer must have the MP or MPStaff badge for the MP who answered.
+///
+/// The answer keeps its version (which identifies it for reporting and censorship), but its text is replaced and it is marked as amended.
+/// The original stays in the question history. If a moderator had allowed the answer, it goes back to StructureChanged,
+/// like the question does in [modify_question_database_version_and_time].
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct AmendAnswerCommand {
+    pub question_id : QuestionID,
+    /// The hash value defining the last update done to the question. This is checked to prevent clashes with edits.
+    pub version : LastQuestionUpdate,
+    /// The version of the answer being amended, as in [QuestionAnswer::version].
+    pub answer_version : HashValue,
+    /// The new text of the answer.
+    pub answer : String,
+}
+
+/// The structure posted to the bulletin board in response to an AmendAnswerCommand.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct AmendAnswerCommandPostedToBulletinBoard {
+    pub command : ClientSigned<AmendAnswerCommand>,
+    pub timestamp : Timestamp,
+    /// This will be a link to the prior node in the database.
+    pub prior : LastQuestionUpdate,
+}
+
+impl AmendAnswerCommand {
+    /// Amend the answer, returning the new version of the question.
+    pub async fn amend(command:&ClientSigned<AmendAnswerCommand>) -> Result<LastQuestionUpdate,QuestionError> {
+        let question_info = QuestionInfo::lookup(command.parsed.question_id).await?.ok_or(QuestionError::QuestionDoesNotExist)?;
+        if question_info.version!=command.parsed.version { return Err(QuestionError::LastUpdateIsNotCurrent); }
+        if question_info.withdrawn.is_some() { return Err(QuestionError::QuestionWithdrawn); }
+        let existing = question_info.non_defining.answers.iter().find(|a|a.version==Some(command.parsed.answer_version)).ok_or(QuestionError::NotAnUncensoredAnswer)?;
+        if command.parsed.answer.len()>MAX_ANSWER_LENGTH { return Err(QuestionError::AnswerTooLong); }
+        if command.parsed.answer==existing.answer { return Err(QuestionError::AnswerUnchanged); }
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        check_user_has_badge_for_mp(&mut conn,&command.signed_message.user,&existing.mp)?;
+        let timestamp = timestamp_now().map_err(internal_error)?;
+        let for_bb = AmendAnswerCommandPostedToBulletinBoard {
+            command: command.clone(),
+            timestamp,
+            prior : command.parsed.version,
+        };
+        let version = LogInBulletinBoard::AmendAnswer(for_bb).log_in_bulletin_board().await.map_err(bulletin_board_error)?;
+        let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error)?;
+        modify_question_database_version_and_time(&mut transaction,command.parsed.question_id,version,Some(command.parsed.version),timestamp).await?;
+        transaction.exec_drop("update Answer set answer=?,AmendedTimestamp=?,AmendedVersion=?,CensorshipStatus = IF(CensorshipStatus='Allowed','StructureChanged',CensorshipStatus) where QuestionId=? and version=?", (&command.parsed.answer,timestamp,version.0,command.parsed.question_id.0,command.parsed.answer_version.0)).map_err(internal_error)?;
+        transaction.commit().map_err(internal_error)?;
+        println!("Answer {} to question {} amended by {}",command.parsed.answer_version,command.parsed.question_id,command.signed_message.user);
+        Ok(version)
+    }
+}
+
 /// Vote on a question
 /// This a placeholder plain-text voting while the crypto is being worked out.
+/// Voting again in the other direction changes the vote. See also [PlainTextRetractVoteCommand].
+/// For voting without the server knowing who voted, see [crate::anonymous_voting].
 #[derive(Serialize,Deserialize,Debug,Clone)]
 pub struct PlainTextVoteOnQuestionCommand {
     /// The hashvalue that defines the unique ID of the question to be modified
@@ -927,8 +1235,23 @@ impl PlainTextVoteOnQuestionCommand {
         let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
         let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error)?;
         let user_id = get_user_id(&command.signed_message.user,QuestionError::NoSuchUser,QuestionError::InternalError,&mut transaction)?;
-        let times_voted = transaction.exec_first::<u32, _, _>("select count(*) from HAS_VOTED where QuestionId=? and VoterId=?", (command.parsed.question_id.0, user_id)).map_err(internal_error)?.ok_or(QuestionError::InternalError)?;
-        if times_voted > 0 { return Err(QuestionError::AlreadyVoted) }
+        // Lock the question so concurrent votes by the same user are applied one after the other.
+        let withdrawn = transaction.exec_first::<Option<Timestamp>, _, _>("select WithdrawnTimestamp from QUESTIONS where QuestionID=? for update", (command.parsed.question_id.0,)).map_err(internal_error)?.ok_or(QuestionError::QuestionDoesNotExist)?;
+        if withdrawn.is_some() { return Err(QuestionError::QuestionWithdrawn) }
+        if has_been_issued_vote_token(&mut transaction,command.parsed.question_id,user_id)? { return Err(QuestionError::VoteTokenAlreadyIssued) }
+        let previous_vote = transaction.exec_first::<Option<bool>, _, _>("select Up from HAS_VOTED where QuestionId=? and VoterId=? for update", (command.parsed.question_id.0, user_id)).map_err(internal_error)?;
+        match previous_vote {
+            None => {}
+            Some(Some(up)) if up!=command.parsed.up => { // change the direction of the vote.
+                transaction.exec_drop("update HAS_VOTED set Up=? where QuestionId=? and VoterId=? and Up=?", (command.parsed.up, command.parsed.question_id.0, user_id, up)).map_err(internal_error)?;
+                if transaction.affected_rows()!=1 { return Err(QuestionError::AlreadyVoted); }
+                transaction.exec_drop("update QUESTIONS set NetVotes=NetVotes+? where QuestionID=?", (if command.parsed.up { 2 } else { -2 }, command.parsed.question_id.0)).map_err(internal_error)?;
+                transaction.commit().map_err(internal_error)?;
+                return Ok(());
+            }
+            Some(Some(_)) => return Err(QuestionError::AlreadyVoted),
+            Some(None) => return Err(QuestionError::VoteDirectionUnknown),
+        }
         // code below used if we want to publish plain text votes to the bulletin board.
         //let (version,) = transaction.exec_first("SELECT Version from QUESTIONS where QuestionID=?", (command.parsed.question_id.0, )).map_err(internal_error)?.ok_or(QuestionError::QuestionDoesNotExist)?;
         //let version = opt_hash_from_value(version).ok_or(QuestionError::InternalError)?;
@@ -936,14 +1259,70 @@ impl PlainTextVoteOnQuestionCommand {
         //let for_bb = PlainTextVoteOnQuestionCommandPostedToBulletinBoard { command: command.clone(), timestamp, prior: version };
         //let version = LogInBulletinBoard::PlainTextVoteQuestion(for_bb).log_in_bulletin_board().await.map_err(bulletin_board_error)?;
         //transaction.exec_drop("update QUESTIONS set Version=?,LastModifiedTimestamp=?,TotalVotes=TotalVotes+1,NetVotes=NetVotes+? where QuestionID=?", (version.0, timestamp, if command.parsed.up { 1 } else { -1 }, command.parsed.question_id.0)).map_err(internal_error)?;
+        // The unique key on HAS_VOTED means a concurrent duplicate vote is ignored rather than counted twice.
+        transaction.exec_drop("insert ignore into HAS_VOTED (QuestionID,VoterId,Up) values (?,?,?)", (command.parsed.question_id.0, user_id, command.parsed.up)).map_err(internal_error)?;
+        if transaction.affected_rows()!=1 { return Err(QuestionError::AlreadyVoted); }
         transaction.exec_drop("update QUESTIONS set TotalVotes=TotalVotes+1,NetVotes=NetVotes+? where QuestionID=?", (if command.parsed.up { 1 } else { -1 }, command.parsed.question_id.0)).map_err(internal_error)?;
-        transaction.exec_drop("insert into HAS_VOTED (QuestionID,VoterId) values (?,?)", (command.parsed.question_id.0, user_id)).map_err(internal_error)?;
         transaction.commit().map_err(internal_error)?;
         //Ok(version)
         Ok(())
     }
 }
 
+/// Retract a vote previously made with a [PlainTextVoteOnQuestionCommand].
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct PlainTextRetractVoteCommand {
+    pub question_id: QuestionID,
+}
+
+impl PlainTextRetractVoteCommand {
+    pub async fn retract(command:&ClientSigned<PlainTextRetractVoteCommand>) -> Result<(),QuestionError> {
+        println!("Retract vote for {} from {}",command.parsed.question_id,command.signed_message.user);
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let mut transaction = conn.start_transaction(TxOpts::default()).map_err(internal_error)?;
+        let user_id = get_user_id(&command.signed_message.user,QuestionError::NoSuchUser,QuestionError::InternalError,&mut transaction)?;
+        // Lock the question so concurrent votes by the same user are applied one after the other.
+        let withdrawn = transaction.exec_first::<Option<Timestamp>, _, _>("select WithdrawnTimestamp from QUESTIONS where QuestionID=? for update", (command.parsed.question_id.0,)).map_err(internal_error)?.ok_or(QuestionError::QuestionDoesNotExist)?;
+        if withdrawn.is_some() { return Err(QuestionError::QuestionWithdrawn) }
+        let up = transaction.exec_first::<Option<bool>, _, _>("select Up from HAS_VOTED where QuestionId=? and VoterId=? for update", (command.parsed.question_id.0, user_id)).map_err(internal_error)?.ok_or(QuestionError::NotVoted)?.ok_or(QuestionError::VoteDirectionUnknown)?;
+        transaction.exec_drop("delete from HAS_VOTED where QuestionId=? and VoterId=?", (command.parsed.question_id.0, user_id)).map_err(internal_error)?;
+        if transaction.affected_rows()!=1 { return Err(QuestionError::NotVoted); }
+        transaction.exec_drop("update QUESTIONS set TotalVotes=TotalVotes-1,NetVotes=NetVotes-? where QuestionID=?", (if up { 1 } else { -1 }, command.parsed.question_id.0)).map_err(internal_error)?;
+        transaction.commit().map_err(internal_error)?;
+        Ok(())
+    }
+}
+
+/// Ask for the signer's own votes on some questions.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct GetMyVotesCommand {
+    /// At most [MAX_QUESTIONS_IN_BATCH_LOOKUP] questions.
+    pub question_ids: Vec<QuestionID>,
+}
+
+/// A user's vote on a question.
+#[derive(Serialize,Deserialize,Debug,Clone,Eq,PartialEq)]
+pub struct MyVote {
+    pub question_id: QuestionID,
+    /// true for an up vote, false for a down vote, None if the vote was made before directions were recorded.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub up: Option<bool>,
+}
+
+impl GetMyVotesCommand {
+    /// Get the signer's votes on the requested questions. Questions not voted on are omitted.
+    pub async fn get_my_votes(command:&ClientSigned<GetMyVotesCommand>) -> Result<Vec<MyVote>,QuestionError> {
+        let question_ids = &command.parsed.question_ids;
+        if question_ids.len()>MAX_QUESTIONS_IN_BATCH_LOOKUP { return Err(QuestionError::TooManyQuestionsRequested); }
+        if question_ids.is_empty() { return Ok(vec![]); }
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let user_id = get_user_id(&command.signed_message.user,QuestionError::NoSuchUser,QuestionError::InternalError,&mut conn)?;
+        let mut params : Vec<mysql::Value> = vec![user_id.into()];
+        params.extend(question_ids.iter().map(|q|mysql::Value::from(q.0.to_vec())));
+        conn.exec_map(format!("select QuestionId,Up from HAS_VOTED where VoterId=? and QuestionId in ({})",vec!["?";question_ids.len()].join(",")),params,|(question_id,up)|MyVote{ question_id: hash_from_value(question_id), up }).map_err(internal_error)
+    }
+}
+
 /// When you query for the best questions matching various things, there is a trade off between various constraints.
 /// The list of resulting questions is ordered by a score, which is the sum of the weights below times the individual subscores.
 /// If you don't want to use some subscore, set the weight to zero.
@@ -1070,16 +1449,29 @@ impl SimilarQuestionQuery {
             // just_text.into_iter().map(|s|ScoredIDs{ id:s.id, score:command.weights.text as f64*s.score+command.weights.metadata as f64*(just_metadata.get(&s.id).cloned().unwrap_or(0) as f64)}).collect()
         };
         let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        if !command.non_defining_fields.topic_tags.is_empty() {
+            let with_tags = questions_with_all_topic_tags(&mut conn,&command.non_defining_fields.topic_tags)?;
+            all_questions.retain(|q|with_tags.contains(&q.id));
+        }
         let now = timestamp_now().map_err(internal_error)?;
+        let mut excluded : HashSet<QuestionID> = HashSet::new(); // withdrawn or censored questions.
         for q in &mut all_questions {
-            if let Some((created,total_votes,net_votes)) = conn.exec_first::<(Timestamp,u64,i64),_,_>("SELECT CreatedTimestamp,TotalVotes,NetVotes from QUESTIONS where QuestionID=?", (&q.id.0,)).map_err(internal_error)? {
+            // A duplicate is replaced by its canonical question, ranked using the votes on the canonical question and all its duplicates.
+            if let Some((canonical,created,total_votes,net_votes,withdrawn,censorship_status)) = conn.exec_first::<(mysql::Value,Timestamp,i64,i64,Option<Timestamp>,CensorshipStatus),_,_>("SELECT c.QuestionID,c.CreatedTimestamp,CAST(c.TotalVotes+COALESCE((SELECT SUM(d.TotalVotes) from QUESTIONS d where d.DuplicateOf=c.QuestionID),0) AS SIGNED),CAST(c.NetVotes+COALESCE((SELECT SUM(d.NetVotes) from QUESTIONS d where d.DuplicateOf=c.QuestionID),0) AS SIGNED),c.WithdrawnTimestamp,c.CensorshipStatus from QUESTIONS q inner join QUESTIONS c on c.QuestionID=COALESCE(q.DuplicateOf,q.QuestionID) where q.QuestionID=?", (&q.id.0,)).map_err(internal_error)? {
+                q.id = hash_from_value(canonical);
+                if withdrawn.is_some() || censorship_status==CensorshipStatus::Censored { excluded.insert(q.id); continue; }
+                let repeat_voters : Vec<(i64,i64)> = conn.exec("SELECT COUNT(*),CAST(SUM(IF(v.Up is NULL,0,IF(v.Up,1,-1))) AS SIGNED) from HAS_VOTED v inner join QUESTIONS d on v.QuestionId=d.QuestionID where d.QuestionID=? or d.DuplicateOf=? group by v.VoterId having COUNT(*)>1",(&q.id.0,&q.id.0)).map_err(internal_error)?;
+                let (total_votes,net_votes) = votes_on_duplicate_set(total_votes,net_votes,&repeat_voters);
                 let recentness = if created>now {0.0} else {f64::exp((-((now-created)as f64))/(command.weights.recentness_timescale as f64))};
                 let total_votes = f64::ln_1p(total_votes as f64);
                 let net_votes = (if net_votes>=0 {1.0} else {-1.0})*f64::ln_1p(f64::abs(total_votes as f64));
                 q.score+=command.weights.recentness as f64*recentness+command.weights.net_votes as f64*net_votes+command.weights.total_votes as f64*total_votes;
             }
         }
+        all_questions.retain(|q|!excluded.contains(&q.id));
         all_questions.sort_by(|a, b|b.score.partial_cmp(&a.score).unwrap());
+        let mut seen : HashSet<QuestionID> = HashSet::new(); // a canonical question may have come from several duplicates. Keep the best scoring.
+        all_questions.retain(|q|seen.insert(q.id));
         let questions = command.page.get_requested_page(&all_questions);
         let token = if all_questions.len()==questions.len() { None } else { Some(QuestionPagination::remember_similar_question_result(all_questions).await) };
         Ok(SimilarQuestionResult{token,questions})
@@ -1087,3 +1479,165 @@ impl SimilarQuestionQuery {
 
 }
 
+/// The total and net votes on a canonical question and its duplicates, given the sums of TotalVotes and NetVotes over
+/// them and, for each user who voted in plain text on more than one of them, the number of votes and the sum of their
+/// directions (+1 up, -1 down, 0 unknown). Such a user counts as a single voter, in the direction of most of their
+/// votes. Anonymous votes can't be linked to voters, so are all counted.
+fn votes_on_duplicate_set(total_votes:i64,net_votes:i64,repeat_voters:&[(i64,i64)]) -> (i64,i64) {
+    repeat_voters.iter().fold((total_votes,net_votes),|(total,net),&(count,directions)|(total-(count-1),net-directions+directions.signum()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::censorship::QuestionHistory;
+    use crate::database::recreate_test_databases;
+    use crate::person::register_test_user;
+    use crate::regions::{Chamber, Electorate};
+    use crate::signing::make_test_signed;
+    use crate::topic_tags::{AddTopicTagsCommand, TopicTagInfo};
+    use super::*;
+
+    /// Add a question, returning its id and version.
+    async fn add_test_question(author:&str,question_text:&str,non_defining_fields:QuestionNonDefiningFields) -> NewQuestionCommandResponse {
+        let command = NewQuestionCommand{ question_text: question_text.to_string(), non_defining_fields };
+        NewQuestionCommand::add_question(&make_test_signed(author,&command,()).await).await.unwrap()
+    }
+
+    /// Register a user with the MP badge for the `index`th MP in the MP list, returning that MP.
+    async fn register_test_mp(uid:&str,index:usize) -> MPId {
+        register_test_user(uid).await;
+        let mp = MPSpec::get().unwrap().mps[index].clone();
+        let mut conn = get_rta_database_connection().await.unwrap();
+        conn.exec_drop("insert into BADGES (user_id,badge,what) select id,'MP',? from USERS where UID=?",(mp.badge_name(),uid)).unwrap();
+        MPId{ first_name: mp.first_name, surname: mp.surname, electorate: mp.electorate }
+    }
+
+    /// An answer as sent by a client.
+    fn new_answer(mp:&MPId,answer:&str) -> QuestionAnswer {
+        QuestionAnswer{ answered_by: None, mp: mp.clone(), answer: answer.to_string(), timestamp: None, censorship_status: CensorshipStatus::NotFlagged, version: None, amended: None, accepted: false }
+    }
+
+    fn answered(version:u8) -> QuestionNonDefiningFields {
+        let mp = MPId{ first_name: "Jane".to_string(), surname: "Citizen".to_string(), electorate: Electorate{ chamber: Chamber::Australian_Senate, region: Some("Tasmania".to_string()) } };
+        QuestionNonDefiningFields{ answers: vec![QuestionAnswer{ answered_by: None, mp, answer: "Yes".to_string(), timestamp: Some(1), censorship_status: CensorshipStatus::NotFlagged, version: Some(HashValue([version;32])), amended: None, accepted: false }], ..Default::default() }
+    }
+
+    #[test]
+    fn author_can_accept_an_existing_answer() {
+        let accept = QuestionNonDefiningFields{ answer_accepted: true, ..Default::default() };
+        // Used to be rejected with CantAcceptAnswerIfNonePresent when there were answers.
+        assert_eq!(accept.check_answer_acceptance(true,Some(&answered(1))),Ok(()));
+        assert_eq!(accept.check_answer_acceptance(true,Some(&QuestionNonDefiningFields::default())),Err(QuestionError::CantAcceptAnswerIfNonePresent));
+        assert_eq!(accept.check_answer_acceptance(true,None),Err(QuestionError::CantAcceptAnswerIfNonePresent));
+        assert_eq!(accept.check_answer_acceptance(false,Some(&answered(1))),Err(QuestionError::OnlyAuthorCanAcceptAnswer));
+    }
+
+    #[test]
+    fn accepted_answer_must_be_one_of_the_answers() {
+        let accept = |v:u8|QuestionNonDefiningFields{ accepted_answer: Some(HashValue([v;32])), ..Default::default() };
+        assert_eq!(accept(1).check_answer_acceptance(true,Some(&answered(1))),Ok(()));
+        assert_eq!(accept(2).check_answer_acceptance(true,Some(&answered(1))),Err(QuestionError::NotAnUncensoredAnswer));
+        assert_eq!(accept(1).check_answer_acceptance(false,Some(&answered(1))),Err(QuestionError::OnlyAuthorCanAcceptAnswer));
+        assert_eq!(accept(1).check_answer_acceptance(true,None),Err(QuestionError::CantAcceptAnswerIfNonePresent));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn lookup_many_matches_lookup() {
+        let _database = recreate_test_databases().await;
+        register_test_user("lookup_author").await;
+        let mp = register_test_mp("lookup_mp",0).await;
+        AddTopicTagsCommand{ tags: vec![TopicTagInfo{ tag: "bananas".to_string(), description: None, num_questions: 0 }], include_listed_keywords: false }.add_topic_tags().await.unwrap();
+        let full = QuestionNonDefiningFields{
+            mp_who_should_ask_the_question: vec![PersonID::MP(mp.clone())],
+            entity_who_should_answer_the_question: vec![PersonID::MP(mp.clone())],
+            hansard_link: vec![HansardLink{ url: "https://www.aph.gov.au/Parliamentary_Business/Hansard".to_string() }],
+            topic_tags: vec!["bananas".to_string()],
+            ..Default::default()
+        };
+        let answered = add_test_question("lookup_author","Why are bananas so expensive in Tasmania this winter?",full).await;
+        let answer = EditQuestionCommand{ question_id: answered.question_id, version: answered.version, edits: QuestionNonDefiningFields{ answers: vec![new_answer(&mp,"Cyclones")], ..Default::default() } };
+        EditQuestionCommand::edit(&make_test_signed("lookup_mp",&answer,()).await).await.unwrap();
+        let plain = add_test_question("lookup_author","Will the government build a bridge to Kangaroo Island?",QuestionNonDefiningFields::default()).await.question_id;
+        let censored = add_test_question("lookup_author","When will the trains run on time in Melbourne?",QuestionNonDefiningFields::default()).await.question_id;
+        get_rta_database_connection().await.unwrap().exec_drop("update QUESTIONS set CensorshipStatus='Censored' where QuestionID=?",(censored.0,)).unwrap();
+        let answered = answered.question_id;
+        let answered_info = QuestionInfo::lookup(answered).await.unwrap().unwrap();
+        assert_eq!(1,answered_info.non_defining.mp_who_should_ask_the_question.len());
+        assert_eq!(1,answered_info.non_defining.entity_who_should_answer_the_question.len());
+        assert_eq!(1,answered_info.non_defining.answers.len());
+        assert_eq!(1,answered_info.non_defining.hansard_link.len());
+        assert_eq!(vec!["bananas".to_string()],answered_info.non_defining.topic_tags);
+        let plain_info = QuestionInfo::lookup(plain).await.unwrap().unwrap();
+        assert_eq!(QuestionInfo::lookup(censored).await.err(),Some(QuestionError::Censored));
+        // in the order requested, without repeats or censored questions.
+        let many = QuestionInfo::lookup_many(&[plain,censored,answered,plain]).await.unwrap();
+        let as_json = |infos:&[&QuestionInfo]| serde_json::to_value(infos).unwrap();
+        assert_eq!(as_json(&[&plain_info,&answered_info]),as_json(&many.iter().collect::<Vec<_>>()));
+        assert!(QuestionInfo::lookup_many(&[censored]).await.unwrap().is_empty());
+        assert_eq!(QuestionInfo::lookup_many(&vec![plain;MAX_QUESTIONS_IN_BATCH_LOOKUP+1]).await.err(),Some(QuestionError::TooManyQuestionsRequested));
+        assert_eq!(1,QuestionInfo::lookup_many(&vec![plain;MAX_QUESTIONS_IN_BATCH_LOOKUP]).await.unwrap().len());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn mp_can_amend_answer() {
+        let _database = recreate_test_databases().await;
+        register_test_user("amend_author").await;
+        let mp = register_test_mp("amend_mp",0).await;
+        register_test_mp("amend_other_mp",1).await;
+        let NewQuestionCommandResponse{ question_id, version } = add_test_question("amend_author","Why are bananas so expensive in Tasmania this winter?",QuestionNonDefiningFields::default()).await;
+        let answer = EditQuestionCommand{ question_id, version, edits: QuestionNonDefiningFields{ answers: vec![new_answer(&mp,"Cyclones in Queensland")], ..Default::default() } };
+        let version = EditQuestionCommand::edit(&make_test_signed("amend_mp",&answer,()).await).await.unwrap();
+        let answer_version = QuestionInfo::lookup(question_id).await.unwrap().unwrap().non_defining.answers[0].version.unwrap();
+        get_rta_database_connection().await.unwrap().exec_drop("update Answer set CensorshipStatus='Allowed' where QuestionId=?",(question_id.0,)).unwrap();
+        let amend = |user:&'static str,answer_version:HashValue,answer:&'static str| async move { AmendAnswerCommand::amend(&make_test_signed(user,&AmendAnswerCommand{ question_id, version, answer_version, answer: answer.to_string() },()).await).await };
+        assert_eq!(amend("amend_other_mp",answer_version,"Cyclones in Fiji").await,Err(QuestionError::UserDoesNotHaveCorrectMPBadge));
+        assert_eq!(amend("amend_mp",HashValue([0;32]),"Cyclones in Fiji").await,Err(QuestionError::NotAnUncensoredAnswer));
+        assert_eq!(amend("amend_mp",answer_version,"Cyclones in Queensland").await,Err(QuestionError::AnswerUnchanged));
+        let amended_version = amend("amend_mp",answer_version,"Cyclones in Fiji").await.unwrap();
+        let info = QuestionInfo::lookup(question_id).await.unwrap().unwrap();
+        assert_eq!(amended_version,info.version);
+        let amended = &info.non_defining.answers[0];
+        assert_eq!("Cyclones in Fiji",amended.answer);
+        assert_eq!(Some(answer_version),amended.version);
+        assert!(amended.amended.is_some());
+        // an allowed answer needs to be looked at again.
+        assert_eq!(CensorshipStatus::StructureChanged,amended.censorship_status);
+        // the original is still in the history.
+        let history = serde_json::to_value(QuestionHistory::lookup(question_id).await.unwrap()).unwrap();
+        assert!(history["history"][0]["action"]["AmendAnswer"].is_object());
+        assert!(history["history"][1]["action"]["EditQuestion"].to_string().contains("Cyclones in Queensland"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn author_can_withdraw_question() {
+        let _database = recreate_test_databases().await;
+        register_test_user("withdraw_author").await;
+        register_test_user("withdraw_other").await;
+        let question_text = "Why are bananas so expensive in Tasmania this winter?";
+        let NewQuestionCommandResponse{ question_id, version } = add_test_question("withdraw_author",question_text,QuestionNonDefiningFields::default()).await;
+        let is_similar = || async { find_similar_text_question(question_text).await.unwrap().iter().any(|s|s.id==question_id) };
+        assert!(is_similar().await);
+        let withdraw = |user:&'static str,version:LastQuestionUpdate| async move { WithdrawQuestionCommand::withdraw(&make_test_signed(user,&WithdrawQuestionCommand{ question_id, version },()).await).await };
+        assert_eq!(withdraw("withdraw_other",version).await,Err(QuestionError::OnlyAuthorCanWithdraw));
+        let version = withdraw("withdraw_author",version).await.unwrap();
+        assert_eq!(withdraw("withdraw_author",version).await,Err(QuestionError::QuestionWithdrawn));
+        let info = QuestionInfo::lookup(question_id).await.unwrap().unwrap();
+        assert_eq!(version,info.version);
+        assert!(info.withdrawn.is_some());
+        // it can no longer be edited or voted on.
+        let edit = EditQuestionCommand{ question_id, version, edits: QuestionNonDefiningFields{ background: Some("They used to be cheap".to_string()), ..Default::default() } };
+        assert_eq!(EditQuestionCommand::edit(&make_test_signed("withdraw_author",&edit,()).await).await,Err(QuestionError::QuestionWithdrawn));
+        let vote = PlainTextVoteOnQuestionCommand{ question_id, up: true };
+        assert_eq!(PlainTextVoteOnQuestionCommand::vote(&make_test_signed("withdraw_other",&vote,()).await).await,Err(QuestionError::QuestionWithdrawn));
+        assert!(!is_similar().await);
+    }
+
+    #[test]
+    fn voters_on_several_duplicates_count_once() {
+        assert_eq!(votes_on_duplicate_set(5,3,&[]),(5,3));
+        // one user voted up on all three questions, another up on one and down on another.
+        assert_eq!(votes_on_duplicate_set(7,3,&[(3,3),(2,0)]),(4,1));
+        // two down votes and an up vote is one down vote.
+        assert_eq!(votes_on_duplicate_set(3,-1,&[(3,-1)]),(1,-1));
+    }
+}
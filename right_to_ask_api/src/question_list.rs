@@ -0,0 +1,201 @@
+//! Filtered listing of questions, a page at a time.
+//!
+//! Questions are listed most recently modified first. Each page comes with a cursor (the LastModifiedTimestamp
+//! and QuestionId of the last question on the page) that is passed back to get the next page. As modifying a question
+//! moves it to the front of the list, a question modified while someone is paging through may be missed or seen twice.
+
+use merkle_tree_bulletin_board::hash_history::Timestamp;
+use mysql::prelude::Queryable;
+use mysql::Value;
+use serde::{Serialize, Deserialize};
+use crate::censorship::CensorshipStatus;
+use crate::database::get_rta_database_connection;
+use crate::lifecycle::QuestionLifecycle;
+use crate::person::UserUID;
+use crate::question::{hash_from_value, internal_error, PersonID, QuestionError, QuestionID};
+use crate::regions::{Chamber, Jurisdiction};
+
+/// The default number of questions returned in a page.
+const DEFAULT_PAGE_SIZE : usize = 50;
+/// The maximum number of questions returned in a page.
+const MAX_PAGE_SIZE : usize = 500;
+
+/// A request for a page of questions. All filters are optional; questions must match all given filters.
+#[derive(Serialize,Deserialize,Debug,Clone,Default)]
+pub struct QuestionListQuery {
+    /// Only questions that this MP, minister, committee, organisation or user is asked to answer.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub answerer : Option<PersonID>,
+    /// Only questions that this MP or user is asked to ask.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub asker : Option<PersonID>,
+    /// Only questions to be asked or answered by an MP in this chamber, or a minister or committee in this jurisdiction.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub jurisdiction : Option<Jurisdiction>,
+    /// Only questions created by this user.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub author : Option<UserUID>,
+    /// If Some(true), only questions with at least one (uncensored) answer. If Some(false), only questions with none.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub answered : Option<bool>,
+    /// Only questions with this censorship status. If not given, censored questions are excluded.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub censorship_status : Option<CensorshipStatus>,
+    /// Only questions at this point in their lifecycle.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub lifecycle : Option<QuestionLifecycle>,
+    /// If true, include questions withdrawn by their authors.
+    #[serde(default)]
+    pub include_withdrawn : bool,
+    /// If true, include questions marked as duplicates of another question.
+    #[serde(default)]
+    pub include_duplicates : bool,
+    /// Only questions created at or after this time.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub created_after : Option<Timestamp>,
+    /// Only questions created before this time.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub created_before : Option<Timestamp>,
+    /// Only questions last modified at or after this time.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub modified_after : Option<Timestamp>,
+    /// Only questions last modified before this time.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub modified_before : Option<Timestamp>,
+    /// The maximum number of questions to return. Default 50, at most 500.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub limit : Option<usize>,
+    /// The next_cursor from the previous page, or None for the first page.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub cursor : Option<String>,
+}
+
+/// A page of questions, most recently modified first.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct QuestionListResult {
+    pub questions : Vec<QuestionID>,
+    /// If there may be more questions, pass this as the cursor to get them.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub next_cursor : Option<String>,
+}
+
+/// A position in the list, just after the question with the given last modified time and id.
+#[derive(Debug,Clone,Copy,Eq,PartialEq)]
+struct ListCursor {
+    last_modified : Timestamp,
+    question_id : QuestionID,
+}
+
+impl ListCursor {
+    fn encode(&self) -> String { format!("{}_{}",self.last_modified,self.question_id) }
+    fn decode(cursor:&str) -> Result<ListCursor,QuestionError> {
+        let (last_modified,question_id) = cursor.split_once('_').ok_or(QuestionError::InvalidListCursor)?;
+        let last_modified = last_modified.parse().map_err(|_|QuestionError::InvalidListCursor)?;
+        let question_id = question_id.parse().map_err(|_|QuestionError::InvalidListCursor)?;
+        Ok(ListCursor{ last_modified, question_id })
+    }
+}
+
+impl QuestionListQuery {
+    /// Add the SQL conditions for a person in a given role. Returns false if the person is unknown, so nothing can match.
+    fn person_condition(conn:&mut impl Queryable,role:&str,person:&PersonID,conditions:&mut Vec<String>,params:&mut Vec<Value>) -> Result<bool,QuestionError> {
+        match person.person_for_question_column(conn).map_err(internal_error)? {
+            Some((column,id)) => {
+                conditions.push(format!("exists (select * from PersonForQuestion where PersonForQuestion.QuestionId=QUESTIONS.QuestionId and ROLE=? and {}=?)",column));
+                params.push(role.into());
+                params.push(id.into());
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Get a page of questions matching the filters.
+    pub async fn list_questions(&self) -> Result<QuestionListResult,QuestionError> {
+        let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+        let limit = self.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1,MAX_PAGE_SIZE);
+        let mut conditions : Vec<String> = vec![];
+        let mut params : Vec<Value> = vec![];
+        for (role,person) in [("A",&self.answerer),("Q",&self.asker)] {
+            let Some(person) = person else { continue };
+            if !Self::person_condition(&mut conn,role,person,&mut conditions,&mut params)? { return Ok(QuestionListResult{questions:vec![],next_cursor:None}); }
+        }
+        if let Some(jurisdiction) = self.jurisdiction { jurisdiction_condition(jurisdiction,&mut conditions,&mut params); }
+        if let Some(author) = &self.author {
+            conditions.push("USERS.UID=?".to_string());
+            params.push(author.into());
+        }
+        if let Some(answered) = self.answered {
+            conditions.push(format!("{}exists (select * from Answer where Answer.QuestionId=QUESTIONS.QuestionId and Answer.CensorshipStatus!='Censored')",if answered {""} else {"not "}));
+        }
+        match self.censorship_status {
+            Some(status) => { conditions.push("QUESTIONS.CensorshipStatus=?".to_string()); params.push(status.into()); }
+            None => conditions.push("QUESTIONS.CensorshipStatus!='Censored'".to_string()),
+        }
+        if let Some(lifecycle) = self.lifecycle {
+            conditions.push("Lifecycle=?".to_string());
+            params.push(lifecycle.into());
+        }
+        if !self.include_withdrawn { conditions.push("WithdrawnTimestamp is NULL".to_string()); }
+        if !self.include_duplicates { conditions.push("DuplicateOf is NULL".to_string()); }
+        for (value,condition) in [(self.created_after,"CreatedTimestamp>=?"),(self.created_before,"CreatedTimestamp<?"),(self.modified_after,"LastModifiedTimestamp>=?"),(self.modified_before,"LastModifiedTimestamp<?")] {
+            if let Some(value) = value {
+                conditions.push(condition.to_string());
+                params.push(value.into());
+            }
+        }
+        if let Some(cursor) = &self.cursor {
+            let cursor = ListCursor::decode(cursor)?;
+            conditions.push("(LastModifiedTimestamp<? or (LastModifiedTimestamp=? and QUESTIONS.QuestionId<?))".to_string());
+            params.extend([cursor.last_modified.into(),cursor.last_modified.into(),cursor.question_id.0.to_vec().into()]);
+        }
+        let query = format!("SELECT QUESTIONS.QuestionId,LastModifiedTimestamp from QUESTIONS inner join USERS ON QUESTIONS.CreatedById=USERS.id where {} ORDER BY LastModifiedTimestamp DESC, QUESTIONS.QuestionId DESC LIMIT {}",conditions.join(" and "),limit+1);
+        let mut found : Vec<ListCursor> = conn.exec_map(query,params,|(question_id,last_modified)|ListCursor{ last_modified, question_id: hash_from_value(question_id) }).map_err(internal_error)?;
+        let next_cursor = if found.len()>limit {
+            found.truncate(limit);
+            found.last().map(|c|c.encode())
+        } else { None };
+        Ok(QuestionListResult{ questions: found.into_iter().map(|c|c.question_id).collect(), next_cursor })
+    }
+}
+
+/// Add the SQL condition for a question being for someone in the jurisdiction: an MP in a chamber compatible with
+/// it, or a committee or minister whose jurisdiction is it or one of those chambers.
+fn jurisdiction_condition(jurisdiction:Jurisdiction,conditions:&mut Vec<String>,params:&mut Vec<Value>) {
+    let chambers : Vec<String> = Chamber::ALL.into_iter().filter(|&c|jurisdiction.compatible_with(c)).map(|c|c.to_string()).collect();
+    let mut jurisdictions = chambers.clone();
+    if !jurisdictions.contains(&jurisdiction.to_string()) { jurisdictions.push(jurisdiction.to_string()); }
+    let in_list = |n:usize|vec!["?";n].join(",");
+    conditions.push(format!("exists (select * from PersonForQuestion left join MP_IDs on PersonForQuestion.MP=MP_IDs.id left join Committee_IDs on PersonForQuestion.Committee=Committee_IDs.id left join Minister_IDs on PersonForQuestion.Minister=Minister_IDs.id where PersonForQuestion.QuestionId=QUESTIONS.QuestionId and (MP_IDs.Chamber in ({}) or Committee_IDs.Jurisdiction in ({}) or Minister_IDs.Jurisdiction in ({})))",in_list(chambers.len()),in_list(jurisdictions.len()),in_list(jurisdictions.len())));
+    params.extend(chambers.into_iter().chain(jurisdictions.iter().cloned()).chain(jurisdictions.iter().cloned()).map(Value::from));
+}
+
+#[cfg(test)]
+mod tests {
+    use merkle_tree_bulletin_board::hash::HashValue;
+    use super::*;
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = ListCursor{ last_modified: 1650000000, question_id: HashValue([7u8;32]) };
+        assert_eq!(ListCursor::decode(&cursor.encode()),Ok(cursor));
+        assert_eq!(ListCursor::decode("1650000000"),Err(QuestionError::InvalidListCursor));
+        assert_eq!(ListCursor::decode("1650000000_0707"),Err(QuestionError::InvalidListCursor));
+    }
+
+    #[test]
+    fn jurisdiction_filter_covers_its_chambers() {
+        let condition = |jurisdiction:Jurisdiction| {
+            let mut conditions = vec![];
+            let mut params = vec![];
+            jurisdiction_condition(jurisdiction,&mut conditions,&mut params);
+            assert_eq!(conditions.len(),1);
+            assert_eq!(conditions[0].matches('?').count(),params.len());
+            params.into_iter().map(|v|String::from_utf8(v.as_sql(true).into_bytes()).unwrap()).collect::<Vec<_>>()
+        };
+        // MP chambers, then committee jurisdictions, then minister jurisdictions.
+        assert_eq!(condition(Jurisdiction::NSW),["'NSW_Legislative_Assembly'","'NSW_Legislative_Council'","'NSW_Legislative_Assembly'","'NSW_Legislative_Council'","'NSW'","'NSW_Legislative_Assembly'","'NSW_Legislative_Council'","'NSW'"]);
+        assert_eq!(condition(Jurisdiction::Federal),["'Australian_House_Of_Representatives'","'Australian_Senate'","'Australian_House_Of_Representatives'","'Australian_Senate'","'Federal'","'Australian_House_Of_Representatives'","'Australian_Senate'","'Federal'"]);
+        assert_eq!(condition(Jurisdiction::Australian_Senate),["'Australian_Senate'","'Australian_Senate'","'Australian_Senate'"]);
+    }
+}
@@ -0,0 +1,215 @@
+//! Token bucket rate limiting of write operations, per user and per client IP address.
+//!
+//! Each (subject,action) pair has a bucket holding up to `burst` tokens, refilled at `per_hour` tokens per hour.
+//! An operation takes a token from the user's bucket and from the IP address's bucket, and is refused if either is empty.
+//! State is kept in memory, so is forgotten when the server restarts. Limits are in config.toml, see [crate::config::RateLimitsConfig].
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Mutex;
+use std::time::Instant;
+use merkle_tree_bulletin_board::hash_history::{Timestamp, timestamp_now};
+use once_cell::sync::Lazy;
+use serde::{Serialize, Deserialize};
+use crate::config::{CONFIG, TokenBucketConfig};
+use crate::person::{RegistrationError, UserUID};
+use crate::question::QuestionError;
+
+/// The kinds of operation that are rate limited. Each has its own limits.
+#[derive(Debug,Copy,Clone,Serialize,Deserialize,Eq,PartialEq,Hash)]
+pub enum RateLimitedAction {
+    NewRegistration,
+    /// Getting a proof of work challenge for a registration. Has the same limits as NewRegistration, in a separate bucket.
+    RegistrationChallenge,
+    NewQuestion,
+    EditQuestion,
+    Vote,
+    ReportQuestion,
+    /// Getting a bulk export of questions. Only limited per IP address.
+    Export,
+}
+
+/// Who is being rate limited.
+#[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq,Hash)]
+pub enum RateLimitSubject {
+    User(UserUID),
+    /// A client IP address, as a string.
+    IP(String),
+}
+
+/// The error produced when a rate limit is exceeded.
+#[derive(Debug,Copy,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub struct RateLimited;
+
+impl Display for RateLimited {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"RateLimited")
+    }
+}
+
+impl From<RateLimited> for QuestionError {
+    fn from(_: RateLimited) -> Self { QuestionError::RateLimited }
+}
+
+impl From<RateLimited> for RegistrationError {
+    fn from(_: RateLimited) -> Self { RegistrationError::RateLimited }
+}
+
+struct TokenBucket {
+    tokens : f64,
+    last_refill : Instant,
+}
+
+impl TokenBucket {
+    fn new(config:&TokenBucketConfig,now:Instant) -> Self { TokenBucket{ tokens: config.burst, last_refill: now } }
+
+    fn refill(&mut self,config:&TokenBucketConfig,now:Instant) {
+        let elapsed_hours = now.saturating_duration_since(self.last_refill).as_secs_f64()/3600.0;
+        self.tokens = (self.tokens+elapsed_hours*config.per_hour).min(config.burst);
+        self.last_refill = now;
+    }
+
+    /// Whether a token is available (after refilling).
+    fn has_token(&mut self,config:&TokenBucketConfig,now:Instant) -> bool {
+        self.refill(config,now);
+        self.tokens>=1.0
+    }
+
+    /// True if the bucket would be full, in which case it may be forgotten.
+    fn is_full(&self,config:&TokenBucketConfig,now:Instant) -> bool {
+        let elapsed_hours = now.saturating_duration_since(self.last_refill).as_secs_f64()/3600.0;
+        self.tokens+elapsed_hours*config.per_hour>=config.burst
+    }
+}
+
+/// Someone who has recently been refused by the rate limiter.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct ThrottledSubject {
+    pub subject : RateLimitSubject,
+    pub action : RateLimitedAction,
+    /// The number of refused operations since first_throttled.
+    pub times_throttled : u64,
+    pub first_throttled : Timestamp,
+    pub last_throttled : Timestamp,
+}
+
+/// How long someone stays in the list returned by [get_throttled_subjects] after last being refused.
+const THROTTLED_REPORT_SECONDS : Timestamp = 24*60*60;
+
+#[derive(Default)]
+struct RateLimiterState {
+    buckets : HashMap<(RateLimitSubject,RateLimitedAction),TokenBucket>,
+    last_size_when_gc_called : usize,
+    throttled : HashMap<(RateLimitSubject,RateLimitedAction),ThrottledSubject>,
+    last_throttled_size_when_pruned : usize,
+}
+
+static RATE_LIMITER : Lazy<Mutex<RateLimiterState>> = Lazy::new(||Mutex::new(RateLimiterState::default()));
+
+impl RateLimiterState {
+    /// Take a token from each subject's bucket if all have a token available. Otherwise take nothing and return the subjects that were empty.
+    fn take(&mut self,action:RateLimitedAction,subjects:&[(RateLimitSubject,TokenBucketConfig)],now:Instant) -> Vec<RateLimitSubject> {
+        let mut empty = vec![];
+        for (subject,config) in subjects {
+            let bucket = self.buckets.entry((subject.clone(),action)).or_insert_with(||TokenBucket::new(config,now));
+            if !bucket.has_token(config,now) { empty.push(subject.clone()); }
+        }
+        if empty.is_empty() {
+            for (subject,_) in subjects {
+                if let Some(bucket) = self.buckets.get_mut(&(subject.clone(),action)) { bucket.tokens-=1.0; }
+            }
+        }
+        if self.buckets.len()>2*self.last_size_when_gc_called.max(1000) { self.gc(now); }
+        empty
+    }
+
+    /// Forget buckets that would have refilled completely, as they are equivalent to new buckets.
+    fn gc(&mut self,now:Instant) {
+        let limits = &CONFIG.rate_limits;
+        self.buckets.retain(|(subject,action),bucket|!bucket.is_full(&limits.limit_for(*action,subject),now));
+        self.last_size_when_gc_called = self.buckets.len();
+    }
+
+    fn note_throttled(&mut self,action:RateLimitedAction,subject:RateLimitSubject,timestamp:Timestamp) {
+        let entry = self.throttled.entry((subject.clone(),action)).or_insert_with(||ThrottledSubject{subject,action,times_throttled:0,first_throttled:timestamp,last_throttled:timestamp});
+        entry.times_throttled+=1;
+        entry.last_throttled=timestamp;
+        if self.throttled.len()>2*self.last_throttled_size_when_pruned.max(1000) { self.prune_throttled(timestamp); }
+    }
+
+    /// Forget subjects that have not been refused in the last [THROTTLED_REPORT_SECONDS].
+    fn prune_throttled(&mut self,now:Timestamp) {
+        let cutoff = now.saturating_sub(THROTTLED_REPORT_SECONDS);
+        self.throttled.retain(|_,t|t.last_throttled>=cutoff);
+        self.last_throttled_size_when_pruned = self.throttled.len();
+    }
+}
+
+/// Check whether a user and/or client IP address may perform the given action, and if so use up one token for each.
+pub fn check_rate_limit(action:RateLimitedAction,uid:Option<&str>,ip:Option<&str>) -> Result<(),RateLimited> {
+    let limits = &CONFIG.rate_limits;
+    if !limits.enabled { return Ok(()); }
+    let mut subjects = vec![];
+    if let Some(uid) = uid { subjects.push(RateLimitSubject::User(uid.to_string())); }
+    if let Some(ip) = ip { subjects.push(RateLimitSubject::IP(ip.to_string())); }
+    let subjects : Vec<(RateLimitSubject,TokenBucketConfig)> = subjects.into_iter().map(|s|{let config=limits.limit_for(action,&s); (s,config)}).collect();
+    let mut state = RATE_LIMITER.lock().unwrap();
+    let empty = state.take(action,&subjects,Instant::now());
+    if empty.is_empty() { Ok(()) } else {
+        let timestamp = timestamp_now().unwrap_or(0);
+        for subject in empty {
+            println!("Rate limited {:?} for {:?}",subject,action);
+            state.note_throttled(action,subject,timestamp);
+        }
+        Err(RateLimited)
+    }
+}
+
+/// Whether the client IP address should be taken from X-Forwarded-For or Forwarded headers (when behind a reverse proxy) rather than the connection.
+pub fn use_forwarded_client_ip() -> bool { CONFIG.rate_limits.trust_forwarded_headers }
+
+/// Get the users and IP addresses that have been refused by the rate limiter recently, most recent first.
+pub fn get_throttled_subjects() -> Vec<ThrottledSubject> {
+    let mut state = RATE_LIMITER.lock().unwrap();
+    state.prune_throttled(timestamp_now().unwrap_or(0));
+    let mut res : Vec<ThrottledSubject> = state.throttled.values().cloned().collect();
+    res.sort_by_key(|t|std::cmp::Reverse(t.last_throttled));
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    fn bucket_empties_and_refills() {
+        let config = TokenBucketConfig{ burst: 2.0, per_hour: 60.0 };
+        let subjects = vec![(RateLimitSubject::User("alice".to_string()),config),(RateLimitSubject::IP("10.0.0.1".to_string()),TokenBucketConfig{ burst: 3.0, per_hour: 60.0 })];
+        let mut state = RateLimiterState::default();
+        let start = Instant::now();
+        assert!(state.take(RateLimitedAction::Vote,&subjects,start).is_empty());
+        assert!(state.take(RateLimitedAction::Vote,&subjects,start).is_empty());
+        assert_eq!(state.take(RateLimitedAction::Vote,&subjects,start),vec![RateLimitSubject::User("alice".to_string())]);
+        // a different action has a separate bucket.
+        assert!(state.take(RateLimitedAction::NewQuestion,&subjects,start).is_empty());
+        // one token per minute.
+        assert!(state.take(RateLimitedAction::Vote,&subjects,start+Duration::from_secs(61)).is_empty());
+        // the IP address has only one token left, and the refused request did not use it up.
+        assert!(state.take(RateLimitedAction::Vote,&subjects[1..],start+Duration::from_secs(61)).is_empty());
+        assert_eq!(state.take(RateLimitedAction::Vote,&subjects[1..],start+Duration::from_secs(61)),vec![RateLimitSubject::IP("10.0.0.1".to_string())]);
+    }
+
+    #[test]
+    fn throttled_subjects_are_forgotten() {
+        let mut state = RateLimiterState::default();
+        let start : Timestamp = 1_000_000;
+        for i in 0..1500 { state.note_throttled(RateLimitedAction::Vote,RateLimitSubject::IP(format!("10.0.{}.{}",i/256,i%256)),start); }
+        assert_eq!(state.throttled.len(),1500);
+        state.note_throttled(RateLimitedAction::Vote,RateLimitSubject::IP("10.0.0.0".to_string()),start+THROTTLED_REPORT_SECONDS);
+        // once the map is large, old entries are pruned as new ones are added.
+        for i in 0..600 { state.note_throttled(RateLimitedAction::Vote,RateLimitSubject::User(format!("user{}",i)),start+THROTTLED_REPORT_SECONDS+1); }
+        assert_eq!(state.throttled.len(),601);
+        assert_eq!(state.throttled[&(RateLimitSubject::IP("10.0.0.0".to_string()),RateLimitedAction::Vote)].times_throttled,2);
+    }
+}
@@ -99,8 +99,11 @@ pub enum Chamber {
 	WA_Legislative_Council
 }
 
-/// Whether a given chamber has regions.
 impl Chamber {
+    /// Every chamber.
+    pub const ALL : [Chamber;15] = [Chamber::ACT_Legislative_Assembly,Chamber::Australian_House_Of_Representatives,Chamber::Australian_Senate,Chamber::NSW_Legislative_Assembly,Chamber::NSW_Legislative_Council,Chamber::NT_Legislative_Assembly,Chamber::Qld_Legislative_Assembly,Chamber::SA_House_Of_Assembly,Chamber::SA_Legislative_Council,Chamber::Vic_Legislative_Assembly,Chamber::Vic_Legislative_Council,Chamber::Tas_House_Of_Assembly,Chamber::Tas_Legislative_Council,Chamber::WA_Legislative_Assembly,Chamber::WA_Legislative_Council];
+
+    /// Whether a given chamber has regions.
     pub fn has_regions(&self) -> bool {
         match self {
             Chamber::ACT_Legislative_Assembly => true,
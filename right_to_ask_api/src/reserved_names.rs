@@ -0,0 +1,124 @@
+//! Stop people registering UIDs or display names that make them look like an MP, or like the RightToAsk organisation itself.
+//!
+//! Names are compared after [normalise_name], which ignores case, spaces, punctuation and common look-alike characters.
+//! Reserved names come from the current MP list (first name plus surname, either way around, and badge names)
+//! and from `reserved_names` in config.toml.
+//!
+//! A UID may not be a reserved name. A display name may not contain a name from config.toml, and may not be
+//! close to an MP's name unless the user has the MP badge for that MP.
+
+use crate::config::CONFIG;
+use crate::mp::MPSpec;
+
+/// Lower case, with everything except letters and digits removed, and digits and symbols commonly used to imitate letters replaced by those letters.
+pub fn normalise_name(name:&str) -> String {
+    name.chars().flat_map(|c|c.to_lowercase()).filter_map(|c|match c {
+        '0' => Some('o'),
+        '1' | '|' | 'i' => Some('l'),
+        '3' => Some('e'),
+        '4' | '@' => Some('a'),
+        '5' | '$' => Some('s'),
+        '7' => Some('t'),
+        c if c.is_alphanumeric() => Some(c),
+        _ => None,
+    }).collect()
+}
+
+/// The number of single character insertions, deletions or substitutions needed to change a into b.
+fn edit_distance(a:&str,b:&str) -> usize {
+    let b : Vec<char> = b.chars().collect();
+    let mut previous : Vec<usize> = (0..=b.len()).collect();
+    for (i,ca) in a.chars().enumerate() {
+        let mut current = vec![i+1];
+        for (j,cb) in b.iter().enumerate() {
+            let substitution = previous[j]+if ca==*cb {0} else {1};
+            current.push(substitution.min(previous[j+1]+1).min(current[j]+1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Whether a normalised name is close enough to a normalised MP name to be mistaken for it.
+/// Longer names are allowed more differences.
+fn is_close_to(name:&str,mp_name:&str) -> bool {
+    if mp_name.is_empty() { return false; }
+    if name.contains(mp_name) { return true; }
+    let allowed_distance = if mp_name.len()>=12 { 2 } else if mp_name.len()>=6 { 1 } else { 0 };
+    edit_distance(name,mp_name)<=allowed_distance
+}
+
+/// A name that may not be used, and who (if anyone) may use it.
+struct ReservedName {
+    /// Normalised
+    name : String,
+    /// For an MP, the [crate::mp::MP::badge_name] of a badge entitling the user to use this name.
+    mp_badge_name : Option<String>,
+}
+
+fn mp_reserved_names() -> Vec<ReservedName> {
+    match MPSpec::get() {
+        Ok(mps) => mps.mps.iter().flat_map(|mp|{
+            let badge_name = mp.badge_name();
+            [mp.first_name.clone()+&mp.surname,mp.surname.clone()+&mp.first_name,badge_name.clone()].into_iter().map(move |name|ReservedName{ name: normalise_name(&name), mp_badge_name: Some(badge_name.clone()) })
+        }).collect(),
+        Err(e) => {
+            println!("Could not load MP list to check reserved names : {}",e);
+            vec![]
+        }
+    }
+}
+
+fn configured_reserved_names() -> impl Iterator<Item=String> {
+    CONFIG.reserved_names.iter().map(|n|normalise_name(n)).filter(|n|!n.is_empty())
+}
+
+/// Check that a UID is not a reserved name.
+pub fn is_reserved_uid(uid:&str) -> bool {
+    let uid = normalise_name(uid);
+    configured_reserved_names().any(|n|uid.contains(&n)) || mp_reserved_names().iter().any(|n|n.name==uid)
+}
+
+/// Why a display name is not allowed.
+#[derive(Debug,Copy,Clone,Eq,PartialEq)]
+pub enum DisplayNameProblem {
+    /// It contains a name listed in config.toml
+    Reserved,
+    /// It is close to the name of an MP, and the user does not have that MP's badge.
+    TooSimilarToMP,
+}
+
+/// Check whether a display name may be used by someone with MP badges with the given names.
+pub fn check_display_name(display_name:&str,mp_badge_names:&[String]) -> Result<(),DisplayNameProblem> {
+    let name = normalise_name(display_name);
+    if configured_reserved_names().any(|n|name.contains(&n)) { return Err(DisplayNameProblem::Reserved); }
+    for reserved in mp_reserved_names() {
+        if is_close_to(&name,&reserved.name) && !reserved.mp_badge_name.as_ref().is_some_and(|b|mp_badge_names.contains(b)) {
+            println!("Rejected display name {} as too similar to {}",display_name,reserved.name);
+            return Err(DisplayNameProblem::TooSimilarToMP);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalise_name() {
+        assert_eq!(normalise_name("Anthony Albanese"),"anthonyalbanese");
+        assert_eq!(normalise_name("anthony_albanese"),normalise_name("AnthonyAlbanese"));
+        assert_eq!(normalise_name("Anth0ny A1banese"),normalise_name("Anthony Albanese"));
+    }
+
+    #[test]
+    fn test_is_close_to() {
+        let mp = normalise_name("Anthony Albanese");
+        assert!(is_close_to(&normalise_name("Anthony Albanese MP"),&mp));
+        assert!(is_close_to(&normalise_name("Antony Albanese"),&mp));
+        assert!(is_close_to(&normalise_name("Anthony Albanesi"),&mp));
+        assert!(!is_close_to(&normalise_name("Anthony Smith"),&mp));
+        assert!(!is_close_to(&normalise_name("Bob Katter"),&normalise_name("Bob Kitten")));
+    }
+}
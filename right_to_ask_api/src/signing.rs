@@ -2,14 +2,20 @@
 
 
 use std::convert::TryFrom;
+use anyhow::anyhow;
 use std::fmt::{Display, Formatter};
 use once_cell::sync::Lazy;
 use ed25519_dalek::{SigningKey,Signature, VerifyingKey,Verifier,pkcs8::DecodePrivateKey};
 use ed25519_dalek::ed25519::signature::{Signer};
 use serde::{Serialize,Deserialize};
-use crate::config::CONFIG;
+use crate::config::{AdministratorConfig, CONFIG, ReplayProtectionConfig};
 use serde::de::DeserializeOwned;
 use crate::person::get_user_public_key_by_id;
+use crate::administration::AdministratorRole;
+use std::sync::Mutex;
+use std::time::Duration;
+use merkle_tree_bulletin_board::hash_history::{Timestamp, timestamp_now};
+use crate::time_limited_hashmap::TimeLimitedHashMap;
 
 pub fn base64_decode(s:&str)-> Result<Vec<u8>, base64::DecodeError> {
     use base64::Engine;
@@ -17,17 +23,108 @@ pub fn base64_decode(s:&str)-> Result<Vec<u8>, base64::DecodeError> {
 }
 pub fn base64_encode<T: AsRef<[u8]>>(input: T) -> String { use base64::Engine; base64::engine::general_purpose::STANDARD.encode(input) }
 
+/// Decode a base 64 encoded SPKI public key, as used for server keys in config.toml
+fn parse_spki_public_key(public_key:&str) -> anyhow::Result<VerifyingKey> {
+    use pkcs8::DecodePublicKey;
+    let der = base64_decode(public_key)?;
+    Ok(VerifyingKey::from_public_key_der(&der)?)
+}
+
 static SERVER_KEY : Lazy<SigningKey>  = Lazy::new(||{
     let private = base64_decode(&CONFIG.signing.private).expect("Could not decode config private key base64 encoding");
     let signing_key = SigningKey::from_pkcs8_der(&private).expect("Could not decode private key as PKCS8");
-    let public_key = base64_decode(&CONFIG.signing.public).expect("Could not decode config public key base64 encoding");
-    use pkcs8::DecodePublicKey;
-    let public_key = VerifyingKey::from_public_key_der(&public_key).expect("Could not decode config public key der encoding");
+    let public_key = parse_spki_public_key(&CONFIG.signing.public).expect("Could not decode config public key");
     let computed_public_key = signing_key.verifying_key();
     if computed_public_key.as_bytes()!=public_key.as_bytes() { panic!("Computed public key from server private key does not match supplied public key.")}
     signing_key
 });
 
+/// A short identifier for a server key, the first 8 bytes (16 hex characters) of the SHA256 hash of the raw 32 byte public key.
+pub fn server_key_id(public_key:&VerifyingKey) -> String {
+    use sha2::Digest;
+    hex::encode(&sha2::Sha256::digest(public_key.as_bytes())[..8])
+}
+
+static SERVER_KEY_ID : Lazy<String> = Lazy::new(||server_key_id(&SERVER_KEY.verifying_key()));
+
+pub fn get_server_key_id() -> String { SERVER_KEY_ID.clone() }
+
+/// The text that the previous server key signs to endorse its successor.
+pub fn server_key_endorsement_message(public_key_spki:&str,valid_from:Option<Timestamp>) -> String {
+    format!("RightToAsk server key {} valid from {}",public_key_spki,valid_from.unwrap_or(0))
+}
+
+/// A server key, current or past, with the period it was used for signing.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct ServerKeyHistoryEntry {
+    /// See [server_key_id]. This is the `key_id` field in [ServerSigned].
+    pub key_id : String,
+    /// Base64 encoded SPKI format, as returned by `/get_server_public_key_spki`
+    pub public_key_spki : String,
+    /// Base64 encoded raw 32 bytes, as returned by `/get_server_public_key_raw`
+    pub public_key_raw : String,
+    /// None for the first key, if its start was not recorded.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub valid_from : Option<Timestamp>,
+    /// None for the current key.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub valid_until : Option<Timestamp>,
+    /// Signature by the previous key in the chain of [server_key_endorsement_message] for this key. None for the first key.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub endorsement : Option<String>,
+}
+
+/// A server key from config.toml : public key, valid from, valid until, endorsement.
+type ConfiguredServerKey<'a> = (&'a String,Option<Timestamp>,Option<Timestamp>,&'a Option<String>);
+
+/// Build the history from a chain of keys, oldest first, checking each endorsement against the previous key.
+fn build_server_key_history(keys:&[ConfiguredServerKey]) -> anyhow::Result<Vec<ServerKeyHistoryEntry>> {
+    let mut res : Vec<ServerKeyHistoryEntry> = vec![];
+    let mut previous : Option<VerifyingKey> = None;
+    for &(public,valid_from,valid_until,endorsement) in keys {
+        let key = parse_spki_public_key(public).map_err(|e|anyhow!("Could not decode server public key {} : {}",public,e))?;
+        if let Some(previous) = previous {
+            let endorsement = endorsement.as_ref().ok_or_else(||anyhow!("Server key {} has no endorsement from the previous key",public))?;
+            let signature = base64_decode(endorsement).ok().and_then(|s|Signature::from_slice(&s).ok()).ok_or_else(||anyhow!("Could not decode endorsement for server key {}",public))?;
+            if previous.verify(server_key_endorsement_message(public,valid_from).as_bytes(),&signature).is_err() { return Err(anyhow!("Endorsement for server key {} is not valid",public)); }
+        }
+        res.push(ServerKeyHistoryEntry{
+            key_id: server_key_id(&key),
+            public_key_spki: public.clone(),
+            public_key_raw: base64_encode(key.as_bytes()),
+            valid_from,
+            valid_until,
+            endorsement: endorsement.clone(),
+        });
+        previous = Some(key);
+    }
+    Ok(res)
+}
+
+/// The chain of server keys from config.toml, oldest first, ending in the current key.
+fn configured_server_key_history() -> anyhow::Result<Vec<ServerKeyHistoryEntry>> {
+    let mut keys : Vec<ConfiguredServerKey> = CONFIG.previous_signing_keys.iter().map(|k|(&k.public,k.valid_from,Some(k.valid_until),&k.endorsement)).collect();
+    keys.push((&CONFIG.signing.public,CONFIG.signing.valid_from,None,&CONFIG.signing.endorsement));
+    build_server_key_history(&keys)
+}
+
+/// Check that each server key in config.toml is validly endorsed by the previous one. The server calls this at startup,
+/// and refuses to start if the chain is broken.
+pub fn check_server_key_history() -> anyhow::Result<()> {
+    configured_server_key_history().map(|_|())
+}
+
+/// See [configured_server_key_history]. A broken chain is a configuration error, found by [check_server_key_history] at startup.
+static SERVER_KEY_HISTORY : Lazy<Vec<ServerKeyHistoryEntry>> = Lazy::new(||configured_server_key_history().unwrap_or_else(|e|panic!("{}",e)));
+
+pub fn get_server_key_history() -> Vec<ServerKeyHistoryEntry> { SERVER_KEY_HISTORY.clone() }
+
+/// Sign, with the current server key, an endorsement of a new server key that will be used from `valid_from`.
+pub fn endorse_new_server_key(public_key_spki:&str,valid_from:Timestamp) -> anyhow::Result<String> {
+    parse_spki_public_key(public_key_spki)?;
+    Ok(sign_message(server_key_endorsement_message(public_key_spki,Some(valid_from)).as_bytes()))
+}
+
 // static SERVER_PRIVATE_EXPANDED_KEY : Lazy<ExpandedSecretKey> = Lazy::new(||{ (&SERVER_KEY.secret).into() });
 
 pub fn get_server_public_key_base64encoded() -> String {
@@ -77,13 +174,25 @@ pub struct ClientSigned<T,U=()> where U: DeserializeOwned {
 ///
 /// There might be extra, unsigned fields (such as an email address) which are
 /// included in U, if specified.
+///
+/// To prevent a captured message being replayed, the client should include a `timestamp` and `nonce`,
+/// in which case the signature is of the envelope described in [ClientSignedUnparsed::signed_bytes]
+/// rather than just the message. Messages without these are only accepted if the server
+/// is configured to accept legacy signatures (see `/info.json`).
 pub struct ClientSignedUnparsed<U=()> {
     /// The message is a JSON encoding of the actual command being sent from the client. The actual command is of type T.
     pub message : String,
-    /// the signature of the message
+    /// the signature of the message (or of the envelope, if timestamp and nonce are present).
     pub signature : String,
     /// unique ID of the user
     pub user : String,
+    /// The time the client signed the message, in seconds since the epoch. Must be within the server's freshness window.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub timestamp : Option<Timestamp>,
+    /// A random string chosen by the client, never reused by that user within the freshness window.
+    /// 8 to 64 characters from base64 or base64url encoding.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub nonce : Option<String>,
     #[serde(flatten)]
     pub unsigned : U,
 }
@@ -106,6 +215,18 @@ pub enum SignatureCheckError {
     BadSignature,
     UserBlocked,
     UserUnregistered,
+    /// The user is not listed as an administrator in config.toml
+    NotAnAdministrator,
+    /// The user is an administrator, but does not have a role allowing this action.
+    AdministratorRoleNotPermitted,
+    /// There is no timestamp and nonce, and the server no longer accepts legacy signatures.
+    MissingReplayProtection,
+    /// Only one of timestamp and nonce was provided, or the nonce has invalid characters or length.
+    InvalidReplayProtectionFormat,
+    /// The timestamp is too far from the server's current time.
+    StaleTimestamp,
+    /// This nonce has already been used by this user recently. The message may be a replay.
+    ReplayedNonce,
 }
 impl Display for SignatureCheckError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -113,33 +234,130 @@ impl Display for SignatureCheckError {
     }
 }
 
+/// Parse a user's public key, which is base64 encoded raw 32 bytes.
+fn parse_public_key(public_key:&str) -> Result<VerifyingKey, SignatureCheckError> {
+    let public_key = base64_decode(public_key).map_err(|_| SignatureCheckError::InvalidPublicKeyFormat)?;
+    if public_key.len()!=32 { return Err(SignatureCheckError::InvalidPublicKeyFormat)}
+    let mut public_key_fixed_size = [0u8;32];
+    public_key_fixed_size.copy_from_slice(&public_key);
+    VerifyingKey::from_bytes(&public_key_fixed_size).map_err(|_| SignatureCheckError::InvalidPublicKeyFormat)
+}
+
+/// Check whether a string is in the format of a user's public key, base64 encoded raw 32 bytes of an ED25519 public key.
+pub fn is_valid_public_key(public_key:&str) -> bool { parse_public_key(public_key).is_ok() }
+
+/// Check that `signature` (base64 encoded) is a valid signature of `message` by `public_key` (base64 encoded raw 32 bytes).
+fn verify_signature(public_key:&str,message:&[u8],signature:&str) -> Result<(), SignatureCheckError> {
+    let public_key = parse_public_key(public_key)?;
+    let signature = base64_decode(signature).map_err(|_| SignatureCheckError::InvalidSignatureFormat)?;
+    if signature.len()!=64 { return Err(SignatureCheckError::InvalidSignatureFormat)}
+    let mut signature_fixed_size = [0u8;64];
+    signature_fixed_size.copy_from_slice(&signature);
+    let signature = Signature::from_bytes(&signature_fixed_size);
+    public_key.verify(message,&signature).map_err(|_| SignatureCheckError::BadSignature)
+}
+
+/// Whether the server accepts client signatures without a timestamp and nonce. Clients at api_level 0 do not send them.
+pub fn legacy_client_signatures_accepted() -> bool { CONFIG.replay_protection.accept_legacy_signatures }
+
+/// The (user,nonce) pairs that have been used recently. They are kept for twice the freshness window,
+/// as the timestamp can be up to the window in the past or future.
+static SEEN_NONCES : Lazy<Mutex<TimeLimitedHashMap<(String,String),()>>> = Lazy::new(||Mutex::new(TimeLimitedHashMap::new(Duration::from_secs(2*CONFIG.replay_protection.freshness_window_seconds))));
+
+fn is_valid_nonce(nonce:&str) -> bool {
+    nonce.len()>=8 && nonce.len()<=64 && nonce.chars().all(|c|c.is_ascii_alphanumeric() || "+/=-_".contains(c))
+}
+
 impl <U> ClientSignedUnparsed<U> {
 
+    /// The bytes that are actually signed. This is the UTF-8 encoding of the message if there
+    /// is no timestamp or nonce (legacy clients), otherwise the UTF-8 encoding of the
+    /// string `timestamp:nonce:message`, where timestamp is in decimal. As nonce cannot contain
+    /// a colon, this is unambiguous.
+    pub fn signed_bytes(&self) -> Result<Vec<u8>,SignatureCheckError> {
+        match (self.timestamp,&self.nonce) {
+            (None,None) => Ok(self.message.as_bytes().to_vec()),
+            (Some(timestamp),Some(nonce)) if is_valid_nonce(nonce) => Ok(format!("{}:{}:{}",timestamp,nonce,self.message).into_bytes()),
+            _ => Err(SignatureCheckError::InvalidReplayProtectionFormat),
+        }
+    }
+
+    /// Check the signature against the given public keys (any may match), and then check that the message is fresh and has not been seen before.
+    fn verify_fresh(&self,public_keys:&[&str]) -> Result<(), SignatureCheckError> {
+        let now = timestamp_now().map_err(|_| SignatureCheckError::InternalError)?;
+        let mut seen = SEEN_NONCES.lock().map_err(|_| SignatureCheckError::InternalError)?;
+        self.verify_fresh_with(public_keys,&CONFIG.replay_protection,now,&mut seen)
+    }
+
+    /// [Self::verify_fresh] with the policy, current time and record of seen nonces given explicitly.
+    fn verify_fresh_with(&self,public_keys:&[&str],policy:&ReplayProtectionConfig,now:Timestamp,seen:&mut TimeLimitedHashMap<(String,String),()>) -> Result<(), SignatureCheckError> {
+        let signed_bytes = self.signed_bytes()?;
+        let mut result = Err(SignatureCheckError::BadSignature);
+        for public_key in public_keys {
+            result = verify_signature(public_key,&signed_bytes,&self.signature);
+            if result.is_ok() { break; }
+        }
+        result?;
+        match (self.timestamp,&self.nonce) {
+            (Some(timestamp),Some(nonce)) => {
+                if timestamp.abs_diff(now)>policy.freshness_window_seconds { return Err(SignatureCheckError::StaleTimestamp); }
+                let key = (self.user.clone(),nonce.clone());
+                if seen.get(&key).is_some() { return Err(SignatureCheckError::ReplayedNonce); }
+                seen.insert(key,());
+                Ok(())
+            }
+            _ if policy.accept_legacy_signatures => Ok(()),
+            _ => Err(SignatureCheckError::MissingReplayProtection),
+        }
+    }
+
     /// Check the signature, return Ok(()) if good, otherwise an error.
+    /// This also checks for replays, so should only be called once per message received.
     pub async fn check_signature(&self,need_to_have_validated_email:bool) -> Result<(), SignatureCheckError> {
         if let Some(signing_info) = get_user_public_key_by_id(&self.user).await.map_err(|_| SignatureCheckError::InternalError)? {
             if signing_info.blocked { return Err(SignatureCheckError::UserBlocked); }
             if CONFIG.require_validated_email && need_to_have_validated_email && !signing_info.email_validated { return Err(SignatureCheckError::UserUnregistered); }
-            let public_key = base64_decode(&signing_info.public_key).map_err(|_| SignatureCheckError::InvalidPublicKeyFormat)?;
-            if public_key.len()!=32 { return Err(SignatureCheckError::InvalidPublicKeyFormat)}
-            let mut public_key_fixed_size = [0u8;32];
-            public_key_fixed_size.copy_from_slice(&public_key);
-            let public_key = VerifyingKey::from_bytes(&public_key_fixed_size).map_err(|_| SignatureCheckError::InvalidPublicKeyFormat)?;
-            let signature = base64_decode(&self.signature).map_err(|_| SignatureCheckError::InvalidSignatureFormat)?;
-            if signature.len()!=64 { return Err(SignatureCheckError::InvalidSignatureFormat)}
-            let mut signature_fixed_size = [0u8;64];
-            signature_fixed_size.copy_from_slice(&signature);
-            let signature = Signature::from_bytes(&signature_fixed_size);
-            public_key.verify(self.message.as_bytes(),&signature).map_err(|_| SignatureCheckError::BadSignature)
+            let public_keys : Vec<&str> = signing_info.public_keys.iter().map(|k|k.as_str()).collect();
+            self.verify_fresh(&public_keys)
         } else { Err(SignatureCheckError::NoSuchUser) }
     }
 
+    /// Check the signature against a given public key rather than the user's registered key.
+    /// This is used for account recovery, where the request is signed by the new key as the old key is lost.
+    pub fn check_signature_by_key(&self,public_key:&str) -> Result<(), SignatureCheckError> {
+        self.verify_fresh(&[public_key])
+    }
+
+    /// Whether the signature is valid for the given public key. This does not check freshness, and
+    /// is used to work out which of a user's keys signed a message that has already been checked.
+    pub fn is_signed_by(&self,public_key:&str) -> bool {
+        self.signed_bytes().is_ok_and(|signed_bytes|verify_signature(public_key,&signed_bytes,&self.signature).is_ok())
+    }
+
+    /// Check the signature of a command sent to an administrative or moderation API.
+    /// The user must be listed in the `administrators` section of config.toml, and the
+    /// message signed by the corresponding key. The administrator must have at least one of the `allowed_roles`.
+    /// Returns the name of the administrator if good.
+    pub fn check_administrator_signature(&self,allowed_roles:&[AdministratorRole]) -> Result<&str, SignatureCheckError> {
+        self.check_administrator_signature_with(&CONFIG.administrators,allowed_roles,|public_key|self.verify_fresh(&[public_key]))
+    }
+
+    /// [Self::check_administrator_signature] with the list of administrators given explicitly. `verify` checks the signature against a public key.
+    fn check_administrator_signature_with<'a>(&self,administrators:&'a [AdministratorConfig],allowed_roles:&[AdministratorRole],verify:impl FnOnce(&str)->Result<(), SignatureCheckError>) -> Result<&'a str, SignatureCheckError> {
+        let administrator = administrators.iter().find(|a|a.name==self.user).ok_or(SignatureCheckError::NotAnAdministrator)?;
+        verify(&administrator.public_key)?;
+        if !administrator.roles.iter().any(|r|allowed_roles.contains(r)) { return Err(SignatureCheckError::AdministratorRoleNotPermitted); }
+        Ok(&administrator.name)
+    }
+
     /// Clone this, discarding any unsigned part. If (as usually) U=() then this is same as clone().
     pub fn just_signed_part(&self) -> ClientSignedUnparsed<()> {
         ClientSignedUnparsed{
             message: self.message.clone(),
             signature: self.signature.clone(),
             user: self.user.clone(),
+            timestamp: self.timestamp,
+            nonce: self.nonce.clone(),
             unsigned: ()
         }
     }
@@ -148,9 +366,12 @@ impl <U> ClientSignedUnparsed<U> {
     pub fn sign(message:String,user:&str,private_key:&str,unsigned:U) ->  ClientSignedUnparsed<U> {
         let private_key = base64_decode(&private_key).expect("Could not decode test private key base64 encoding");
         let signer = SigningKey::from_pkcs8_der(&private_key).expect("Could not decode test private key as PKCS8");
-        let signature = signer.sign(message.as_bytes());
-        let signature = base64_encode(signature.to_bytes());
-        ClientSignedUnparsed{ message,signature,user: user.to_string(),unsigned }
+        let timestamp = timestamp_now().expect("Could not get time");
+        let nonce = hex::encode(rand::random::<[u8;16]>());
+        let mut res = ClientSignedUnparsed{ message,signature:String::new(),user: user.to_string(),timestamp:Some(timestamp),nonce:Some(nonce),unsigned };
+        let signature = signer.sign(&res.signed_bytes().unwrap());
+        res.signature = base64_encode(signature.to_bytes());
+        res
     }
 }
 
@@ -167,30 +388,50 @@ pub const DEFAULT_TESTING_PUBLIC_KEY : &str = "1chhwoStgwImuvUkLcZ5RhHjloRTp82of
 #[cfg(test)]
 /// A test public key for use in unit tests. There is no reason not to make this public. It is only used for unit tests. Don't use it for anything else.
 /// See DEFAULT_TESTING_PUBLIC_KEY for how it is made using openssl
-const DEFAULT_TESTING_SECRET_KEY : &str = "MC4CAQAwBQYDK2VwBCIEICMI7uUJF/iueFO6T5xin638TU7y/6I6avrAM47VzBpr"; // Note that this key is just for testing and does not actually keep anything secure and is not used outside of unit testing.
+pub const DEFAULT_TESTING_SECRET_KEY : &str = "MC4CAQAwBQYDK2VwBCIEICMI7uUJF/iueFO6T5xin638TU7y/6I6avrAM47VzBpr"; // Note that this key is just for testing and does not actually keep anything secure and is not used outside of unit testing.
 #[cfg(test)]
 pub async fn make_test_signed<T:Serialize+DeserializeOwned,U:DeserializeOwned>(user:&str,to_be_signed:&T,unsigned:U) -> ClientSigned<T,U> {
+    let signed = make_test_signed_by_key(user,to_be_signed,DEFAULT_TESTING_SECRET_KEY,unsigned);
+    signed.signed_message.check_signature(false).await.unwrap();
+    signed
+}
+
+/// Sign as `user` with the given private key, in the form of [DEFAULT_TESTING_SECRET_KEY]. Unlike [make_test_signed],
+/// the signature is not checked, so it may be checked however the test wants.
+#[cfg(test)]
+pub fn make_test_signed_by_key<T:Serialize+DeserializeOwned,U:DeserializeOwned>(user:&str,to_be_signed:&T,private_key:&str,unsigned:U) -> ClientSigned<T,U> {
     let message = serde_json::to_string(to_be_signed).expect("Could not serialize to_be_signed");
-    let unparsed = ClientSignedUnparsed::sign(message,user,DEFAULT_TESTING_SECRET_KEY,unsigned);
-    unparsed.check_signature(false).await.unwrap();
-    unparsed.try_into().expect("Could not parse the signed client")
+    ClientSignedUnparsed::sign(message,user,private_key,unsigned).try_into().expect("Could not parse the signed client")
+}
+
+/// A key pair for tests other than the default one, made deterministically from `seed`. Returns (private key, public key),
+/// the private key in the form of [DEFAULT_TESTING_SECRET_KEY] and the public key in the form of [DEFAULT_TESTING_PUBLIC_KEY].
+#[cfg(test)]
+pub fn make_test_key_pair(seed:u8) -> (String,String) {
+    const PKCS8_ED25519_PREFIX : [u8;16] = [0x30,0x2e,0x02,0x01,0x00,0x30,0x05,0x06,0x03,0x2b,0x65,0x70,0x04,0x22,0x04,0x20];
+    let secret = [seed;32];
+    let public_key = SigningKey::from_bytes(&secret).verifying_key().to_bytes();
+    (base64_encode([&PKCS8_ED25519_PREFIX[..],&secret[..]].concat()),base64_encode(public_key))
 }
 
 #[derive(Serialize,Deserialize,Debug,Clone)] // deserialization probably won't be needed.
 pub struct ServerSigned {
     message : String,
-    signature : String
+    signature : String,
+    /// Which server key made the signature. See `/get_server_key_history`. Absent in receipts made before key rotation was supported.
+    #[serde(default)]
+    key_id : String,
 }
 
 impl ServerSigned {
     pub fn new(x:&impl Serialize) -> serde_json::Result<Self> {
         let message = serde_json::to_string(x)?;
         let signature = sign_message(message.as_bytes());
-        Ok(ServerSigned{ message, signature })
+        Ok(ServerSigned{ message, signature, key_id: get_server_key_id() })
     }
     pub fn new_string(message : String) -> Self {
         let signature = sign_message(message.as_bytes());
-        ServerSigned{ message, signature }
+        ServerSigned{ message, signature, key_id: get_server_key_id() }
     }
 
     pub fn sign<T:Serialize,E:ToString>(r:Result<T,E>) -> Result<ServerSigned,String> {
@@ -207,3 +448,109 @@ impl ServerSigned {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::administration::AdministratorRole::{Admin, Moderator};
+
+    fn policy(accept_legacy_signatures:bool) -> ReplayProtectionConfig {
+        ReplayProtectionConfig{ freshness_window_seconds: 300, accept_legacy_signatures }
+    }
+
+    fn seen() -> TimeLimitedHashMap<(String,String),()> { TimeLimitedHashMap::new(Duration::from_secs(600)) }
+
+    #[test]
+    fn test_signed_bytes() {
+        let mut m = ClientSignedUnparsed{ message: "{}".to_string(), signature: String::new(), user: "u".to_string(), timestamp: None, nonce: None, unsigned: () };
+        assert_eq!(m.signed_bytes().unwrap(),b"{}");
+        m.timestamp = Some(1000);
+        assert!(matches!(m.signed_bytes(),Err(SignatureCheckError::InvalidReplayProtectionFormat)));
+        m.nonce = Some("abcdefgh".to_string());
+        assert_eq!(m.signed_bytes().unwrap(),b"1000:abcdefgh:{}");
+        m.nonce = Some("abc:defgh".to_string()); // a colon would make the envelope ambiguous.
+        assert!(matches!(m.signed_bytes(),Err(SignatureCheckError::InvalidReplayProtectionFormat)));
+        m.nonce = Some("short".to_string());
+        assert!(matches!(m.signed_bytes(),Err(SignatureCheckError::InvalidReplayProtectionFormat)));
+    }
+
+    #[test]
+    fn test_verify_fresh() {
+        let m = ClientSignedUnparsed::sign("{}".to_string(),"u",DEFAULT_TESTING_SECRET_KEY,());
+        let now = m.timestamp.unwrap();
+        let mut seen = seen();
+        assert!(m.verify_fresh_with(&["AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="],&policy(true),now,&mut seen).is_err());
+        assert!(m.verify_fresh_with(&[DEFAULT_TESTING_PUBLIC_KEY],&policy(true),now+300,&mut seen).is_ok());
+        assert!(matches!(m.verify_fresh_with(&[DEFAULT_TESTING_PUBLIC_KEY],&policy(true),now,&mut seen),Err(SignatureCheckError::ReplayedNonce)));
+        // The same nonce from a different user is not a replay.
+        let mut other = m.clone();
+        other.user = "v".to_string();
+        assert!(other.verify_fresh_with(&[DEFAULT_TESTING_PUBLIC_KEY],&policy(true),now,&mut seen).is_ok());
+        let m = ClientSignedUnparsed::sign("{}".to_string(),"u",DEFAULT_TESTING_SECRET_KEY,());
+        let now = m.timestamp.unwrap();
+        assert!(matches!(m.verify_fresh_with(&[DEFAULT_TESTING_PUBLIC_KEY],&policy(true),now+301,&mut seen),Err(SignatureCheckError::StaleTimestamp)));
+        assert!(matches!(m.verify_fresh_with(&[DEFAULT_TESTING_PUBLIC_KEY],&policy(true),now-301,&mut seen),Err(SignatureCheckError::StaleTimestamp)));
+        // A stale message does not use up its nonce.
+        assert!(m.verify_fresh_with(&[DEFAULT_TESTING_PUBLIC_KEY],&policy(true),now,&mut seen).is_ok());
+        // Changing the timestamp invalidates the signature.
+        let mut altered = ClientSignedUnparsed::sign("{}".to_string(),"u",DEFAULT_TESTING_SECRET_KEY,());
+        altered.timestamp = Some(now+1);
+        assert!(matches!(altered.verify_fresh_with(&[DEFAULT_TESTING_PUBLIC_KEY],&policy(true),now,&mut seen),Err(SignatureCheckError::BadSignature)));
+    }
+
+    #[test]
+    fn test_key_pair_matches_default_form() {
+        let (private_key,public_key) = make_test_key_pair(7);
+        assert_eq!(base64_decode(&private_key).unwrap().len(),base64_decode(DEFAULT_TESTING_SECRET_KEY).unwrap().len());
+        let m = ClientSignedUnparsed::sign("{}".to_string(),"u",&private_key,());
+        assert!(m.is_signed_by(&public_key));
+        assert!(!m.is_signed_by(DEFAULT_TESTING_PUBLIC_KEY));
+    }
+
+    #[test]
+    fn test_legacy_signatures() {
+        let signed = ClientSignedUnparsed::sign("{}".to_string(),"u",DEFAULT_TESTING_SECRET_KEY,());
+        let private_key = base64_decode(DEFAULT_TESTING_SECRET_KEY).unwrap();
+        let signer = SigningKey::from_pkcs8_der(&private_key).unwrap();
+        let legacy = ClientSignedUnparsed{ message: "{}".to_string(), signature: base64_encode(signer.sign(b"{}").to_bytes()), user: "u".to_string(), timestamp: None, nonce: None, unsigned: () };
+        let now = signed.timestamp.unwrap();
+        assert!(legacy.verify_fresh_with(&[DEFAULT_TESTING_PUBLIC_KEY],&policy(true),now,&mut seen()).is_ok());
+        assert!(matches!(legacy.verify_fresh_with(&[DEFAULT_TESTING_PUBLIC_KEY],&policy(false),now,&mut seen()),Err(SignatureCheckError::MissingReplayProtection)));
+    }
+
+    #[test]
+    fn test_server_key_history() {
+        use pkcs8::EncodePublicKey;
+        let signers : Vec<SigningKey> = (1..=3u8).map(|i|SigningKey::from_bytes(&[i;32])).collect();
+        let spki : Vec<String> = signers.iter().map(|k|base64_encode(k.verifying_key().to_public_key_der().unwrap().as_bytes())).collect();
+        let endorse = |by:usize,public:&str,valid_from:Timestamp|Some(base64_encode(signers[by].sign(server_key_endorsement_message(public,Some(valid_from)).as_bytes()).to_bytes()));
+        let e1 = endorse(0,&spki[1],100);
+        let e2 = endorse(1,&spki[2],200);
+        let chain : Vec<ConfiguredServerKey> = vec![(&spki[0],None,Some(100),&None),(&spki[1],Some(100),Some(200),&e1),(&spki[2],Some(200),None,&e2)];
+        assert_eq!(build_server_key_history(&chain).unwrap().len(),3);
+        // endorsed by the wrong key
+        let wrong = endorse(0,&spki[2],200);
+        assert!(build_server_key_history(&[chain[0],chain[1],(&spki[2],Some(200),None,&wrong)]).is_err());
+        // endorsement for a different start time
+        assert!(build_server_key_history(&[chain[0],chain[1],(&spki[2],Some(201),None,&e2)]).is_err());
+        // missing endorsement
+        assert!(build_server_key_history(&[chain[0],(&spki[1],Some(100),None,&None)]).is_err());
+    }
+
+    #[test]
+    fn test_administrator_roles() {
+        let administrators = vec![
+            AdministratorConfig{ name: "mod".to_string(), public_key: DEFAULT_TESTING_PUBLIC_KEY.to_string(), roles: vec![Moderator] },
+            AdministratorConfig{ name: "admin".to_string(), public_key: DEFAULT_TESTING_PUBLIC_KEY.to_string(), roles: vec![Admin] },
+        ];
+        let check = |user:&str,allowed:&[AdministratorRole]| {
+            let m = ClientSignedUnparsed::sign("{}".to_string(),user,DEFAULT_TESTING_SECRET_KEY,());
+            let now = m.timestamp.unwrap();
+            m.check_administrator_signature_with(&administrators,allowed,|key|m.verify_fresh_with(&[key],&policy(false),now,&mut seen())).map(|s|s.to_string())
+        };
+        assert_eq!(check("mod",&[Moderator,Admin]).unwrap(),"mod");
+        assert!(matches!(check("mod",&[Admin]),Err(SignatureCheckError::AdministratorRoleNotPermitted)));
+        assert!(matches!(check("admin",&[Moderator]),Err(SignatureCheckError::AdministratorRoleNotPermitted)));
+        assert!(matches!(check("nobody",&[Moderator,Admin]),Err(SignatureCheckError::NotAnAdministrator)));
+    }
+}
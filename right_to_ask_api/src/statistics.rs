@@ -0,0 +1,285 @@
+//! How responsive MPs are to the questions directed at them.
+//!
+//! For each MP, and aggregated by party, chamber and jurisdiction (state or Federal), this counts
+//! * the questions the MP was asked to answer,
+//! * how many of those they answered, and how many answers they gave to them,
+//! * the median time from a question being asked to the MP's first answer to it,
+//! * the fraction of those answers that the question's author accepted.
+//!
+//! Censored questions and answers are ignored, as are answers to questions the MP was not asked to answer. Questions directed to a ministry or committee rather than
+//! the MP directly are not included. These are computed periodically by [refresh_answer_statistics], as they
+//! involve scanning every answer.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use merkle_tree_bulletin_board::hash_history::{Timestamp, timestamp_now};
+use mysql::prelude::Queryable;
+use once_cell::sync::Lazy;
+use serde::{Serialize, Deserialize};
+use crate::censorship::CensorshipStatus;
+use crate::config::CONFIG;
+use crate::database::get_rta_database_connection;
+use crate::mp::{MPId, MPIndexInDatabaseTable, MPSpec};
+use crate::question::{hash_from_value, internal_error, QuestionError, QuestionID};
+use crate::regions::{Chamber, Electorate, Jurisdiction};
+
+/// Statistics for an MP or a group of MPs.
+#[derive(Serialize,Deserialize,Debug,Clone,Default,PartialEq)]
+pub struct AnswerStatistics {
+    /// The number of questions asking the MP(s) to answer.
+    pub questions_received : usize,
+    /// The number of those questions that the MP(s) answered.
+    pub questions_answered : usize,
+    /// The number of answers given to those questions. An MP may give several answers to one question.
+    pub answers_given : usize,
+    /// The median, in seconds, of the time from a question being created to the MP's first answer to it.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub median_seconds_to_answer : Option<Timestamp>,
+    /// The fraction of answers given that were accepted by the question's author.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub acceptance_rate : Option<f64>,
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct MPAnswerStatistics {
+    pub mp : MPId,
+    /// From the current MP list. None if the MP is no longer listed.
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub party : Option<String>,
+    #[serde(flatten)]
+    pub statistics : AnswerStatistics,
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct PartyAnswerStatistics {
+    pub party : String,
+    #[serde(flatten)]
+    pub statistics : AnswerStatistics,
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct ChamberAnswerStatistics {
+    pub chamber : Chamber,
+    #[serde(flatten)]
+    pub statistics : AnswerStatistics,
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct JurisdictionAnswerStatistics {
+    pub jurisdiction : Jurisdiction,
+    #[serde(flatten)]
+    pub statistics : AnswerStatistics,
+}
+
+/// All the answer statistics, as computed at a given time.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct AllAnswerStatistics {
+    /// When these were computed.
+    pub computed : Timestamp,
+    pub by_mp : Vec<MPAnswerStatistics>,
+    pub by_party : Vec<PartyAnswerStatistics>,
+    pub by_chamber : Vec<ChamberAnswerStatistics>,
+    pub by_jurisdiction : Vec<JurisdictionAnswerStatistics>,
+}
+
+/// The median of some values. Sorts them.
+fn median(values:&mut [Timestamp]) -> Option<Timestamp> {
+    if values.is_empty() { return None; }
+    values.sort_unstable();
+    let mid = values.len()/2;
+    Some(if values.len().is_multiple_of(2) { (values[mid-1]+values[mid])/2 } else { values[mid] })
+}
+
+/// The state or Federal jurisdiction a chamber is in.
+fn place_jurisdiction(chamber:Chamber) -> Option<Jurisdiction> {
+    use Jurisdiction::*;
+    [Federal,ACT,NSW,NT,QLD,SA,TAS,VIC,WA].into_iter().find(|j|j.compatible_with(chamber))
+}
+
+/// Statistics being accumulated for an MP or group.
+#[derive(Default)]
+struct Accumulator {
+    received : HashSet<QuestionID>,
+    answered : HashSet<QuestionID>,
+    answers : usize,
+    accepted : usize,
+    /// time to first answer, for each (MP,question) answered.
+    times : Vec<Timestamp>,
+}
+
+impl Accumulator {
+    fn add(&mut self,other:&Accumulator) {
+        self.received.extend(other.received.iter().cloned());
+        self.answered.extend(other.answered.iter().cloned());
+        self.answers+=other.answers;
+        self.accepted+=other.accepted;
+        self.times.extend_from_slice(&other.times);
+    }
+
+    fn finish(&self) -> AnswerStatistics {
+        AnswerStatistics{
+            questions_received: self.received.len(),
+            questions_answered: self.answered.len(),
+            answers_given: self.answers,
+            median_seconds_to_answer: median(&mut self.times.clone()),
+            acceptance_rate: if self.answers==0 { None } else { Some(self.accepted as f64/self.answers as f64) },
+        }
+    }
+}
+
+/// Combine the statistics for each MP into groups, sorted by name.
+fn group_statistics<K:Eq+std::hash::Hash+std::fmt::Display,T>(by_mp:&[(MPId,Option<String>,Accumulator)],key:impl Fn(&MPId,&Option<String>)->Option<K>,make:impl Fn(K,AnswerStatistics)->T) -> Vec<T> {
+    let mut groups : HashMap<K,Accumulator> = HashMap::new();
+    for (mp,party,acc) in by_mp {
+        if let Some(k) = key(mp,party) { groups.entry(k).or_default().add(acc); }
+    }
+    let mut groups : Vec<(K,Accumulator)> = groups.into_iter().collect();
+    groups.sort_by_key(|(k,_)|k.to_string());
+    groups.into_iter().map(|(k,acc)|make(k,acc.finish())).collect()
+}
+
+/// An MP asked to answer a question, and the question's censorship status, as selected by [compute_answer_statistics].
+type ReceivedRow = (MPIndexInDatabaseTable,mysql::Value,CensorshipStatus);
+/// The columns of an answer as selected by [compute_answer_statistics]: the MP, the question, when it was answered,
+/// whether it was accepted, when the question was created, and the censorship status of the answer and question.
+type AnswerRow = (MPIndexInDatabaseTable,mysql::Value,Timestamp,bool,Timestamp,CensorshipStatus,CensorshipStatus);
+/// The columns of MP_IDs as selected by [compute_answer_statistics].
+type MPRow = (MPIndexInDatabaseTable,Chamber,Option<String>,String,String);
+
+/// Accumulate the statistics for each MP, leaving out censored questions and answers, and answers to questions
+/// the MP was not asked to answer.
+fn accumulate_by_mp(received:Vec<ReceivedRow>,answers:Vec<AnswerRow>) -> HashMap<MPIndexInDatabaseTable,Accumulator> {
+    let mut by_mp : HashMap<MPIndexInDatabaseTable,Accumulator> = HashMap::new();
+    for (mp,question,status) in received {
+        if status==CensorshipStatus::Censored { continue; }
+        by_mp.entry(mp).or_default().received.insert(hash_from_value(question));
+    }
+    let mut first_answers : HashMap<(MPIndexInDatabaseTable,QuestionID),Timestamp> = HashMap::new();
+    for (mp,question,answered,accepted,created,answer_status,question_status) in answers {
+        if answer_status==CensorshipStatus::Censored || question_status==CensorshipStatus::Censored { continue; }
+        let question = hash_from_value(question);
+        let Some(acc) = by_mp.get_mut(&mp) else { continue; };
+        if !acc.received.contains(&question) { continue; }
+        acc.answers+=1;
+        if accepted { acc.accepted+=1; }
+        acc.answered.insert(question);
+        let time = answered.saturating_sub(created);
+        first_answers.entry((mp,question)).and_modify(|t|*t=(*t).min(time)).or_insert(time);
+    }
+    for ((mp,_),time) in first_answers {
+        if let Some(acc) = by_mp.get_mut(&mp) { acc.times.push(time); }
+    }
+    by_mp
+}
+
+/// Name the MPs in `by_mp` using the MP_IDs rows, and group them by party (from the current MP list), chamber and jurisdiction.
+fn summarise(mut by_mp:HashMap<MPIndexInDatabaseTable,Accumulator>,mp_ids:Vec<MPRow>,mps:&MPSpec,computed:Timestamp) -> AllAnswerStatistics {
+    let mut named : Vec<(MPId,Option<String>,Accumulator)> = vec![];
+    for (id,chamber,region,first_name,surname) in mp_ids {
+        if let Some(acc) = by_mp.remove(&id) {
+            let mp = MPId{ first_name, surname, electorate: Electorate{ chamber, region } };
+            let party = mps.find(&mp).map(|m|m.party.clone());
+            named.push((mp,party,acc));
+        }
+    }
+    let by_party = group_statistics(&named,|_,party|party.clone(),|party,statistics|PartyAnswerStatistics{party,statistics});
+    let by_chamber = group_statistics(&named,|mp,_|Some(mp.electorate.chamber),|chamber,statistics|ChamberAnswerStatistics{chamber,statistics});
+    let by_jurisdiction = group_statistics(&named,|mp,_|place_jurisdiction(mp.electorate.chamber),|jurisdiction,statistics|JurisdictionAnswerStatistics{jurisdiction,statistics});
+    let by_mp = named.into_iter().map(|(mp,party,acc)|MPAnswerStatistics{mp,party,statistics:acc.finish()}).collect();
+    AllAnswerStatistics{ computed, by_mp, by_party, by_chamber, by_jurisdiction }
+}
+
+async fn compute_answer_statistics() -> Result<AllAnswerStatistics,QuestionError> {
+    let mut conn = get_rta_database_connection().await.map_err(internal_error)?;
+    let received : Vec<ReceivedRow> = conn.exec("select distinct p.MP,p.QuestionId,q.CensorshipStatus from PersonForQuestion p inner join QUESTIONS q on p.QuestionId=q.QuestionId where p.ROLE='A' and p.MP is not NULL",()).map_err(internal_error)?;
+    let answers : Vec<AnswerRow> = conn.exec("select a.MP,a.QuestionId,a.timestamp,a.Accepted,q.CreatedTimestamp,a.CensorshipStatus,q.CensorshipStatus from Answer a inner join QUESTIONS q on a.QuestionId=q.QuestionId",()).map_err(internal_error)?;
+    let by_mp = accumulate_by_mp(received,answers);
+    let mp_ids : Vec<MPRow> = conn.exec("select id,Chamber,Electorate,FirstName,LastName from MP_IDs",()).map_err(internal_error)?;
+    let mps = MPSpec::get().map_err(internal_error)?;
+    Ok(summarise(by_mp,mp_ids,&mps,timestamp_now().map_err(internal_error)?))
+}
+
+static ANSWER_STATISTICS : Lazy<Mutex<Option<Arc<AllAnswerStatistics>>>> = Lazy::new(||Mutex::new(None));
+
+/// Recompute the statistics. Called periodically by the server; see [answer_statistics_refresh_interval].
+pub async fn refresh_answer_statistics() -> Result<Arc<AllAnswerStatistics>,QuestionError> {
+    let statistics = Arc::new(compute_answer_statistics().await?);
+    *ANSWER_STATISTICS.lock().unwrap() = Some(statistics.clone());
+    Ok(statistics)
+}
+
+/// Get the most recently computed statistics, computing them if this has not been done yet.
+pub async fn get_answer_statistics() -> Result<Arc<AllAnswerStatistics>,QuestionError> {
+    let existing = ANSWER_STATISTICS.lock().unwrap().clone();
+    match existing {
+        Some(statistics) => Ok(statistics),
+        None => refresh_answer_statistics().await,
+    }
+}
+
+/// How often [refresh_answer_statistics] should be called.
+pub fn answer_statistics_refresh_interval() -> Duration {
+    Duration::from_secs(CONFIG.statistics_refresh_seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mp::MP;
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&mut []),None);
+        assert_eq!(median(&mut [5]),Some(5));
+        assert_eq!(median(&mut [9,1,5]),Some(5));
+        assert_eq!(median(&mut [8,2,4,6]),Some(5));
+    }
+
+    fn q(n:u8) -> mysql::Value { mysql::Value::Bytes(vec![n;32]) }
+
+    fn stats(questions_received:usize,questions_answered:usize,answers_given:usize,median_seconds_to_answer:Option<Timestamp>,acceptance_rate:Option<f64>) -> AnswerStatistics {
+        AnswerStatistics{ questions_received, questions_answered, answers_given, median_seconds_to_answer, acceptance_rate }
+    }
+
+    fn mp(first_name:&str,chamber:Chamber,region:&str,party:&str) -> MP {
+        MP{ first_name: first_name.to_string(), surname: "Citizen".to_string(), electorate: Electorate{ chamber, region: Some(region.to_string()) }, email: String::new(), role: String::new(), party: party.to_string(), non_authoritative: None }
+    }
+
+    #[test]
+    fn statistics_by_mp_and_group() {
+        use CensorshipStatus::*;
+        let received : Vec<ReceivedRow> = vec![(1,q(1),NotFlagged),(1,q(2),Flagged),(1,q(9),Censored),(2,q(1),NotFlagged),(3,q(3),Allowed),(4,q(4),NotFlagged)];
+        let answers : Vec<AnswerRow> = vec![
+            (1,q(1),100,true,0,NotFlagged,NotFlagged),
+            (1,q(1),50,false,0,NotFlagged,NotFlagged),
+            (1,q(2),30,true,10,Censored,Flagged), // censored answer
+            (1,q(9),10,true,0,NotFlagged,Censored), // censored question
+            (1,q(3),10,true,0,NotFlagged,NotFlagged), // MP 1 was not asked to answer
+            (2,q(1),400,true,0,NotFlagged,NotFlagged),
+            (3,q(3),70,false,10,NotFlagged,Allowed),
+        ];
+        let listed = [mp("Jane",Chamber::Australian_House_Of_Representatives,"Bean","Greens"),mp("John",Chamber::Australian_Senate,"ACT","Greens"),mp("Sam",Chamber::NSW_Legislative_Assembly,"Sydney","Labor")];
+        let mps = MPSpec{ mps: listed.to_vec(), federal_electorates_by_state: vec![], vic_districts: vec![] };
+        let mut mp_ids : Vec<MPRow> = listed.iter().enumerate().map(|(i,m)|(i+1,m.electorate.chamber,m.electorate.region.clone(),m.first_name.clone(),m.surname.clone())).collect();
+        mp_ids.push((4,Chamber::Australian_House_Of_Representatives,Some("Canberra".to_string()),"Former".to_string(),"Member".to_string()));
+        let all = summarise(accumulate_by_mp(received,answers),mp_ids,&mps,0);
+        let by_mp : Vec<(&str,Option<&str>,AnswerStatistics)> = all.by_mp.iter().map(|s|(s.mp.first_name.as_str(),s.party.as_deref(),s.statistics.clone())).collect();
+        assert_eq!(by_mp,vec![
+            ("Jane",Some("Greens"),stats(2,1,2,Some(50),Some(0.5))),
+            ("John",Some("Greens"),stats(1,1,1,Some(400),Some(1.0))),
+            ("Sam",Some("Labor"),stats(1,1,1,Some(60),Some(0.0))),
+            ("Former",None,stats(1,0,0,None,None)),
+        ]);
+        let by_party : Vec<(&str,AnswerStatistics)> = all.by_party.iter().map(|s|(s.party.as_str(),s.statistics.clone())).collect();
+        assert_eq!(by_party,vec![("Greens",stats(2,1,3,Some(225),Some(2.0/3.0))),("Labor",stats(1,1,1,Some(60),Some(0.0)))]);
+        let by_chamber : Vec<(Chamber,AnswerStatistics)> = all.by_chamber.iter().map(|s|(s.chamber,s.statistics.clone())).collect();
+        assert_eq!(by_chamber,vec![
+            (Chamber::Australian_House_Of_Representatives,stats(3,1,2,Some(50),Some(0.5))),
+            (Chamber::Australian_Senate,stats(1,1,1,Some(400),Some(1.0))),
+            (Chamber::NSW_Legislative_Assembly,stats(1,1,1,Some(60),Some(0.0))),
+        ]);
+        let by_jurisdiction : Vec<(Jurisdiction,AnswerStatistics)> = all.by_jurisdiction.iter().map(|s|(s.jurisdiction,s.statistics.clone())).collect();
+        assert_eq!(by_jurisdiction,vec![(Jurisdiction::Federal,stats(3,1,3,Some(225),Some(2.0/3.0))),(Jurisdiction::NSW,stats(1,1,1,Some(60),Some(0.0)))]);
+    }
+}
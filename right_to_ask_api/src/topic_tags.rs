@@ -0,0 +1,196 @@
+//! Topic tags on questions, such as "health" or "housing", so questions can be browsed by subject.
+//!
+//! Tags come from a controlled vocabulary kept in the TopicTags table, which administrators add to
+//! (optionally seeding it from the listed keywords used for question comparison). Tags are added to questions
+//! via [crate::question::QuestionNonDefiningFields::topic_tags].
+
+use std::collections::HashSet;
+use anyhow::anyhow;
+use mysql::prelude::Queryable;
+use serde::{Serialize, Deserialize};
+use crate::database::{get_rta_database_connection, listed_keyword_canonical_names};
+use crate::question::{hash_from_value, internal_error, Permissions, QuestionError, QuestionID};
+
+/// A topic tag. Always lower case.
+pub type TopicTag = String;
+
+/// The maximum number of topic tags on a single question.
+pub const MAX_TOPIC_TAGS_PER_QUESTION : usize = 10;
+const MAX_TOPIC_TAG_LENGTH : usize = 40;
+
+/// The canonical (lower case, trimmed) form of a tag.
+pub fn canonicalise_topic_tag(tag:&str) -> TopicTag { tag.trim().to_lowercase() }
+
+fn is_valid_topic_tag(tag:&str) -> bool {
+    !tag.is_empty() && tag.len()<=MAX_TOPIC_TAG_LENGTH && tag.chars().all(|c|c.is_alphanumeric()||c==' '||c=='-')
+}
+
+/// An entry in the vocabulary of topic tags.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct TopicTagInfo {
+    pub tag : TopicTag,
+    #[serde(skip_serializing_if = "Option::is_none",default)]
+    pub description : Option<String>,
+    /// The number of questions (not withdrawn or censored) with this tag. Ignored when adding tags.
+    #[serde(default)]
+    pub num_questions : usize,
+}
+
+/// Check that a user may add topic tags to a question, given whether they wrote it and the question's
+/// topic_tags_permissions (None for a new question).
+pub(crate) fn check_may_add_topic_tags(is_creator:bool,permissions:Option<Permissions>) -> Result<(),QuestionError> {
+    if is_creator || permissions==Some(Permissions::Others) { Ok(()) } else { Err(QuestionError::OnlyAuthorCanChangeTopicTags) }
+}
+
+/// The canonical forms of the `requested` tags not already in `existing`, without repeats, in the order requested.
+/// Fails if the question would end up with more than MAX_TOPIC_TAGS_PER_QUESTION tags.
+pub(crate) fn extra_topic_tags(existing:&[TopicTag],requested:&[TopicTag]) -> Result<Vec<TopicTag>,QuestionError> {
+    let mut seen : HashSet<TopicTag> = existing.iter().map(|t|canonicalise_topic_tag(t)).collect();
+    let num_existing = seen.len();
+    let extra : Vec<TopicTag> = requested.iter().map(|t|canonicalise_topic_tag(t)).filter(|t|seen.insert(t.clone())).collect();
+    if num_existing+extra.len()>MAX_TOPIC_TAGS_PER_QUESTION { Err(QuestionError::TooManyTopicTags) } else { Ok(extra) }
+}
+
+/// The questions in every one of the sets. Empty if there are no sets.
+fn in_all(sets:impl IntoIterator<Item=HashSet<QuestionID>>) -> HashSet<QuestionID> {
+    sets.into_iter().reduce(|a,b|a.intersection(&b).cloned().collect()).unwrap_or_default()
+}
+
+/// Get the ids in the TopicTags table for the given tags, failing if any are not in the vocabulary.
+fn get_tag_ids(conn:&mut impl Queryable,tags:&[TopicTag]) -> Result<Vec<u32>,QuestionError> {
+    let mut res = vec![];
+    for tag in tags {
+        let id : u32 = conn.exec_first("select id from TopicTags where Tag=?",(canonicalise_topic_tag(tag),)).map_err(internal_error)?.ok_or(QuestionError::UnknownTopicTag)?;
+        res.push(id);
+    }
+    Ok(res)
+}
+
+/// Check that the tags are all in the vocabulary.
+pub(crate) fn check_topic_tags_exist(conn:&mut impl Queryable,tags:&[TopicTag]) -> Result<(),QuestionError> {
+    get_tag_ids(conn,tags).map(|_|())
+}
+
+/// Get the topic tags on a question, alphabetically.
+pub(crate) fn get_topic_tags_for_question(conn:&mut impl Queryable,question:QuestionID) -> mysql::Result<Vec<TopicTag>> {
+    conn.exec_map("select Tag from QuestionTags inner join TopicTags on QuestionTags.TagId=TopicTags.id where QuestionId=? order by Tag",(question.0,),|(tag,)|tag)
+}
+
+/// Add topic tags to a question, ignoring ones already present.
+pub(crate) fn add_topic_tags_for_question(conn:&mut impl Queryable,question:QuestionID,tags:&[TopicTag]) -> Result<(),QuestionError> {
+    let existing = get_topic_tags_for_question(conn,question).map_err(internal_error)?;
+    let extra = extra_topic_tags(&existing,tags)?;
+    let ids = get_tag_ids(conn,&extra)?;
+    conn.exec_batch("insert into QuestionTags (QuestionId,TagId) values (?,?)",ids.into_iter().map(|id|(question.0,id))).map_err(internal_error)
+}
+
+/// Get the questions (not withdrawn or censored) that have all the given tags.
+pub(crate) fn questions_with_all_topic_tags(conn:&mut impl Queryable,tags:&[TopicTag]) -> Result<HashSet<QuestionID>,QuestionError> {
+    let mut with_each_tag = vec![];
+    for id in get_tag_ids(conn,tags)? {
+        with_each_tag.push(conn.exec_map("select QuestionId from QuestionTags where TagId=?",(id,),|(q,)|hash_from_value(q)).map_err(internal_error)?.into_iter().collect::<HashSet<QuestionID>>());
+    }
+    Ok(in_all(with_each_tag))
+}
+
+/// Get the vocabulary of topic tags, alphabetically, with the number of questions using each.
+pub async fn get_topic_tags() -> mysql::Result<Vec<TopicTagInfo>> {
+    let mut conn = get_rta_database_connection().await?;
+    conn.exec_map("select Tag,Description,(select count(*) from QuestionTags inner join QUESTIONS on QuestionTags.QuestionId=QUESTIONS.QuestionId where TagId=TopicTags.id and WithdrawnTimestamp is NULL and CensorshipStatus!='Censored') from TopicTags order by Tag",(),|(tag,description,num_questions)|TopicTagInfo{tag,description,num_questions})
+}
+
+/// Get the questions (not withdrawn or censored) with a given tag, most recently modified first.
+pub async fn get_questions_with_topic_tag(tag:&str) -> mysql::Result<Vec<QuestionID>> {
+    let mut conn = get_rta_database_connection().await?;
+    conn.exec_map("select QUESTIONS.QuestionId from QuestionTags inner join TopicTags on QuestionTags.TagId=TopicTags.id inner join QUESTIONS on QuestionTags.QuestionId=QUESTIONS.QuestionId where Tag=? and WithdrawnTimestamp is NULL and CensorshipStatus!='Censored' order by LastModifiedTimestamp DESC",(canonicalise_topic_tag(tag),),|(q,)|hash_from_value(q))
+}
+
+/// Add to the vocabulary of topic tags. An administrator command.
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct AddTopicTagsCommand {
+    #[serde(default)]
+    pub tags : Vec<TopicTagInfo>,
+    /// If true, also add the canonical name of each listed keyword (ListedKeywords.csv) that is a valid tag.
+    #[serde(default)]
+    pub include_listed_keywords : bool,
+}
+
+impl AddTopicTagsCommand {
+    /// Add the tags, ignoring any already present. Returns the number added.
+    pub async fn add_topic_tags(&self) -> anyhow::Result<usize> {
+        let tags = self.tags_to_add(&if self.include_listed_keywords { listed_keyword_canonical_names() } else { vec![] })?;
+        let mut conn = get_rta_database_connection().await?;
+        let mut added = 0;
+        for (tag,description) in tags {
+            conn.exec_drop("insert ignore into TopicTags (Tag,Description) values (?,?)",(tag,description))?;
+            added+=conn.affected_rows() as usize;
+        }
+        Ok(added)
+    }
+
+    /// The canonical tags and their descriptions, followed by those of the `listed_keywords` that are valid tags.
+    /// Fails if any explicitly given tag is invalid.
+    fn tags_to_add(&self,listed_keywords:&[String]) -> anyhow::Result<Vec<(TopicTag,Option<String>)>> {
+        let mut tags : Vec<(TopicTag,Option<String>)> = vec![];
+        for t in &self.tags {
+            let tag = canonicalise_topic_tag(&t.tag);
+            if !is_valid_topic_tag(&tag) { return Err(anyhow!("Invalid topic tag {}",t.tag)); }
+            tags.push((tag,t.description.clone()));
+        }
+        tags.extend(listed_keywords.iter().map(|k|canonicalise_topic_tag(k)).filter(|k|is_valid_topic_tag(k)).map(|k|(k,None)));
+        Ok(tags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use merkle_tree_bulletin_board::hash::HashValue;
+    use super::*;
+
+    fn tags(t:&[&str]) -> Vec<TopicTag> { t.iter().map(|s|s.to_string()).collect() }
+
+    #[test]
+    fn test_canonical_and_valid_tags() {
+        assert_eq!(canonicalise_topic_tag("  Mental Health "),"mental health");
+        assert!(is_valid_topic_tag("climate-change"));
+        assert!(!is_valid_topic_tag(""));
+        assert!(!is_valid_topic_tag("health; drop table"));
+        assert!(!is_valid_topic_tag(&"a".repeat(MAX_TOPIC_TAG_LENGTH+1)));
+    }
+
+    #[test]
+    fn only_author_adds_tags_unless_permitted() {
+        assert!(check_may_add_topic_tags(true,None).is_ok());
+        assert!(check_may_add_topic_tags(true,Some(Permissions::WriterOnly)).is_ok());
+        assert!(check_may_add_topic_tags(false,Some(Permissions::Others)).is_ok());
+        assert!(matches!(check_may_add_topic_tags(false,Some(Permissions::WriterOnly)),Err(QuestionError::OnlyAuthorCanChangeTopicTags)));
+        assert!(matches!(check_may_add_topic_tags(false,None),Err(QuestionError::OnlyAuthorCanChangeTopicTags)));
+    }
+
+    #[test]
+    fn extra_tags_are_canonical_new_and_limited() {
+        assert_eq!(extra_topic_tags(&tags(&["health"]),&tags(&["Health","housing"," HOUSING","water"])).unwrap(),tags(&["housing","water"]));
+        let nine : Vec<TopicTag> = (0..MAX_TOPIC_TAGS_PER_QUESTION-1).map(|i|format!("tag{i}")).collect();
+        assert_eq!(extra_topic_tags(&nine,&tags(&["tag0","new"])).unwrap(),tags(&["new"]));
+        assert!(matches!(extra_topic_tags(&nine,&tags(&["new","other"])),Err(QuestionError::TooManyTopicTags)));
+    }
+
+    #[test]
+    fn filtering_requires_all_tags() {
+        let q = |i:u8|HashValue([i;32]);
+        let health : HashSet<QuestionID> = [q(1),q(2),q(3)].into_iter().collect();
+        let housing : HashSet<QuestionID> = [q(2),q(3),q(4)].into_iter().collect();
+        let water : HashSet<QuestionID> = [q(3),q(5)].into_iter().collect();
+        assert_eq!(in_all([health.clone(),housing.clone()]),[q(2),q(3)].into_iter().collect());
+        assert_eq!(in_all([health,housing,water]),[q(3)].into_iter().collect());
+        assert!(in_all([]).is_empty());
+    }
+
+    #[test]
+    fn seeding_from_listed_keywords_skips_invalid_ones() {
+        let command = AddTopicTagsCommand{ tags: vec![TopicTagInfo{tag:"Housing".to_string(),description:Some("Homes".to_string()),num_questions:0}], include_listed_keywords: true };
+        assert_eq!(command.tags_to_add(&tags(&["Climate Change","covid/19"])).unwrap(),vec![("housing".to_string(),Some("Homes".to_string())),("climate change".to_string(),None)]);
+        let bad = AddTopicTagsCommand{ tags: vec![TopicTagInfo{tag:"a/b".to_string(),description:None,num_questions:0}], include_listed_keywords: false };
+        assert!(bad.tags_to_add(&[]).is_err());
+    }
+}
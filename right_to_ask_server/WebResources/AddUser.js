@@ -24,6 +24,22 @@ function updateUserList() {
     getWebJSON("get_user_list",success,failure);
 }
 
+/** Find a nonce such that SHA256(challenge:uid:nonce) has at least difficulty leading zero bits. */
+async function solveProofOfWork(challenge,uid) {
+    function leadingZeroBits(bytes) {
+        let res = 0;
+        for (const b of bytes) {
+            if (b===0) { res+=8; continue; }
+            return res+Math.clz32(b)-24;
+        }
+        return res;
+    }
+    const encoder = new TextEncoder();
+    for (let nonce=0;;nonce++) {
+        const hash = new Uint8Array(await crypto.subtle.digest('SHA-256',encoder.encode(challenge.challenge+":"+uid+":"+nonce)));
+        if (leadingZeroBits(hash)>=challenge.difficulty) return { challenge : challenge.challenge, nonce : ""+nonce };
+    }
+}
 
 function addUser() {
     function success(result) {
@@ -52,7 +68,13 @@ function addUser() {
         return null;
     }
     if (electorates.length>0) message.electorates=electorates.split(';').map(describe_electorate);
-    getWebJSON("new_registration",success,failure,JSON.stringify(message),"application/json")
+    function register() { getWebJSON("new_registration",success,failure,JSON.stringify(message),"application/json"); }
+    if (!(window.crypto && crypto.subtle)) { register(); return; } // no sha256 available. Usually available on localhost, or via https.
+    function gotChallenge(challenge) {
+        status("Doing proof of work, difficulty "+challenge.difficulty+"...");
+        solveProofOfWork(challenge,message.uid).then(solution=>{ message.proof_of_work=solution; register(); });
+    }
+    getWebJSON("get_registration_challenge",gotChallenge,failure);
 }
 
 window.onload = function () {
@@ -7,7 +7,7 @@ function change_list(url) {
             refreshList();
         } else failure(status.Err);
     }
-    getWebJSON(url,success,failure,JSON.stringify({email:email}),"application/json");
+    getAdminWebJSON(url,success,failure,{email:email});
 }
 
 function put_on_list() { change_list("put_on_do_not_email_list"); }
@@ -25,10 +25,11 @@ function refreshList() {
             }
         } else failure(list.Err);
     }
-    getWebJSON("get_do_not_email_list",success,failure);
+    getAdminWebJSON("get_do_not_email_list",success,failure,null);
 }
 
 
 window.onload = function () {
+    restore_admin_credentials();
     refreshList();
 }
\ No newline at end of file
@@ -0,0 +1,25 @@
+"use strict";
+
+function refreshThrottled() {
+    function success(result) {
+        if (result.hasOwnProperty("Ok")) {
+            const tbody = document.getElementById("Throttled");
+            removeAllChildElements(tbody);
+            for (const entry of result.Ok) {
+                const row = add(tbody,"tr");
+                add(row,"td").innerText=entry.subject.User ? "User "+entry.subject.User : "IP "+entry.subject.IP;
+                add(row,"td").innerText=entry.action;
+                add(row,"td").innerText=entry.times_throttled;
+                add(row,"td").innerText=new Date(entry.first_throttled*1000).toLocaleString();
+                add(row,"td").innerText=new Date(entry.last_throttled*1000).toLocaleString();
+            }
+        } else failure(result.Err);
+    }
+    getAdminWebJSON("get_rate_limited",success,failure,null);
+}
+
+window.onload = function () {
+    restore_admin_credentials();
+    document.getElementById("Refresh").onclick = refreshThrottled;
+    refreshThrottled();
+}
@@ -7,7 +7,7 @@ function reset_times_sent(timescale) {
             refreshList(timescale);
         } else failure(status.Err);
     }
-    getWebJSON("reset_times_sent",success,failure,JSON.stringify(timescale),"application/json");
+    getAdminWebJSON("reset_times_sent",success,failure,timescale);
 }
 
 function remove_specific_email() {
@@ -18,7 +18,7 @@ function remove_specific_email() {
             refreshList(1);
         } else failure(status.Err);
     }
-    getWebJSON("take_off_times_sent_list",success,failure,JSON.stringify({email:email}),"application/json");
+    getAdminWebJSON("take_off_times_sent_list",success,failure,{email:email});
 }
 
 function refreshList(timescale) {
@@ -33,11 +33,12 @@ function refreshList(timescale) {
             }
         } else failure(list.Err);
     }
-    getWebJSON(getURL("get_times_sent",{timescale:timescale}),success,failure);
+    getAdminWebJSON("get_times_sent",success,failure,{timescale:timescale});
 }
 
 
 window.onload = function () {
+    restore_admin_credentials();
     refreshList(0);
     refreshList(1);
 }
\ No newline at end of file
@@ -0,0 +1,33 @@
+"use strict";
+
+function refreshAuditLog() {
+    const query = {};
+    const moderator = document.getElementById("Moderator").value;
+    const question_id = document.getElementById("QuestionID").value;
+    const uid = document.getElementById("UserUID").value;
+    if (moderator) query.moderator=moderator;
+    if (question_id) query.question_id=question_id;
+    if (uid) query.uid=uid;
+    function success(result) {
+        if (result.hasOwnProperty("Ok")) {
+            const tbody = document.getElementById("AuditLog");
+            removeAllChildElements(tbody);
+            for (const entry of result.Ok) {
+                const row = add(tbody,"tr");
+                add(row,"td").innerText=new Date(entry.timestamp*1000).toLocaleString();
+                add(row,"td").innerText=entry.moderator;
+                add(row,"td").innerText=entry.action;
+                add(row,"td").innerText=entry.question_id || "";
+                add(row,"td").innerText=entry.uid || "";
+                add(row,"td").innerText=entry.reason || "";
+                add(row,"td").innerText=entry.bulletin_board_hash || "";
+            }
+        } else failure(result.Err);
+    }
+    getAdminWebJSON("get_moderation_audit_log",success,failure,query);
+}
+
+window.onload = function () {
+    restore_admin_credentials();
+    document.getElementById("Refresh").onclick = refreshAuditLog;
+}
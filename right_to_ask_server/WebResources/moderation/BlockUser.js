@@ -14,10 +14,11 @@ function set_block_status(block) {
             status("Tried to set block status for "+command.uid+" to "+command.block+". Got Error message "+result.Err);
         }
     }
-    getWebJSON("block_user",success,failure,JSON.stringify(command),"application/json")
+    getAdminWebJSON("block_user",success,failure,command)
 }
 
 window.onload = function () {
+    restore_admin_credentials();
     document.getElementById("BlockUser").onclick = () => set_block_status(true);
     document.getElementById("UnblockUser").onclick = () => set_block_status(false);
 }
@@ -23,10 +23,17 @@ function resolveCensorship(allow,answers) {
             status("Tried to censor question. Got Error message "+result.Err);
         }
     }
+    function claimed(result) { // censoring needs a claim on the question.
+        if (result.Ok) {
+            getAdminWebJSON("censor_question",success,failure,command)
+        } else {
+            status("Tried to claim question. Got Error message "+result.Err);
+        }
+    }
     if (command.question_id.length!==64) {
         status("Question ID should be 64 hex characters.");
     } else {
-        getWebJSON("censor_question",success,failure,JSON.stringify(command),"application/json")
+        getAdminWebJSON("claim",claimed,failure,{question_id:current_question_id})
     }
 }
 
@@ -55,7 +62,7 @@ function refreshQuestions() {
             }
         }
     }
-    getWebJSON("get_reported_questions",function (list) { if (list.hasOwnProperty("Ok")) success(list.Ok); else failure(list.Err); },failure);
+    getAdminWebJSON("get_reported_questions",function (list) { if (list.hasOwnProperty("Ok")) success(list.Ok); else failure(list.Err); },failure,null);
 }
 
 function pretty_show_report_reasons(reportDiv,question,report_reasons) {
@@ -128,14 +135,15 @@ function updateQuestion() {
             getWebJSON(getURL("../get_question_history",{question_id:question_id}),function(history) {
                 if (current_question_id === question_id) pretty_show_history(historyDiv,question,history);
             },failure);
-            getWebJSON(getURL("get_reasons_reported",{question_id:question_id}),function(report_reasons) {
+            getAdminWebJSON("get_reasons_reported",function(report_reasons) {
                 if (current_question_id === question_id) pretty_show_report_reasons(reportDiv,question,report_reasons);
-            },failure);
+            },failure,{question_id:question_id});
         }
     },failure);
 }
 
 window.onload = function () {
+    restore_admin_credentials();
     document.getElementById("Refresh").onclick = refreshQuestions;
     document.getElementById("CensorQuestion").onclick = doCensorQuestion;
     document.getElementById("CensorJustAnswers").onclick = doCensorAnswers;
@@ -32,16 +32,46 @@ function check_signature(signed) {
     getWebJSON("get_server_public_key_raw",success,failure)
 }
 
+// Sign a message (already JSON encoded) as a given user, including a timestamp and nonce to prevent replays.
+// The signature is of the string timestamp:nonce:message
+function sign_with_replay_protection(message,user,privateKey) {
+    const timestamp = Math.floor(Date.now()/1000);
+    const nonce = encodeBase64(nacl.randomBytes(16));
+    const privateKeyUint8Array = decodeBase64(privateKey);
+    const envelopeUint8Array = (new TextEncoder()).encode(timestamp+":"+nonce+":"+message);
+    const signatureUint8Array = nacl.sign.detached(envelopeUint8Array,privateKeyUint8Array);
+    let signature = encodeBase64(signatureUint8Array);
+    return { message:message, user:user, signature:signature, timestamp:timestamp, nonce:nonce };
+}
+
 // sign an object, using user name from field UID and privateKey from field PrivateKey
 function sign_message(command) {
     let message = JSON.stringify(command);
     let user = document.getElementById("UID").value;
     if (!user) user = document.getElementById("UID").innerText;
     let privateKey = document.getElementById("PrivateKey").value;
-    const privateKeyUint8Array = decodeBase64(privateKey);
-    const messageUint8Array = (new TextEncoder()).encode(message);
-    const signatureUint8Array = nacl.sign.detached(messageUint8Array,privateKeyUint8Array);
-    console.log(signatureUint8Array);
-    let signature = encodeBase64(signatureUint8Array);
-    return { message:message, user:user, signature:signature };
-}
\ No newline at end of file
+    return sign_with_replay_protection(message,user,privateKey);
+}
+
+// sign an object as an administrator, using the administrator name from field AdminName and privateKey from field AdminPrivateKey.
+// The name is remembered for the browser session so it does not need to be retyped on every admin page. The private key
+// is never stored, so it only exists in the password field.
+function sign_admin_message(command) {
+    let message = JSON.stringify(command);
+    let user = document.getElementById("AdminName").value;
+    let privateKey = document.getElementById("AdminPrivateKey").value;
+    sessionStorage.setItem("AdminName",user);
+    return sign_with_replay_protection(message,user,privateKey);
+}
+
+// fill in the AdminName field from the value remembered earlier this browser session.
+function restore_admin_credentials() {
+    sessionStorage.removeItem("AdminPrivateKey"); // stored by earlier versions.
+    const user = sessionStorage.getItem("AdminName");
+    if (user) document.getElementById("AdminName").value=user;
+}
+
+// Call an /admin or /moderation API with a command signed by sign_admin_message.
+function getAdminWebJSON(url,success,failure,command) {
+    getWebJSON(url,success,failure,JSON.stringify(sign_admin_message(command)),"application/json");
+}
@@ -1,20 +1,78 @@
 use std::ops::Deref;
-use actix_web::{HttpServer, middleware, web};
+use actix_web::{HttpRequest, HttpResponse, HttpServer, middleware, web};
 use actix_web::{get, post};
 use std::path::PathBuf;
 use actix_web::web::Json;
-use right_to_ask_api::person::{NewRegistration, get_list_of_all_users, get_count_of_all_users, UserInfo, get_user_by_id, RequestEmailValidation, EmailProof, EmailAddress, EditUserDetails, MiniUserInfo, search_for_users, TimesSent, RequestEmailValidationResult, BlockUserError,BlockUserCommand};
+use right_to_ask_api::person::{NewRegistration, get_list_of_all_users, get_count_of_all_users, UserInfo, get_user_by_id, RequestEmailValidation, EmailProof, EmailAddress, EditUserDetails, MiniUserInfo, search_for_users, TimesSent, RequestEmailValidationResult, BlockUserCommand, RotateKeyCommand, AddDeviceKeyCommand, RevokeDeviceKeyCommand, CancelAccountRecovery, CompleteAccountRecovery, RegistrationError};
 use merkle_tree_bulletin_board::hash::HashValue;
-use right_to_ask_api::database::{check_rta_database_version_current, find_similar_text_question, get_bulletin_board};
+use right_to_ask_api::database::{check_rta_database_version_current, find_similar_text_question, get_bulletin_board, get_rta_database_connection};
 use merkle_tree_bulletin_board::hash_history::{FullProof, HashInfo};
 use right_to_ask_api::censorship::{CensorQuestionCommand, QuestionHistory, ReportedQuestionReasonSummary, ReportedQuestionSummary, ReportQuestionCommand};
-use right_to_ask_api::signing::{get_server_public_key_base64encoded, ServerSigned, get_server_public_key_raw_hex, get_server_public_key_raw_base64, ClientSigned};
+use right_to_ask_api::signing::{get_server_public_key_base64encoded, ServerSigned, get_server_public_key_raw_hex, get_server_public_key_raw_base64, ClientSigned, legacy_client_signatures_accepted, get_server_key_history, ServerKeyHistoryEntry, check_server_key_history};
 use right_to_ask_api::common_file::{COMMITTEES, HEARINGS, MPS};
-use right_to_ask_api::question::{EditQuestionCommand, NewQuestionCommand, PlainTextVoteOnQuestionCommand, QuestionID, QuestionInfo, QuestionNonDefiningFields, SimilarQuestionQuery, SimilarQuestionResult};
+use right_to_ask_api::anonymous_voting::{anonymous_voting_enabled, AnonymousVoteCommand, BlindVoteToken, get_anonymous_votes, get_vote_token_public_key, RequestVoteTokenCommand};
+use right_to_ask_api::duplicates::{get_proposed_duplicates, lookup_canonical_question, MarkDuplicateCommand, ProposeDuplicateCommand, ProposedDuplicate};
+use right_to_ask_api::lifecycle::{get_lifecycle_transitions, LifecycleTransition, SetLifecycleCommand};
+use right_to_ask_api::notifications::{get_subscriptions, GetNotificationsCommand, Notification, notification_poll_interval, process_notifications, Subscription, UnsubscribeCommand};
+use right_to_ask_api::mp_dashboard::{MPDashboard, MPDashboardQuery};
+use right_to_ask_api::moderation_queue::{claim_question, ModerationClaim, ModerationQueueItem, ModerationQueueQuery, release_question};
+use right_to_ask_api::export::{ExportFormat, ExportRoot, QuestionExporter};
+use right_to_ask_api::statistics::{AllAnswerStatistics, answer_statistics_refresh_interval, get_answer_statistics, refresh_answer_statistics};
+use right_to_ask_api::question_list::{QuestionListQuery, QuestionListResult};
+use right_to_ask_api::question::{AmendAnswerCommand, EditQuestionCommand, NewQuestionCommand, GetMyVotesCommand, MyVote, PlainTextRetractVoteCommand, PlainTextVoteOnQuestionCommand, QuestionID, QuestionError, QuestionInfo, QuestionNonDefiningFields, SimilarQuestionQuery, SimilarQuestionResult, WithdrawQuestionCommand};
 use word_comparison::comparison_list::ScoredIDs;
+use serde::de::DeserializeOwned;
+use right_to_ask_api::proof_of_work::{new_challenge, proof_of_work_required, ProofOfWorkChallenge};
+use right_to_ask_api::topic_tags::{AddTopicTagsCommand, get_questions_with_topic_tag, get_topic_tags, TopicTagInfo};
+use right_to_ask_api::rate_limit::{check_rate_limit, get_throttled_subjects, RateLimitedAction, ThrottledSubject, use_forwarded_client_ip};
+use right_to_ask_api::administration::{AdministratorRole, ModerationAction, ModerationAuditEntry, ModerationAuditQuery};
+
+/// Roles allowed to moderate questions and users.
+const MODERATOR : &[AdministratorRole] = &[AdministratorRole::Moderator];
+/// Roles allowed to administer the server itself.
+const ADMIN : &[AdministratorRole] = &[AdministratorRole::Admin];
+/// Roles allowed to see or change lists of email addresses.
+const DATA_ADMIN : &[AdministratorRole] = &[AdministratorRole::DataAdmin];
+/// Roles allowed to see the moderation audit log.
+const MODERATOR_OR_ADMIN : &[AdministratorRole] = &[AdministratorRole::Moderator,AdministratorRole::Admin];
+
+/// Check that a command to an `/admin/*` or `/moderation/*` API is signed by someone listed in
+/// the administrators section of config.toml with one of the `allowed_roles`. Returns the name of the administrator, after logging
+/// who is calling what.
+fn check_administrator<'a,T,U:DeserializeOwned>(command:&'a ClientSigned<T,U>,api:&str,allowed_roles:&[AdministratorRole]) -> Result<&'a str,String> {
+    match command.signed_message.check_administrator_signature(allowed_roles) {
+        Ok(name) => {
+            println!("Administrator {} called {}",name,api);
+            Ok(name)
+        }
+        Err(signing_error) => {
+            println!("Rejected call to {} claiming to be from administrator {} : {}",api,command.signed_message.user,signing_error);
+            Err(signing_error.to_string())
+        }
+    }
+}
+
+/// The client's IP address, for rate limiting.
+fn client_ip(req:&HttpRequest) -> Option<String> {
+    if use_forwarded_client_ip() { req.connection_info().realip_remote_addr().map(|s|s.to_string()) }
+    else { req.peer_addr().map(|a|a.ip().to_string()) }
+}
+
+/// Get a challenge for the proof of work in [NewRegistration]. See [right_to_ask_api::proof_of_work].
+#[get("/get_registration_challenge")]
+async fn get_registration_challenge(req:HttpRequest) -> Json<Result<ProofOfWorkChallenge,String>> {
+    if let Err(e) = check_rate_limit(RateLimitedAction::RegistrationChallenge,None,client_ip(&req).as_deref()) {
+        return Json(Err(RegistrationError::from(e).to_string()));
+    }
+    Json(new_challenge().map_err(|e|e.to_string()))
+}
 
 #[post("/new_registration")]
-async fn new_registration(command : Json<NewRegistration>) -> Json<Result<ServerSigned,String>> {
+async fn new_registration(command : Json<NewRegistration>,req:HttpRequest) -> Json<Result<ServerSigned,String>> {
+    // The UID is chosen by the client, so only the IP address is meaningful to rate limit here.
+    if let Err(e) = check_rate_limit(RateLimitedAction::NewRegistration,None,client_ip(&req).as_deref()) {
+        return Json(Err(RegistrationError::from(e).to_string()));
+    }
     Json(ServerSigned::sign_string(command.register().await))
 }
 
@@ -29,6 +87,67 @@ async fn edit_user(command : Json<ClientSigned<EditUserDetails>>) -> Json<Result
     }
 }
 
+/// Replace the user's public key with a new one. Must be signed by the current key.
+#[post("/rotate_key")]
+async fn rotate_key(command : Json<ClientSigned<RotateKeyCommand>>) -> Json<Result<ServerSigned,String>> {
+    if let Err(signing_error) = command.signed_message.check_signature(false).await {
+        Json(Err(signing_error.to_string()))
+    } else {
+        let res = RotateKeyCommand::rotate_key(&command).await;
+        let signed = ServerSigned::sign_string(res);
+        Json(signed)
+    }
+}
+
+/// Add a key for another device. Signed by any current key of the user.
+#[post("/add_device_key")]
+async fn add_device_key(command : Json<ClientSigned<AddDeviceKeyCommand>>) -> Json<Result<ServerSigned,String>> {
+    if let Err(signing_error) = command.signed_message.check_signature(false).await {
+        Json(Err(signing_error.to_string()))
+    } else {
+        let res = AddDeviceKeyCommand::add_device_key(&command).await;
+        Json(ServerSigned::sign_string(res))
+    }
+}
+
+/// Revoke one of the user's keys. Signed by any current key of the user.
+#[post("/revoke_device_key")]
+async fn revoke_device_key(command : Json<ClientSigned<RevokeDeviceKeyCommand>>) -> Json<Result<ServerSigned,String>> {
+    if let Err(signing_error) = command.signed_message.check_signature(false).await {
+        Json(Err(signing_error.to_string()))
+    } else {
+        let res = RevokeDeviceKeyCommand::revoke_device_key(&command).await;
+        Json(ServerSigned::sign_string(res))
+    }
+}
+
+/// Cancel a pending account recovery. Signed by the current key, in case someone else has control of the email account.
+#[post("/cancel_account_recovery")]
+async fn cancel_account_recovery(command : Json<ClientSigned<CancelAccountRecovery>>) -> Json<Result<(),String>> {
+    if let Err(signing_error) = command.signed_message.check_signature(false).await {
+        Json(Err(signing_error.to_string()))
+    } else {
+        Json(CancelAccountRecovery::cancel(&command).await.map_err(|e|e.to_string()))
+    }
+}
+
+/// Install the new key from an account recovery, after the cooling off period. Signed by the new key.
+#[post("/complete_account_recovery")]
+async fn complete_account_recovery(command : Json<ClientSigned<CompleteAccountRecovery>>) -> Json<Result<ServerSigned,String>> {
+    match CompleteAccountRecovery::pending_public_key(&command.signed_message.user).await {
+        Err(e) => Json(Err(e.to_string())),
+        Ok(None) => Json(Err(RegistrationError::NoAccountRecoveryPending.to_string())),
+        Ok(Some(new_key)) => {
+            if let Err(signing_error) = command.signed_message.check_signature_by_key(&new_key) {
+                Json(Err(signing_error.to_string()))
+            } else {
+                let res = CompleteAccountRecovery::complete(&command).await;
+                Json(ServerSigned::sign_string(res))
+            }
+        }
+    }
+}
+
 const SCORE_FOR_SINGLE_METADATA_MATCH : f64 = 20.0;
 
 async fn similar_questions_work(command:&NewQuestionCommand) -> Result<Vec<ScoredIDs<QuestionID>>,String> {
@@ -58,9 +177,11 @@ async fn get_similar_questions(command : Json<SimilarQuestionQuery>) -> Json<Res
 
 
 #[post("/new_question")]
-async fn new_question(command : Json<ClientSigned<NewQuestionCommand>>) -> Json<Result<ServerSigned,String>> {
+async fn new_question(command : Json<ClientSigned<NewQuestionCommand>>,req:HttpRequest) -> Json<Result<ServerSigned,String>> {
     if let Err(signing_error) = command.signed_message.check_signature(true).await {
         Json(Err(signing_error.to_string()))
+    } else if let Err(e) = check_rate_limit(RateLimitedAction::NewQuestion,Some(&command.signed_message.user),client_ip(&req).as_deref()) {
+        Json(Err(QuestionError::from(e).to_string()))
     } else {
         let res = NewQuestionCommand::add_question(&command).await;
         let signed = ServerSigned::sign(res);
@@ -69,9 +190,11 @@ async fn new_question(command : Json<ClientSigned<NewQuestionCommand>>) -> Json<
 }
 
 #[post("/edit_question")]
-async fn edit_question(command : Json<ClientSigned<EditQuestionCommand>>) -> Json<Result<ServerSigned,String>> {
+async fn edit_question(command : Json<ClientSigned<EditQuestionCommand>>,req:HttpRequest) -> Json<Result<ServerSigned,String>> {
     if let Err(signing_error) = command.signed_message.check_signature(true).await {
         Json(Err(signing_error.to_string()))
+    } else if let Err(e) = check_rate_limit(RateLimitedAction::EditQuestion,Some(&command.signed_message.user),client_ip(&req).as_deref()) {
+        Json(Err(QuestionError::from(e).to_string()))
     } else {
         let res = EditQuestionCommand::edit(&command).await;
         let signed = ServerSigned::sign_string(res);
@@ -79,10 +202,68 @@ async fn edit_question(command : Json<ClientSigned<EditQuestionCommand>>) -> Jso
     }
 }
 
+/// Amend an answer. Must be signed by someone with the MP or MPStaff badge for the MP who answered.
+#[post("/amend_answer")]
+async fn amend_answer(command : Json<ClientSigned<AmendAnswerCommand>>,req:HttpRequest) -> Json<Result<ServerSigned,String>> {
+    if let Err(signing_error) = command.signed_message.check_signature(true).await {
+        Json(Err(signing_error.to_string()))
+    } else if let Err(e) = check_rate_limit(RateLimitedAction::EditQuestion,Some(&command.signed_message.user),client_ip(&req).as_deref()) {
+        Json(Err(QuestionError::from(e).to_string()))
+    } else {
+        let res = AmendAnswerCommand::amend(&command).await;
+        let signed = ServerSigned::sign_string(res);
+        Json(signed)
+    }
+}
+
+/// Propose that a question is a duplicate of another. Must be signed by its author, and confirmed by a moderator.
+#[post("/propose_duplicate")]
+async fn propose_duplicate(command : Json<ClientSigned<ProposeDuplicateCommand>>,req:HttpRequest) -> Json<Result<ServerSigned,String>> {
+    if let Err(signing_error) = command.signed_message.check_signature(true).await {
+        Json(Err(signing_error.to_string()))
+    } else if let Err(e) = check_rate_limit(RateLimitedAction::EditQuestion,Some(&command.signed_message.user),client_ip(&req).as_deref()) {
+        Json(Err(QuestionError::from(e).to_string()))
+    } else {
+        let res = ProposeDuplicateCommand::propose(&command).await;
+        let signed = ServerSigned::sign_string(res);
+        Json(signed)
+    }
+}
+
+/// Change where a question is up to, e.g. an MP committing to ask it. See right_to_ask_api::lifecycle for who may do what.
+#[post("/set_lifecycle")]
+async fn set_lifecycle(command : Json<ClientSigned<SetLifecycleCommand>>,req:HttpRequest) -> Json<Result<ServerSigned,String>> {
+    if let Err(signing_error) = command.signed_message.check_signature(true).await {
+        Json(Err(signing_error.to_string()))
+    } else if let Err(e) = check_rate_limit(RateLimitedAction::EditQuestion,Some(&command.signed_message.user),client_ip(&req).as_deref()) {
+        Json(Err(QuestionError::from(e).to_string()))
+    } else {
+        let res = SetLifecycleCommand::set_lifecycle(&command).await;
+        let signed = ServerSigned::sign_string(res);
+        Json(signed)
+    }
+}
+
+/// Withdraw a question. Must be signed by its author.
+#[post("/withdraw_question")]
+async fn withdraw_question(command : Json<ClientSigned<WithdrawQuestionCommand>>,req:HttpRequest) -> Json<Result<ServerSigned,String>> {
+    if let Err(signing_error) = command.signed_message.check_signature(false).await {
+        Json(Err(signing_error.to_string()))
+    } else if let Err(e) = check_rate_limit(RateLimitedAction::EditQuestion,Some(&command.signed_message.user),client_ip(&req).as_deref()) {
+        Json(Err(QuestionError::from(e).to_string()))
+    } else {
+        let res = WithdrawQuestionCommand::withdraw(&command).await;
+        let signed = ServerSigned::sign_string(res);
+        Json(signed)
+    }
+}
+
 #[post("/plaintext_vote_question")]
-async fn plaintext_vote_question(command : Json<ClientSigned<PlainTextVoteOnQuestionCommand>>) -> Json<Result<(),String>> {
+async fn plaintext_vote_question(command : Json<ClientSigned<PlainTextVoteOnQuestionCommand>>,req:HttpRequest) -> Json<Result<(),String>> {
     if let Err(signing_error) = command.signed_message.check_signature(true).await {
         Json(Err(signing_error.to_string()))
+    } else if let Err(e) = check_rate_limit(RateLimitedAction::Vote,Some(&command.signed_message.user),client_ip(&req).as_deref()) {
+        Json(Err(QuestionError::from(e).to_string()))
     } else {
         let res = PlainTextVoteOnQuestionCommand::vote(&command).await;
         //let signed = ServerSigned::sign_string(res);
@@ -91,10 +272,154 @@ async fn plaintext_vote_question(command : Json<ClientSigned<PlainTextVoteOnQues
 }
 
 
+/// Get a blinded token for voting anonymously on a question. The token is used with `/anonymous_vote`.
+#[post("/request_vote_token")]
+async fn request_vote_token(command : Json<ClientSigned<RequestVoteTokenCommand>>,req:HttpRequest) -> Json<Result<BlindVoteToken,String>> {
+    if let Err(signing_error) = command.signed_message.check_signature(true).await {
+        Json(Err(signing_error.to_string()))
+    } else if let Err(e) = check_rate_limit(RateLimitedAction::Vote,Some(&command.signed_message.user),client_ip(&req).as_deref()) {
+        Json(Err(QuestionError::from(e).to_string()))
+    } else {
+        Json(RequestVoteTokenCommand::issue(&command).await.map_err(|e|e.to_string()))
+    }
+}
+
+/// Vote using a token from `/request_vote_token`. Not signed, as it should not be linked to the user.
+#[post("/anonymous_vote")]
+async fn anonymous_vote(command : Json<AnonymousVoteCommand>,req:HttpRequest) -> Json<Result<HashValue,String>> {
+    if let Err(e) = check_rate_limit(RateLimitedAction::Vote,None,client_ip(&req).as_deref()) {
+        Json(Err(QuestionError::from(e).to_string()))
+    } else {
+        Json(command.vote().await.map_err(|e|e.to_string()))
+    }
+}
+
+#[post("/plaintext_retract_vote_question")]
+async fn plaintext_retract_vote_question(command : Json<ClientSigned<PlainTextRetractVoteCommand>>,req:HttpRequest) -> Json<Result<(),String>> {
+    if let Err(signing_error) = command.signed_message.check_signature(true).await {
+        Json(Err(signing_error.to_string()))
+    } else if let Err(e) = check_rate_limit(RateLimitedAction::Vote,Some(&command.signed_message.user),client_ip(&req).as_deref()) {
+        Json(Err(QuestionError::from(e).to_string()))
+    } else {
+        Json(PlainTextRetractVoteCommand::retract(&command).await.map_err(|e|e.to_string()))
+    }
+}
 
+/// Get the signer's own votes on a set of questions. Signed as votes are not public.
+#[post("/get_my_votes")]
+async fn get_my_votes(command : Json<ClientSigned<GetMyVotesCommand>>) -> Json<Result<Vec<MyVote>,String>> {
+    if let Err(signing_error) = command.signed_message.check_signature(false).await {
+        Json(Err(signing_error.to_string()))
+    } else {
+        Json(GetMyVotesCommand::get_my_votes(&command).await.map_err(|e|e.to_string()))
+    }
+}
+
+/// The questions directed at an MP, for the MP or their staff. The signer must have an MP or MPStaff badge for the MP.
+#[post("/mp_dashboard")]
+async fn mp_dashboard(command : Json<ClientSigned<MPDashboardQuery>>) -> Json<Result<MPDashboard,String>> {
+    if let Err(signing_error) = command.signed_message.check_signature(false).await {
+        Json(Err(signing_error.to_string()))
+    } else {
+        Json(command.parsed.get_dashboard(&command.signed_message.user).await.map_err(|e|e.to_string()))
+    }
+}
+
+/// How responsive MPs are, by MP, party, chamber and jurisdiction. Recomputed periodically, not live.
+#[get("/answer_statistics")]
+async fn answer_statistics() -> Json<Result<AllAnswerStatistics,String>> {
+    Json(get_answer_statistics().await.map(|s|(*s).clone()).map_err(|e|e.to_string()))
+}
+
+#[derive(serde::Deserialize)]
+struct QueryExport {
+    #[serde(default)]
+    format : ExportFormat,
+    /// The published root to pin the export to. Default the most recently published root.
+    root : Option<HashValue>,
+    /// Also export questions modified since the root, after the others.
+    #[serde(default)]
+    include_modified : bool,
+}
+
+/// Stream every uncensored question as NDJSON or CSV, as of a published bulletin board root. See [right_to_ask_api::export].
+/// The root is also given in the X-Pinned-Root header. Rate limited per IP address, as it reads every question.
+#[get("/export_questions")]
+async fn export_questions(req:HttpRequest,query:web::Query<QueryExport>) -> HttpResponse {
+    let exporter = match check_rate_limit(RateLimitedAction::Export,None,client_ip(&req).as_deref()) {
+        Err(e) => Err(QuestionError::from(e)),
+        Ok(()) => match ExportRoot::resolve(query.root).await {
+            Ok(root) => QuestionExporter::new(root,query.format,query.include_modified).await,
+            Err(e) => Err(e),
+        },
+    };
+    match exporter {
+        Ok(exporter) => {
+            let root = exporter.root();
+            let stream = futures::stream::unfold(Some(exporter),|exporter|async move {
+                let mut exporter = exporter?;
+                match exporter.next_batch().await {
+                    Ok(Some(batch)) => Some((Ok(web::Bytes::from(batch)),Some(exporter))),
+                    Ok(None) => None,
+                    Err(e) => Some((Err(actix_web::error::ErrorInternalServerError(e.to_string())),None)),
+                }
+            });
+            HttpResponse::Ok()
+                .content_type(query.format.content_type())
+                .insert_header(("X-Pinned-Root",root.root.to_string()))
+                .streaming(stream)
+        }
+        Err(e) => HttpResponse::Ok().json(Result::<(),String>::Err(e.to_string())),
+    }
+}
+
+/// Subscribe to notifications about a question, MP, electorate or topic tag. Returns the subscription id.
+#[post("/subscribe")]
+async fn subscribe(command : Json<ClientSigned<Subscription>>) -> Json<Result<u64,String>> {
+    if let Err(signing_error) = command.signed_message.check_signature(true).await {
+        Json(Err(signing_error.to_string()))
+    } else {
+        Json(command.parsed.subscribe(&command.signed_message.user).await.map_err(|e|e.to_string()))
+    }
+}
+
+#[post("/unsubscribe")]
+async fn unsubscribe(command : Json<ClientSigned<UnsubscribeCommand>>) -> Json<Result<(),String>> {
+    if let Err(signing_error) = command.signed_message.check_signature(true).await {
+        Json(Err(signing_error.to_string()))
+    } else {
+        Json(command.parsed.unsubscribe(&command.signed_message.user).await.map_err(|e|e.to_string()))
+    }
+}
+
+/// Get the signer's subscriptions. Signed as subscriptions are not public.
+#[post("/get_subscriptions")]
+async fn get_subscriptions_api(command : Json<ClientSigned<()>>) -> Json<Result<Vec<Subscription>,String>> {
+    if let Err(signing_error) = command.signed_message.check_signature(false).await {
+        Json(Err(signing_error.to_string()))
+    } else {
+        Json(get_subscriptions(&command.signed_message.user).await.map_err(|e|e.to_string()))
+    }
+}
+
+/// Get the signer's pending pull notifications, acknowledging ones already received.
+#[post("/get_notifications")]
+async fn get_notifications(command : Json<ClientSigned<GetNotificationsCommand>>) -> Json<Result<Vec<Notification>,String>> {
+    if let Err(signing_error) = command.signed_message.check_signature(false).await {
+        Json(Err(signing_error.to_string()))
+    } else {
+        Json(command.parsed.get_notifications(&command.signed_message.user).await.map_err(|e|e.to_string()))
+    }
+}
+
+/// For account recovery, the request is signed by the new key rather than the lost one.
 #[post("/request_email_validation")]
 async fn request_email_validation(command : Json<ClientSigned<RequestEmailValidation,EmailAddress>>) -> Json<Result<RequestEmailValidationResult<ServerSigned>,String>> {
-    if let Err(signing_error) = command.signed_message.check_signature(false).await {
+    let signature_check = match command.parsed.account_recovery_key() {
+        Some(new_key) => command.signed_message.check_signature_by_key(new_key),
+        None => command.signed_message.check_signature(false).await,
+    };
+    if let Err(signing_error) = signature_check {
         Json(Err(signing_error.to_string()))
     } else {
         let res = RequestEmailValidation::process(&command).await;
@@ -112,9 +437,14 @@ async fn request_email_validation(command : Json<ClientSigned<RequestEmailValida
     }
 }
 
+/// For account recovery, the proof is signed by the new key rather than the lost one.
 #[post("/email_proof")]
 async fn email_proof(command : Json<ClientSigned<EmailProof>>) -> Json<Result<Option<ServerSigned>,String>> {
-    if let Err(signing_error) = command.signed_message.check_signature(false).await {
+    let signature_check = match command.parsed.account_recovery_key() {
+        Some(new_key) => command.signed_message.check_signature_by_key(&new_key),
+        None => command.signed_message.check_signature(false).await,
+    };
+    if let Err(signing_error) = signature_check {
         Json(Err(signing_error.to_string()))
     } else {
         let res = EmailProof::process(&command).await;
@@ -145,6 +475,13 @@ async fn get_server_public_key_raw() -> Json<String> {
     Json(get_server_public_key_raw_base64())
 }
 
+/// Get the chain of server keys, oldest first, each endorsed by the previous one.
+/// The `key_id` in a [ServerSigned] receipt identifies which of these signed it.
+#[get("/get_server_key_history")]
+async fn get_server_key_history_api() -> Json<Vec<ServerKeyHistoryEntry>> {
+    Json(get_server_key_history())
+}
+
 /// For testing only!
 #[get("/get_user_list")]
 async fn get_user_list() -> Json<Result<Vec<String>,String>> {
@@ -176,9 +513,38 @@ async fn search_user(query:web::Query<SearchUser>) -> Json<Result<Vec<MiniUserIn
 struct QueryQuestion {
     question_id : QuestionID,
 }
+#[derive(serde::Deserialize)]
+struct QueryQuestionMaybeDuplicate {
+    question_id : QuestionID,
+    /// If true, return the question asked for even if it is a duplicate, rather than the question it duplicates.
+    #[serde(default)]
+    no_redirect : bool,
+}
+
+/// Get a question. If it has been marked as a duplicate, the question it duplicates is returned instead unless no_redirect is set.
 #[get("/get_question")]
-async fn get_question(query:web::Query<QueryQuestion>) -> Json<Result<Option<QuestionInfo>,String>> {
-    Json(QuestionInfo::lookup(query.question_id).await.map_err(|e|e.to_string()))
+async fn get_question(query:web::Query<QueryQuestionMaybeDuplicate>) -> Json<Result<Option<QuestionInfo>,String>> {
+    let res = if query.no_redirect { QuestionInfo::lookup(query.question_id).await } else { lookup_canonical_question(query.question_id).await };
+    Json(res.map_err(|e|e.to_string()))
+}
+
+/// Get many questions at once, in the order requested, omitting ones that do not exist or are censored.
+/// At most MAX_QUESTIONS_IN_BATCH_LOOKUP questions may be requested.
+#[post("/get_questions")]
+async fn get_questions(question_ids:Json<Vec<QuestionID>>) -> Json<Result<Vec<QuestionInfo>,String>> {
+    Json(QuestionInfo::lookup_many(&question_ids).await.map_err(|e|e.to_string()))
+}
+
+/// Get the public key used to check anonymous vote tokens for a question.
+#[get("/get_vote_token_key")]
+async fn get_vote_token_key(query:web::Query<QueryQuestion>) -> Json<Result<String,String>> {
+    Json(get_vote_token_public_key(query.question_id).map_err(|e|e.to_string()))
+}
+
+/// Get the bulletin board entries for the anonymous votes on a question.
+#[get("/get_anonymous_votes")]
+async fn get_anonymous_votes_api(query:web::Query<QueryQuestion>) -> Json<Result<Vec<HashValue>,String>> {
+    Json(get_anonymous_votes(query.question_id).await.map_err(|e|e.to_string()))
 }
 
 #[get("/get_question_history")]
@@ -186,6 +552,12 @@ async fn get_question_history(query:web::Query<QueryQuestion>) -> Json<Result<Qu
     Json(QuestionHistory::lookup(query.question_id).await.map_err(|e|e.to_string()))
 }
 
+/// Who changed the lifecycle status of a question, and when, oldest first.
+#[get("/get_lifecycle_transitions")]
+async fn get_lifecycle_transitions_api(query:web::Query<QueryQuestion>) -> Json<Result<Vec<LifecycleTransition>,String>> {
+    Json(get_lifecycle_transitions(query.question_id).await.map_err(|e|e.to_string()))
+}
+
 
 
 /// For testing only!
@@ -194,33 +566,124 @@ async fn get_question_list() -> Json<Result<Vec<QuestionID>,String>> {
     Json(QuestionInfo::get_list_of_all_questions().await.map_err(|e|e.to_string()))
 }
 
+/// Get a page of questions matching some filters. This is idempotent; it is a post because the sent data structure is quite complex.
+#[post("/list_questions")]
+async fn list_questions(query : Json<QuestionListQuery>) -> Json<Result<QuestionListResult,String>> {
+    Json(query.list_questions().await.map_err(|e|e.to_string()))
+}
+
 #[get("/get_questions_created_by_user")]
 async fn get_questions_created_by_user(query:web::Query<QueryUser>) -> Json<Result<Vec<QuestionID>,String>> {
     Json(QuestionInfo::get_questions_created_by_user(&query.uid).await.map_err(|e|e.to_string()))
 }
 
 
+/// Get the vocabulary of topic tags, with the number of questions using each.
+#[get("/get_topic_tags")]
+async fn get_topic_tags_api() -> Json<Result<Vec<TopicTagInfo>,String>> {
+    Json(get_topic_tags().await.map_err(|e|e.to_string()))
+}
+
+#[derive(serde::Deserialize)]
+struct QueryTopicTag {
+    tag : String,
+}
+
+/// Get the questions with a given topic tag, most recently modified first.
+#[get("/get_questions_with_topic_tag")]
+async fn get_questions_with_topic_tag_api(query:web::Query<QueryTopicTag>) -> Json<Result<Vec<QuestionID>,String>> {
+    Json(get_questions_with_topic_tag(&query.tag).await.map_err(|e|e.to_string()))
+}
+
+#[post("/admin/add_topic_tags")]
+async fn add_topic_tags(command : Json<ClientSigned<AddTopicTagsCommand>>) -> Json<Result<usize,String>> {
+    match check_administrator(&command,"add_topic_tags",ADMIN) {
+        Err(e) => Json(Err(e)),
+        Ok(_) => Json(command.parsed.add_topic_tags().await.map_err(|e|e.to_string())),
+    }
+}
+
 #[post("/moderation/censor_question")]
-async fn censor_question(command : Json<CensorQuestionCommand>) -> Json<Result<HashValue,String>> {
-    Json(command.censor_question().await.map_err(|e|e.to_string()))
+async fn censor_question(command : Json<ClientSigned<CensorQuestionCommand>>) -> Json<Result<HashValue,String>> {
+    match check_administrator(&command,"censor_question",MODERATOR) {
+        Err(e) => Json(Err(e)),
+        Ok(moderator) => Json(command.parsed.censor_question(moderator).await.map_err(|e|e.to_string())),
+    }
+}
+
+/// Mark a question as a duplicate of another, or not. Also confirms or rejects a duplicate proposed by the author.
+#[post("/moderation/mark_duplicate")]
+async fn mark_duplicate(command : Json<ClientSigned<MarkDuplicateCommand>>) -> Json<Result<HashValue,String>> {
+    match check_administrator(&command,"mark_duplicate",MODERATOR) {
+        Err(e) => Json(Err(e)),
+        Ok(moderator) => Json(command.parsed.mark_duplicate(moderator).await.map_err(|e|e.to_string())),
+    }
 }
 
-#[get("/moderation/get_reported_questions")]
-async fn get_reported_questions() -> Json<Result<Vec<ReportedQuestionSummary>,String>> {
-    Json(ReportedQuestionSummary::get_reported_questions().await.map_err(|e|e.to_string()))
+/// Duplicates proposed by authors, waiting for a moderator to confirm or reject them with `/moderation/mark_duplicate`.
+/// This is idempotent; it is a post because it needs to be signed.
+#[post("/moderation/get_proposed_duplicates")]
+async fn get_proposed_duplicates_api(command : Json<ClientSigned<()>>) -> Json<Result<Vec<ProposedDuplicate>,String>> {
+    match check_administrator(&command,"get_proposed_duplicates",MODERATOR) {
+        Err(e) => Json(Err(e)),
+        Ok(_) => Json(get_proposed_duplicates().await.map_err(|e|e.to_string())),
+    }
 }
 
-#[get("/moderation/get_reasons_reported")]
-async fn get_reasons_reported(query:web::Query<QueryQuestion>) -> Json<Result<ReportedQuestionReasonSummary,String>> {
-    Json(ReportedQuestionReasonSummary::get_reasons_reported(query.question_id).await.map_err(|e|e.to_string()))
+/// This is idempotent; it is a post because it needs to be signed.
+#[post("/moderation/get_reported_questions")]
+async fn get_reported_questions(command : Json<ClientSigned<()>>) -> Json<Result<Vec<ReportedQuestionSummary>,String>> {
+    match check_administrator(&command,"get_reported_questions",MODERATOR) {
+        Err(e) => Json(Err(e)),
+        Ok(_) => Json(ReportedQuestionSummary::get_reported_questions().await.map_err(|e|e.to_string())),
+    }
+}
+
+/// Questions with outstanding flags, most urgent first. Questions claimed by other moderators are omitted unless include_claimed is set.
+/// This is idempotent; it is a post because it needs to be signed.
+#[post("/moderation/get_queue")]
+async fn get_moderation_queue(command : Json<ClientSigned<ModerationQueueQuery>>) -> Json<Result<Vec<ModerationQueueItem>,String>> {
+    match check_administrator(&command,"get_moderation_queue",MODERATOR) {
+        Err(e) => Json(Err(e)),
+        Ok(moderator) => Json(command.parsed.get_queue(moderator).await.map_err(|e|e.to_string())),
+    }
+}
+
+/// Claim a question in the moderation queue, or renew a claim, so other moderators leave it alone.
+#[post("/moderation/claim")]
+async fn claim_moderation(command : Json<ClientSigned<QueryQuestion>>) -> Json<Result<ModerationClaim,String>> {
+    match check_administrator(&command,"claim_moderation",MODERATOR) {
+        Err(e) => Json(Err(e)),
+        Ok(moderator) => Json(claim_question(command.parsed.question_id,moderator).await.map_err(|e|e.to_string())),
+    }
+}
+
+/// Release a claim on a question in the moderation queue.
+#[post("/moderation/release")]
+async fn release_moderation(command : Json<ClientSigned<QueryQuestion>>) -> Json<Result<(),String>> {
+    match check_administrator(&command,"release_moderation",MODERATOR) {
+        Err(e) => Json(Err(e)),
+        Ok(moderator) => Json(release_question(command.parsed.question_id,moderator).await.map_err(|e|e.to_string())),
+    }
+}
+
+/// This is idempotent; it is a post because it needs to be signed.
+#[post("/moderation/get_reasons_reported")]
+async fn get_reasons_reported(command : Json<ClientSigned<QueryQuestion>>) -> Json<Result<ReportedQuestionReasonSummary,String>> {
+    match check_administrator(&command,"get_reasons_reported",MODERATOR) {
+        Err(e) => Json(Err(e)),
+        Ok(_) => Json(ReportedQuestionReasonSummary::get_reasons_reported(command.parsed.question_id).await.map_err(|e|e.to_string())),
+    }
 }
 
 
 
 #[post("/report_question")]
-async fn report_question(command : Json<ClientSigned<ReportQuestionCommand>>) -> Json<Result<(),String>> { // ServerSigned not () in result if want to put on bulletin board
+async fn report_question(command : Json<ClientSigned<ReportQuestionCommand>>,req:HttpRequest) -> Json<Result<(),String>> { // ServerSigned not () in result if want to put on bulletin board
     if let Err(signing_error) = command.signed_message.check_signature(true).await {
         Json(Err(signing_error.to_string()))
+    } else if let Err(e) = check_rate_limit(RateLimitedAction::ReportQuestion,Some(&command.signed_message.user),client_ip(&req).as_deref()) {
+        Json(Err(QuestionError::from(e).to_string()))
     } else {
         let res = ReportQuestionCommand::report_question(&command).await;
         let signed = res.map_err(|e|e.to_string()); //.map(|h|ServerSigned::new_string(h.to_string()));
@@ -236,14 +699,47 @@ struct Censor {
 }
 
 #[post("/moderation/censor_leaf")]
-async fn censor_leaf(command : Json<Censor>) -> Json<Result<(),String>> {
-    Json(get_bulletin_board().await.censor_leaf(command.leaf_to_censor).map_err(|e|e.to_string()))
+async fn censor_leaf(command : Json<ClientSigned<Censor>>) -> Json<Result<(),String>> {
+    match check_administrator(&command,"censor_leaf",ADMIN) {
+        Err(e) => Json(Err(e)),
+        Ok(moderator) => Json(censor_leaf_work(moderator,command.parsed.leaf_to_censor).await),
+    }
+}
+
+async fn censor_leaf_work(moderator:&str,leaf_to_censor:HashValue) -> Result<(),String> {
+    get_bulletin_board().await.censor_leaf(leaf_to_censor).map_err(|e|e.to_string())?;
+    let mut audit = ModerationAuditEntry::new(moderator,ModerationAction::CensorLeaf).map_err(|e|e.to_string())?;
+    audit.bulletin_board_hash = Some(leaf_to_censor);
+    let mut conn = get_rta_database_connection().await.map_err(|e|e.to_string())?;
+    audit.record(&mut conn).map_err(|e|e.to_string())
+}
+
+
+/// This is idempotent; it is a post because it needs to be signed.
+#[post("/moderation/get_moderation_audit_log")]
+async fn get_moderation_audit_log(command : Json<ClientSigned<ModerationAuditQuery>>) -> Json<Result<Vec<ModerationAuditEntry>,String>> {
+    match check_administrator(&command,"get_moderation_audit_log",MODERATOR_OR_ADMIN) {
+        Err(e) => Json(Err(e)),
+        Ok(_) => Json(command.parsed.get_moderation_audit_log().await.map_err(|e|e.to_string())),
+    }
 }
 
+/// Users and IP addresses that have recently been refused by the rate limiter.
+/// This is idempotent; it is a post because it needs to be signed.
+#[post("/admin/get_rate_limited")]
+async fn get_rate_limited(command : Json<ClientSigned<()>>) -> Json<Result<Vec<ThrottledSubject>,String>> {
+    match check_administrator(&command,"get_rate_limited",MODERATOR_OR_ADMIN) {
+        Err(e) => Json(Err(e)),
+        Ok(_) => Json(Ok(get_throttled_subjects())),
+    }
+}
 
 #[post("/moderation/block_user")]
-async fn block_user(command : Json<BlockUserCommand>)-> Json<Result<(),BlockUserError>> {
-    Json(command.apply().await)
+async fn block_user(command : Json<ClientSigned<BlockUserCommand>>)-> Json<Result<(),String>> {
+    match check_administrator(&command,"block_user",MODERATOR) {
+        Err(e) => Json(Err(e)),
+        Ok(moderator) => Json(command.parsed.apply(moderator).await.map_err(|e|e.to_string())),
+    }
 }
 
 #[get("/get_parentless_unpublished_hash_values")]
@@ -257,8 +753,11 @@ async fn get_most_recent_published_root() -> Json<Result<Option<HashValue>,Strin
 }
 
 #[post("/admin/order_new_published_root")]
-async fn order_new_published_root() -> Json<Result<HashValue,String>> {
-    Json(get_bulletin_board().await.order_new_published_root().map_err(|e|e.to_string()))
+async fn order_new_published_root(command : Json<ClientSigned<()>>) -> Json<Result<HashValue,String>> {
+    match check_administrator(&command,"order_new_published_root",ADMIN) {
+        Err(e) => Json(Err(e)),
+        Ok(_) => Json(get_bulletin_board().await.order_new_published_root().map_err(|e|e.to_string())),
+    }
 }
 
 #[derive(serde::Deserialize)]
@@ -326,54 +825,88 @@ struct Info {
     hash_committees : HashValue,
     /// SHA2 hash of the hearings.json file
     hash_hearings : HashValue,
+    /// Whether client signed messages without a timestamp and nonce (as sent by api_level 0 clients) are still accepted.
+    accepts_legacy_signatures : bool,
+    /// Whether a new registration must include a proof of work from `/get_registration_challenge`.
+    registration_requires_proof_of_work : bool,
+    /// Whether `/request_vote_token` and `/anonymous_vote` are available.
+    anonymous_voting : bool,
 }
 #[get("/info.json")]
 async fn info() -> Result<Json<Info>,Box<dyn std::error::Error + 'static>> {
     Ok(Json(Info{
-        api_level: 0, // This should be increased each time there is a change API that will break prior clients.
+        api_level: 1, // This should be increased each time there is a change API that will break prior clients.
         hash_mps: MPS.get_hash()?,
         hash_committees: COMMITTEES.get_hash()?,
         hash_hearings: HEARINGS.get_hash()?,
+        accepts_legacy_signatures: legacy_client_signatures_accepted(),
+        registration_requires_proof_of_work: proof_of_work_required(),
+        anonymous_voting: anonymous_voting_enabled(),
     }))
 }
 
 #[post("/admin/reload_info")]
 /// Force the server to reload the MPs.json file, the committees.json file, and the hearings.json file (without restarting).
-async fn reload_info() -> &'static str {
-    MPS.reset();
-    COMMITTEES.reset();
-    HEARINGS.reset();
-    "OK"
+async fn reload_info(command : Json<ClientSigned<()>>) -> Json<Result<(),String>> {
+    match check_administrator(&command,"reload_info",ADMIN) {
+        Err(e) => Json(Err(e)),
+        Ok(_) => {
+            MPS.reset();
+            COMMITTEES.reset();
+            HEARINGS.reset();
+            Json(Ok(()))
+        }
+    }
 }
 
 #[post("/admin/put_on_do_not_email_list")]
-async fn put_on_do_not_email_list(command : Json<EmailAddress>) -> Json<Result<(),String>> {
-    Json(command.change_do_not_email_list(true).await.map_err(|e|e.to_string()))
+async fn put_on_do_not_email_list(command : Json<ClientSigned<EmailAddress>>) -> Json<Result<(),String>> {
+    match check_administrator(&command,"put_on_do_not_email_list",DATA_ADMIN) {
+        Err(e) => Json(Err(e)),
+        Ok(_) => Json(command.parsed.change_do_not_email_list(true).await.map_err(|e|e.to_string())),
+    }
 }
 #[post("/admin/take_off_do_not_email_list")]
-async fn take_off_do_not_email_list(command : Json<EmailAddress>) -> Json<Result<(),String>> {
-    Json(command.change_do_not_email_list(false).await.map_err(|e|e.to_string()))
+async fn take_off_do_not_email_list(command : Json<ClientSigned<EmailAddress>>) -> Json<Result<(),String>> {
+    match check_administrator(&command,"take_off_do_not_email_list",DATA_ADMIN) {
+        Err(e) => Json(Err(e)),
+        Ok(_) => Json(command.parsed.change_do_not_email_list(false).await.map_err(|e|e.to_string())),
+    }
 }
 
-#[get("/admin/get_do_not_email_list")]
-async fn get_do_not_email_list() -> Json<Result<Vec<EmailAddress>,String>> {
-    Json(EmailAddress::get_do_not_email_list().await.map_err(|e|e.to_string()))
+/// This is idempotent; it is a post because it needs to be signed.
+#[post("/admin/get_do_not_email_list")]
+async fn get_do_not_email_list(command : Json<ClientSigned<()>>) -> Json<Result<Vec<EmailAddress>,String>> {
+    match check_administrator(&command,"get_do_not_email_list",DATA_ADMIN) {
+        Err(e) => Json(Err(e)),
+        Ok(_) => Json(EmailAddress::get_do_not_email_list().await.map_err(|e|e.to_string())),
+    }
 }
 #[post("/admin/reset_times_sent")]
-async fn reset_times_sent(command : Json<u32>) -> Json<Result<(),String>> {
-    Json(EmailAddress::reset_times_sent(command.0).await.map_err(|e|e.to_string()))
+async fn reset_times_sent(command : Json<ClientSigned<u32>>) -> Json<Result<(),String>> {
+    match check_administrator(&command,"reset_times_sent",DATA_ADMIN) {
+        Err(e) => Json(Err(e)),
+        Ok(_) => Json(EmailAddress::reset_times_sent(command.parsed).await.map_err(|e|e.to_string())),
+    }
 }
 #[derive(serde::Deserialize)]
 struct QueryTimescale {
     timescale : u32,
 }
-#[get("/admin/get_times_sent")]
-async fn get_times_sent(query:web::Query<QueryTimescale>) -> Json<Result<Vec<TimesSent>,String>> {
-    Json(EmailAddress::get_times_sent(query.timescale).await.map_err(|e|e.to_string()))
+/// This is idempotent; it is a post because it needs to be signed.
+#[post("/admin/get_times_sent")]
+async fn get_times_sent(command : Json<ClientSigned<QueryTimescale>>) -> Json<Result<Vec<TimesSent>,String>> {
+    match check_administrator(&command,"get_times_sent",DATA_ADMIN) {
+        Err(e) => Json(Err(e)),
+        Ok(_) => Json(EmailAddress::get_times_sent(command.parsed.timescale).await.map_err(|e|e.to_string())),
+    }
 }
 #[post("/admin/take_off_times_sent_list")]
-async fn take_off_times_sent_list(command : Json<EmailAddress>) -> Json<Result<(),String>> {
-    Json(command.take_off_times_sent_list().await.map_err(|e|e.to_string()))
+async fn take_off_times_sent_list(command : Json<ClientSigned<EmailAddress>>) -> Json<Result<(),String>> {
+    match check_administrator(&command,"take_off_times_sent_list",DATA_ADMIN) {
+        Err(e) => Json(Err(e)),
+        Ok(_) => Json(command.parsed.take_off_times_sent_list().await.map_err(|e|e.to_string())),
+    }
 }
 
 
@@ -381,9 +914,22 @@ async fn take_off_times_sent_list(command : Json<EmailAddress>) -> Json<Result<(
 async fn main() -> anyhow::Result<()> {
     // check whether everything is working before starting the web server. Don't want to find out in the middle of a transaction.
     check_rta_database_version_current().await?;
+    check_server_key_history()?;
     println!("Server public key {}",get_server_public_key_raw_base64());
     println!("Bulletin board latest published root {:?}",get_bulletin_board().await.get_most_recent_published_root()?);
     println!("{} users in the database",get_count_of_all_users().await?);
+    actix_web::rt::spawn(async {
+        loop {
+            if let Err(e) = process_notifications().await { println!("Error processing notifications : {}",e); }
+            actix_web::rt::time::sleep(notification_poll_interval()).await;
+        }
+    });
+    actix_web::rt::spawn(async {
+        loop {
+            if let Err(e) = refresh_answer_statistics().await { println!("Error computing answer statistics : {}",e); }
+            actix_web::rt::time::sleep(answer_statistics_refresh_interval()).await;
+        }
+    });
     println!("Running demo webserver on http://localhost:8099 stop with control C.");
     HttpServer::new(move|| {
         actix_web::App::new()
@@ -391,28 +937,65 @@ async fn main() -> anyhow::Result<()> {
             .service(get_server_public_key_hex)
             .service(get_server_public_key_spki)
             .service(get_server_public_key_raw)
+            .service(get_server_key_history_api)
+            .service(get_registration_challenge)
             .service(new_registration)
             .service(edit_user)
+            .service(rotate_key)
+            .service(add_device_key)
+            .service(revoke_device_key)
+            .service(cancel_account_recovery)
+            .service(complete_account_recovery)
             .service(request_email_validation)
             .service(email_proof)
             .service(similar_questions)
             .service(get_similar_questions)
             .service(new_question)
             .service(edit_question)
+            .service(amend_answer)
+            .service(withdraw_question)
+            .service(propose_duplicate)
+            .service(set_lifecycle)
             .service(plaintext_vote_question)
+            .service(plaintext_retract_vote_question)
+            .service(get_my_votes)
+            .service(mp_dashboard)
+            .service(answer_statistics)
+            .service(export_questions)
+            .service(subscribe)
+            .service(unsubscribe)
+            .service(get_subscriptions_api)
+            .service(get_notifications)
+            .service(request_vote_token)
+            .service(anonymous_vote)
+            .service(get_vote_token_key)
+            .service(get_anonymous_votes_api)
             .service(get_user_list)
             .service(get_user)
             .service(search_user)
             .service(get_question_list)
+            .service(list_questions)
             .service(get_questions_created_by_user)
+            .service(get_topic_tags_api)
+            .service(get_questions_with_topic_tag_api)
+            .service(add_topic_tags)
             .service(get_question)
+            .service(get_questions)
             .service(get_question_history)
+            .service(get_lifecycle_transitions_api)
             .service(censor_question)
+            .service(mark_duplicate)
+            .service(get_proposed_duplicates_api)
             .service(get_reported_questions)
+            .service(get_moderation_queue)
+            .service(claim_moderation)
+            .service(release_moderation)
             .service(get_reasons_reported)
             .service(report_question)
             .service(censor_leaf)
             .service(block_user)
+            .service(get_moderation_audit_log)
+            .service(get_rate_limited)
             .service(get_parentless_unpublished_hash_values)
             .service(get_most_recent_published_root)
             .service(order_new_published_root)
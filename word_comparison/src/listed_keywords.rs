@@ -40,6 +40,11 @@ impl ListedKeywords {
     pub fn canonical(&self,index:ListedKeywordIndex) -> &str {
         self.keywords[index.0].0[0].as_str()
     }
+
+    /// Get a canonical example of every keyword.
+    pub fn all_canonical(&self) -> impl Iterator<Item=&str> {
+        self.keywords.iter().filter_map(|k|k.0.first()).map(|s|s.as_str())
+    }
 }
 
 impl ListedKeyword {